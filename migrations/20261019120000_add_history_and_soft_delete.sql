@@ -0,0 +1,106 @@
+-- Soft delete: deleted rows keep their data until purged
+-- The table is rebuilt so that UNIQUE(start, end) only applies to rows that aren't deleted
+CREATE TABLE sleep_new
+(
+    id      INTEGER primary key AUTOINCREMENT NOT NULL,
+    start   TEXT NOT NULL,
+    minutes_to_fall_asleep INTEGER NULL DEFAULT 0,
+    end     TEXT NULL DEFAULT NULL,
+    total_time_hours TEXT GENERATED ALWAYS AS ((JULIANDAY(end) - JULIANDAY(start)) * 24) STORED,
+    awake_count INTEGER NULL DEFAULT 0,
+    time_awake INTEGER NULL DEFAULT 0,
+    time_in_bed_after_waking INTEGER NULL DEFAULT 0,
+    quality INTEGER DEFAULT 0,
+    melatonin FLOAT DEFAULT NULL, -- mg
+    benadryl FLOAT DEFAULT 0, -- mg
+    edible FLOAT DEFAULT 0, -- mg
+    exertion INTEGER NULL DEFAULT NULL,
+    notes TEXT NULL DEFAULT NULL,
+    deleted_at TEXT NULL DEFAULT NULL
+);
+
+INSERT INTO sleep_new (id, start, minutes_to_fall_asleep, end, awake_count, time_awake, time_in_bed_after_waking, quality, melatonin, benadryl, edible, exertion, notes)
+SELECT id, start, minutes_to_fall_asleep, end, awake_count, time_awake, time_in_bed_after_waking, quality, melatonin, benadryl, edible, exertion, notes
+FROM sleep;
+
+DROP TABLE sleep;
+ALTER TABLE sleep_new RENAME TO sleep;
+
+CREATE UNIQUE INDEX sleep_start_end ON sleep (start, end) WHERE deleted_at IS NULL;
+
+-- Every change to a sleep row, with the row as it was before the change
+/*
+  action: insert | update | delete (soft) | restore (undelete) | purge (hard delete)
+  previous: the row before the change as JSON, NULL for inserts
+  undone_at: set once `slog undo` has reverted the change
+ */
+CREATE TABLE IF NOT EXISTS sleep_history
+(
+    id         INTEGER primary key AUTOINCREMENT NOT NULL,
+    entry_id   INTEGER NOT NULL,
+    action     TEXT NOT NULL,
+    changed_at TEXT NOT NULL DEFAULT (datetime('now', 'localtime')),
+    previous   TEXT NULL DEFAULT NULL,
+    undone_at  TEXT NULL DEFAULT NULL
+);
+
+CREATE INDEX sleep_history_entry_id ON sleep_history (entry_id);
+
+CREATE TRIGGER sleep_history_insert AFTER INSERT ON sleep
+BEGIN
+    INSERT INTO sleep_history (entry_id, action) VALUES (NEW.id, 'insert');
+END;
+
+CREATE TRIGGER sleep_history_update AFTER UPDATE ON sleep
+BEGIN
+    INSERT INTO sleep_history (entry_id, action, previous)
+    VALUES (
+        OLD.id,
+        CASE
+            WHEN OLD.deleted_at IS NULL AND NEW.deleted_at IS NOT NULL THEN 'delete'
+            WHEN OLD.deleted_at IS NOT NULL AND NEW.deleted_at IS NULL THEN 'restore'
+            ELSE 'update'
+        END,
+        json_object(
+            'id', OLD.id,
+            'start', OLD.start,
+            'minutes_to_fall_asleep', OLD.minutes_to_fall_asleep,
+            'end', OLD.end,
+            'awake_count', OLD.awake_count,
+            'time_awake', OLD.time_awake,
+            'time_in_bed_after_waking', OLD.time_in_bed_after_waking,
+            'quality', OLD.quality,
+            'melatonin', OLD.melatonin,
+            'benadryl', OLD.benadryl,
+            'edible', OLD.edible,
+            'exertion', OLD.exertion,
+            'notes', OLD.notes,
+            'deleted_at', OLD.deleted_at
+        )
+    );
+END;
+
+CREATE TRIGGER sleep_history_delete AFTER DELETE ON sleep
+BEGIN
+    INSERT INTO sleep_history (entry_id, action, previous)
+    VALUES (
+        OLD.id,
+        'purge',
+        json_object(
+            'id', OLD.id,
+            'start', OLD.start,
+            'minutes_to_fall_asleep', OLD.minutes_to_fall_asleep,
+            'end', OLD.end,
+            'awake_count', OLD.awake_count,
+            'time_awake', OLD.time_awake,
+            'time_in_bed_after_waking', OLD.time_in_bed_after_waking,
+            'quality', OLD.quality,
+            'melatonin', OLD.melatonin,
+            'benadryl', OLD.benadryl,
+            'edible', OLD.edible,
+            'exertion', OLD.exertion,
+            'notes', OLD.notes,
+            'deleted_at', OLD.deleted_at
+        )
+    );
+END;
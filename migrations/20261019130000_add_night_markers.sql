@@ -0,0 +1,7 @@
+-- Nights that are known to have no entry, so they don't show up as missing
+CREATE TABLE night_markers (
+    date TEXT PRIMARY KEY NOT NULL,
+    -- 'unknown': slept, but the details are forgotten; 'away': didn't sleep here
+    kind TEXT NOT NULL CHECK (kind IN ('unknown', 'away')),
+    created_at TEXT NOT NULL DEFAULT (datetime('now', 'localtime'))
+);
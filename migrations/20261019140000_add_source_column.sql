@@ -0,0 +1,64 @@
+-- Where an entry came from: NULL for entries recorded by hand, otherwise the importer
+-- (apple_health, fitbit, oura, sleep_as_android), joined with '+' when an import was merged
+-- into an entry recorded by hand, e.g. 'manual+fitbit'
+ALTER TABLE sleep ADD COLUMN source TEXT NULL DEFAULT NULL;
+
+-- The history triggers capture every column, so they are recreated to include the new one
+DROP TRIGGER sleep_history_update;
+DROP TRIGGER sleep_history_delete;
+
+CREATE TRIGGER sleep_history_update AFTER UPDATE ON sleep
+BEGIN
+    INSERT INTO sleep_history (entry_id, action, previous)
+    VALUES (
+        OLD.id,
+        CASE
+            WHEN OLD.deleted_at IS NULL AND NEW.deleted_at IS NOT NULL THEN 'delete'
+            WHEN OLD.deleted_at IS NOT NULL AND NEW.deleted_at IS NULL THEN 'restore'
+            ELSE 'update'
+        END,
+        json_object(
+            'id', OLD.id,
+            'start', OLD.start,
+            'minutes_to_fall_asleep', OLD.minutes_to_fall_asleep,
+            'end', OLD.end,
+            'awake_count', OLD.awake_count,
+            'time_awake', OLD.time_awake,
+            'time_in_bed_after_waking', OLD.time_in_bed_after_waking,
+            'quality', OLD.quality,
+            'melatonin', OLD.melatonin,
+            'benadryl', OLD.benadryl,
+            'edible', OLD.edible,
+            'exertion', OLD.exertion,
+            'notes', OLD.notes,
+            'source', OLD.source,
+            'deleted_at', OLD.deleted_at
+        )
+    );
+END;
+
+CREATE TRIGGER sleep_history_delete AFTER DELETE ON sleep
+BEGIN
+    INSERT INTO sleep_history (entry_id, action, previous)
+    VALUES (
+        OLD.id,
+        'purge',
+        json_object(
+            'id', OLD.id,
+            'start', OLD.start,
+            'minutes_to_fall_asleep', OLD.minutes_to_fall_asleep,
+            'end', OLD.end,
+            'awake_count', OLD.awake_count,
+            'time_awake', OLD.time_awake,
+            'time_in_bed_after_waking', OLD.time_in_bed_after_waking,
+            'quality', OLD.quality,
+            'melatonin', OLD.melatonin,
+            'benadryl', OLD.benadryl,
+            'edible', OLD.edible,
+            'exertion', OLD.exertion,
+            'notes', OLD.notes,
+            'source', OLD.source,
+            'deleted_at', OLD.deleted_at
+        )
+    );
+END;
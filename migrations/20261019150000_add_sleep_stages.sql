@@ -0,0 +1,10 @@
+-- Sleep stages measured by a tracker, one row per continuous stretch of a night
+CREATE TABLE sleep_stages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    sleep_id INTEGER NOT NULL REFERENCES sleep (id) ON DELETE CASCADE,
+    stage TEXT NOT NULL CHECK (stage IN ('awake', 'rem', 'light', 'deep')),
+    start TEXT NOT NULL,
+    end TEXT NOT NULL
+);
+
+CREATE INDEX sleep_stages_sleep_id ON sleep_stages (sleep_id, start);
@@ -0,0 +1,96 @@
+-- Profiles let several people keep their own log in one database
+CREATE TABLE profiles
+(
+    id         INTEGER primary key AUTOINCREMENT NOT NULL,
+    name       TEXT NOT NULL UNIQUE,
+    created_at TEXT NOT NULL DEFAULT (datetime('now', 'localtime'))
+);
+
+-- Existing entries and markers belong to the default profile
+INSERT INTO profiles (id, name) VALUES (1, 'default');
+
+-- SQLite can't add a column with both a REFERENCES clause and a non-NULL default,
+-- so slog keeps profile_id pointing at an existing profile itself
+ALTER TABLE sleep ADD COLUMN profile_id INTEGER NOT NULL DEFAULT 1;
+
+-- Two people can go to bed at the same time
+DROP INDEX sleep_start_end;
+CREATE UNIQUE INDEX sleep_start_end ON sleep (profile_id, start, end) WHERE deleted_at IS NULL;
+CREATE INDEX sleep_profile_id_start ON sleep (profile_id, start);
+
+CREATE TABLE night_markers_new (
+    profile_id INTEGER NOT NULL REFERENCES profiles (id),
+    date TEXT NOT NULL,
+    -- 'unknown': slept, but the details are forgotten; 'away': didn't sleep here
+    kind TEXT NOT NULL CHECK (kind IN ('unknown', 'away')),
+    created_at TEXT NOT NULL DEFAULT (datetime('now', 'localtime')),
+    PRIMARY KEY (profile_id, date)
+);
+
+INSERT INTO night_markers_new (profile_id, date, kind, created_at)
+SELECT 1, date, kind, created_at FROM night_markers;
+
+DROP TABLE night_markers;
+ALTER TABLE night_markers_new RENAME TO night_markers;
+
+-- The history triggers capture every column, so they are recreated to include the new one
+DROP TRIGGER sleep_history_update;
+DROP TRIGGER sleep_history_delete;
+
+CREATE TRIGGER sleep_history_update AFTER UPDATE ON sleep
+BEGIN
+    INSERT INTO sleep_history (entry_id, action, previous)
+    VALUES (
+        OLD.id,
+        CASE
+            WHEN OLD.deleted_at IS NULL AND NEW.deleted_at IS NOT NULL THEN 'delete'
+            WHEN OLD.deleted_at IS NOT NULL AND NEW.deleted_at IS NULL THEN 'restore'
+            ELSE 'update'
+        END,
+        json_object(
+            'id', OLD.id,
+            'start', OLD.start,
+            'minutes_to_fall_asleep', OLD.minutes_to_fall_asleep,
+            'end', OLD.end,
+            'awake_count', OLD.awake_count,
+            'time_awake', OLD.time_awake,
+            'time_in_bed_after_waking', OLD.time_in_bed_after_waking,
+            'quality', OLD.quality,
+            'melatonin', OLD.melatonin,
+            'benadryl', OLD.benadryl,
+            'edible', OLD.edible,
+            'exertion', OLD.exertion,
+            'notes', OLD.notes,
+            'source', OLD.source,
+            'profile_id', OLD.profile_id,
+            'deleted_at', OLD.deleted_at
+        )
+    );
+END;
+
+CREATE TRIGGER sleep_history_delete AFTER DELETE ON sleep
+BEGIN
+    INSERT INTO sleep_history (entry_id, action, previous)
+    VALUES (
+        OLD.id,
+        'purge',
+        json_object(
+            'id', OLD.id,
+            'start', OLD.start,
+            'minutes_to_fall_asleep', OLD.minutes_to_fall_asleep,
+            'end', OLD.end,
+            'awake_count', OLD.awake_count,
+            'time_awake', OLD.time_awake,
+            'time_in_bed_after_waking', OLD.time_in_bed_after_waking,
+            'quality', OLD.quality,
+            'melatonin', OLD.melatonin,
+            'benadryl', OLD.benadryl,
+            'edible', OLD.edible,
+            'exertion', OLD.exertion,
+            'notes', OLD.notes,
+            'source', OLD.source,
+            'profile_id', OLD.profile_id,
+            'deleted_at', OLD.deleted_at
+        )
+    );
+END;
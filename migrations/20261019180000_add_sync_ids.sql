@@ -0,0 +1,102 @@
+-- Stable ids and change times, so `slog sync` can match entries across databases
+-- The history triggers capture every column, so they are dropped before the backfill (which
+-- isn't a change to undo) and recreated to include the new columns
+DROP TRIGGER sleep_history_update;
+DROP TRIGGER sleep_history_delete;
+
+ALTER TABLE sleep ADD COLUMN uuid TEXT NULL DEFAULT NULL;
+ALTER TABLE sleep ADD COLUMN updated_at TEXT NULL DEFAULT NULL;
+
+-- Random version 4 UUIDs; entries last changed when the history last saw them
+UPDATE sleep SET
+    uuid = lower(
+    hex(randomblob(4)) || '-' || hex(randomblob(2)) || '-4' || substr(hex(randomblob(2)), 2) || '-'
+    || substr('89ab', abs(random()) % 4 + 1, 1) || substr(hex(randomblob(2)), 2) || '-'
+    || hex(randomblob(6))
+),
+    updated_at = COALESCE(
+        (SELECT MAX(h.changed_at) FROM sleep_history h WHERE h.entry_id = sleep.id),
+        deleted_at,
+        start
+    );
+
+CREATE UNIQUE INDEX sleep_uuid ON sleep (uuid);
+
+-- Settings of `slog sync`, e.g. this database's device id
+CREATE TABLE sync_state
+(
+    key   TEXT primary key NOT NULL,
+    value TEXT NOT NULL
+);
+
+-- Each entry as both sides had it after the last sync with a target (as JSON), so a sync can
+-- tell which side changed it since
+CREATE TABLE sync_base
+(
+    target TEXT NOT NULL,
+    uuid   TEXT NOT NULL,
+    change TEXT NOT NULL,
+    PRIMARY KEY (target, uuid)
+);
+
+CREATE TRIGGER sleep_history_update AFTER UPDATE ON sleep
+BEGIN
+    INSERT INTO sleep_history (entry_id, action, previous)
+    VALUES (
+        OLD.id,
+        CASE
+            WHEN OLD.deleted_at IS NULL AND NEW.deleted_at IS NOT NULL THEN 'delete'
+            WHEN OLD.deleted_at IS NOT NULL AND NEW.deleted_at IS NULL THEN 'restore'
+            ELSE 'update'
+        END,
+        json_object(
+            'id', OLD.id,
+            'start', OLD.start,
+            'minutes_to_fall_asleep', OLD.minutes_to_fall_asleep,
+            'end', OLD.end,
+            'awake_count', OLD.awake_count,
+            'time_awake', OLD.time_awake,
+            'time_in_bed_after_waking', OLD.time_in_bed_after_waking,
+            'quality', OLD.quality,
+            'melatonin', OLD.melatonin,
+            'benadryl', OLD.benadryl,
+            'edible', OLD.edible,
+            'exertion', OLD.exertion,
+            'notes', OLD.notes,
+            'source', OLD.source,
+            'profile_id', OLD.profile_id,
+            'uuid', OLD.uuid,
+            'updated_at', OLD.updated_at,
+            'deleted_at', OLD.deleted_at
+        )
+    );
+END;
+
+CREATE TRIGGER sleep_history_delete AFTER DELETE ON sleep
+BEGIN
+    INSERT INTO sleep_history (entry_id, action, previous)
+    VALUES (
+        OLD.id,
+        'purge',
+        json_object(
+            'id', OLD.id,
+            'start', OLD.start,
+            'minutes_to_fall_asleep', OLD.minutes_to_fall_asleep,
+            'end', OLD.end,
+            'awake_count', OLD.awake_count,
+            'time_awake', OLD.time_awake,
+            'time_in_bed_after_waking', OLD.time_in_bed_after_waking,
+            'quality', OLD.quality,
+            'melatonin', OLD.melatonin,
+            'benadryl', OLD.benadryl,
+            'edible', OLD.edible,
+            'exertion', OLD.exertion,
+            'notes', OLD.notes,
+            'source', OLD.source,
+            'profile_id', OLD.profile_id,
+            'uuid', OLD.uuid,
+            'updated_at', OLD.updated_at,
+            'deleted_at', OLD.deleted_at
+        )
+    );
+END;
@@ -0,0 +1,88 @@
+-- When each entry was first saved, and how it was made: 'manual' (recorded in slog), 'import'
+-- (from a tracker's export, named by `source`) or 'api' (written by another program)
+-- The history triggers capture every column, so they are dropped before the backfill (which
+-- isn't a change to undo) and recreated to include the new columns
+DROP TRIGGER sleep_history_update;
+DROP TRIGGER sleep_history_delete;
+
+ALTER TABLE sleep ADD COLUMN created_at TEXT NULL DEFAULT NULL;
+ALTER TABLE sleep ADD COLUMN origin TEXT NOT NULL DEFAULT 'manual'
+    CHECK (origin IN ('manual', 'import', 'api'));
+
+-- Entries from before the history was kept are taken as saved when the night ended
+UPDATE sleep SET
+    created_at = COALESCE(
+        (SELECT MIN(h.changed_at) FROM sleep_history h
+         WHERE h.entry_id = sleep.id AND h.action = 'insert'),
+        end,
+        start
+    ),
+    origin = CASE WHEN source IS NULL THEN 'manual' ELSE 'import' END;
+
+UPDATE sleep SET updated_at = created_at WHERE updated_at IS NULL OR updated_at < created_at;
+
+CREATE TRIGGER sleep_history_update AFTER UPDATE ON sleep
+BEGIN
+    INSERT INTO sleep_history (entry_id, action, previous)
+    VALUES (
+        OLD.id,
+        CASE
+            WHEN OLD.deleted_at IS NULL AND NEW.deleted_at IS NOT NULL THEN 'delete'
+            WHEN OLD.deleted_at IS NOT NULL AND NEW.deleted_at IS NULL THEN 'restore'
+            ELSE 'update'
+        END,
+        json_object(
+            'id', OLD.id,
+            'start', OLD.start,
+            'minutes_to_fall_asleep', OLD.minutes_to_fall_asleep,
+            'end', OLD.end,
+            'awake_count', OLD.awake_count,
+            'time_awake', OLD.time_awake,
+            'time_in_bed_after_waking', OLD.time_in_bed_after_waking,
+            'quality', OLD.quality,
+            'melatonin', OLD.melatonin,
+            'benadryl', OLD.benadryl,
+            'edible', OLD.edible,
+            'exertion', OLD.exertion,
+            'notes', OLD.notes,
+            'source', OLD.source,
+            'profile_id', OLD.profile_id,
+            'uuid', OLD.uuid,
+            'created_at', OLD.created_at,
+            'updated_at', OLD.updated_at,
+            'origin', OLD.origin,
+            'deleted_at', OLD.deleted_at
+        )
+    );
+END;
+
+CREATE TRIGGER sleep_history_delete AFTER DELETE ON sleep
+BEGIN
+    INSERT INTO sleep_history (entry_id, action, previous)
+    VALUES (
+        OLD.id,
+        'purge',
+        json_object(
+            'id', OLD.id,
+            'start', OLD.start,
+            'minutes_to_fall_asleep', OLD.minutes_to_fall_asleep,
+            'end', OLD.end,
+            'awake_count', OLD.awake_count,
+            'time_awake', OLD.time_awake,
+            'time_in_bed_after_waking', OLD.time_in_bed_after_waking,
+            'quality', OLD.quality,
+            'melatonin', OLD.melatonin,
+            'benadryl', OLD.benadryl,
+            'edible', OLD.edible,
+            'exertion', OLD.exertion,
+            'notes', OLD.notes,
+            'source', OLD.source,
+            'profile_id', OLD.profile_id,
+            'uuid', OLD.uuid,
+            'created_at', OLD.created_at,
+            'updated_at', OLD.updated_at,
+            'origin', OLD.origin,
+            'deleted_at', OLD.deleted_at
+        )
+    );
+END;
@@ -0,0 +1,3 @@
+-- Changes made together, e.g. deleting a range of entries, share a batch: the id of the batch's
+-- first history row. `slog undo` reverts a batch as a whole
+ALTER TABLE sleep_history ADD COLUMN batch INTEGER NULL DEFAULT NULL;
@@ -0,0 +1,40 @@
+-- A purged entry's stages are deleted with it (ON DELETE CASCADE), so its history row keeps
+-- them too and `slog undo` can bring them back. The trigger runs before the delete, since the
+-- cascade has removed the stages by the time an AFTER trigger sees the row
+DROP TRIGGER sleep_history_delete;
+
+CREATE TRIGGER sleep_history_delete BEFORE DELETE ON sleep
+BEGIN
+    INSERT INTO sleep_history (entry_id, action, previous)
+    VALUES (
+        OLD.id,
+        'purge',
+        json_object(
+            'id', OLD.id,
+            'start', OLD.start,
+            'minutes_to_fall_asleep', OLD.minutes_to_fall_asleep,
+            'end', OLD.end,
+            'awake_count', OLD.awake_count,
+            'time_awake', OLD.time_awake,
+            'time_in_bed_after_waking', OLD.time_in_bed_after_waking,
+            'quality', OLD.quality,
+            'melatonin', OLD.melatonin,
+            'benadryl', OLD.benadryl,
+            'edible', OLD.edible,
+            'exertion', OLD.exertion,
+            'notes', OLD.notes,
+            'source', OLD.source,
+            'profile_id', OLD.profile_id,
+            'uuid', OLD.uuid,
+            'created_at', OLD.created_at,
+            'updated_at', OLD.updated_at,
+            'origin', OLD.origin,
+            'deleted_at', OLD.deleted_at,
+            'stages', json((
+                SELECT json_group_array(json_object('stage', stage, 'start', start, 'end', end))
+                FROM (SELECT stage, start, end FROM sleep_stages
+                      WHERE sleep_id = OLD.id ORDER BY start)
+            ))
+        )
+    );
+END;
@@ -0,0 +1,28 @@
+-- Schema of the Postgres storage (`db_url: postgres://…`). Times are local, like in SQLite.
+CREATE TABLE profiles (
+    id BIGSERIAL PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMP(0) NOT NULL DEFAULT LOCALTIMESTAMP(0)
+);
+
+INSERT INTO profiles (name) VALUES ('default');
+
+CREATE TABLE sleep (
+    id BIGSERIAL PRIMARY KEY,
+    profile_id BIGINT NOT NULL REFERENCES profiles (id),
+    start TIMESTAMP(0) NOT NULL,
+    "end" TIMESTAMP(0),
+    minutes_to_fall_asleep INTEGER,
+    awake_count SMALLINT,
+    time_awake INTEGER,
+    time_in_bed_after_waking INTEGER,
+    quality SMALLINT,
+    melatonin REAL,
+    benadryl REAL,
+    edible REAL,
+    exertion SMALLINT,
+    notes TEXT,
+    source TEXT
+);
+
+CREATE UNIQUE INDEX sleep_profile_id_start_end ON sleep (profile_id, start, "end");
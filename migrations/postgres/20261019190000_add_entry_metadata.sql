@@ -0,0 +1,16 @@
+-- A uuid identifying each entry in every database, when it was first and last saved, and how it
+-- was made: 'manual' (recorded in slog), 'import' (from a tracker's export, named by `source`)
+-- or 'api' (written by another program)
+ALTER TABLE sleep ADD COLUMN uuid UUID NOT NULL DEFAULT gen_random_uuid();
+ALTER TABLE sleep ADD COLUMN created_at TIMESTAMP(0) NULL;
+ALTER TABLE sleep ADD COLUMN updated_at TIMESTAMP(0) NULL;
+ALTER TABLE sleep ADD COLUMN origin TEXT NOT NULL DEFAULT 'manual'
+    CHECK (origin IN ('manual', 'import', 'api'));
+
+CREATE UNIQUE INDEX sleep_uuid ON sleep (uuid);
+
+-- Existing entries are taken as saved when the night ended
+UPDATE sleep SET
+    created_at = COALESCE("end", start),
+    updated_at = COALESCE("end", start),
+    origin = CASE WHEN source IS NULL THEN 'manual' ELSE 'import' END;
@@ -0,0 +1,19 @@
+-- Night markers and sleep stages, as in SQLite
+CREATE TABLE night_markers (
+    profile_id BIGINT NOT NULL REFERENCES profiles (id),
+    date DATE NOT NULL,
+    -- 'unknown': slept, but the details are forgotten; 'away': didn't sleep here
+    kind TEXT NOT NULL CHECK (kind IN ('unknown', 'away')),
+    created_at TIMESTAMP(0) NOT NULL DEFAULT LOCALTIMESTAMP(0),
+    PRIMARY KEY (profile_id, date)
+);
+
+CREATE TABLE sleep_stages (
+    id BIGSERIAL PRIMARY KEY,
+    sleep_id BIGINT NOT NULL REFERENCES sleep (id) ON DELETE CASCADE,
+    stage TEXT NOT NULL CHECK (stage IN ('awake', 'rem', 'light', 'deep')),
+    start TIMESTAMP(0) NOT NULL,
+    "end" TIMESTAMP(0) NOT NULL
+);
+
+CREATE INDEX sleep_stages_sleep_id ON sleep_stages (sleep_id, start);
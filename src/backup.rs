@@ -0,0 +1,322 @@
+use std::ffi::{CStr, CString};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use chrono::Local;
+use libsqlite3_sys as ffi;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
+
+use crate::config::Config;
+use crate::db;
+
+/// Prefix of backups made automatically; only these are rotated.
+const AUTO_PREFIX: &str = "auto-";
+
+/// Directory backups go to unless a destination is given: `backups/` next to the database.
+pub fn backup_dir(config: &Config) -> PathBuf {
+    Path::new(&config.db_file_path)
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join("backups")
+}
+
+/// How often a backup step is retried while another connection holds a lock on the database,
+/// and how long to wait in between: five seconds in all.
+const BUSY_RETRIES: u32 = 100;
+const BUSY_WAIT: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// Copies the database behind `pool` to `dest` with SQLite's online backup API, which gives a
+/// consistent snapshot even while other connections are writing. An encrypted database needs
+/// its `passphrase`, which the copy is encrypted with too.
+pub async fn backup_to(
+    pool: &SqlitePool,
+    dest: &Path,
+    passphrase: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+
+    let mut conn = pool.acquire().await?;
+    let mut handle = conn.lock_handle().await?;
+    let source = Connection(handle.as_raw_handle().as_ptr());
+
+    // The copy blocks, waiting out other connections' locks, so it runs off the async threads
+    // while `handle` keeps sqlx away from the connection
+    let dest = dest.to_path_buf();
+    let passphrase = passphrase.map(str::to_string);
+    tokio::task::spawn_blocking(move || copy(source, &dest, passphrase.as_deref())).await??;
+    Ok(())
+}
+
+/// A raw SQLite connection handed to the blocking copy.
+struct Connection(*mut ffi::sqlite3);
+
+// SAFETY: SQLite connections may be used from any thread as long as only one uses it at a time,
+// which the lock `backup_to` holds until the copy is done ensures.
+unsafe impl Send for Connection {}
+
+fn copy(source: Connection, dest: &Path, passphrase: Option<&str>) -> Result<(), String> {
+    let dest_path = CString::new(dest.to_string_lossy().as_bytes()).map_err(|e| e.to_string())?;
+
+    // SAFETY: `source` stays valid and unused by sqlx while `backup_to` holds its handle;
+    // `dest_db` and `backup` are owned here and released on every path below.
+    unsafe {
+        let mut dest_db: *mut ffi::sqlite3 = std::ptr::null_mut();
+        if ffi::sqlite3_open(dest_path.as_ptr(), &mut dest_db) != ffi::SQLITE_OK {
+            let message = error_message(dest_db);
+            ffi::sqlite3_close(dest_db);
+            return Err(format!("Failed to open {}: {}", dest.display(), message));
+        }
+        if let Some(passphrase) = passphrase {
+            let pragma = CString::new(format!("PRAGMA key = {}", db::quote(passphrase)))
+                .map_err(|e| e.to_string())?;
+            let rc = ffi::sqlite3_exec(
+                dest_db,
+                pragma.as_ptr(),
+                None,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            );
+            if rc != ffi::SQLITE_OK {
+                let message = error_message(dest_db);
+                ffi::sqlite3_close(dest_db);
+                return Err(format!("Failed to key {}: {}", dest.display(), message));
+            }
+        }
+
+        let main = c"main";
+        let backup = ffi::sqlite3_backup_init(dest_db, main.as_ptr(), source.0, main.as_ptr());
+        if backup.is_null() {
+            let message = error_message(dest_db);
+            ffi::sqlite3_close(dest_db);
+            return Err(format!("Failed to start backup: {}", message));
+        }
+
+        let mut rc = ffi::sqlite3_backup_step(backup, -1);
+        let mut retries = 0;
+        while (rc == ffi::SQLITE_BUSY || rc == ffi::SQLITE_LOCKED) && retries < BUSY_RETRIES {
+            std::thread::sleep(BUSY_WAIT);
+            retries += 1;
+            rc = ffi::sqlite3_backup_step(backup, -1);
+        }
+
+        ffi::sqlite3_backup_finish(backup);
+        let message = error_message(dest_db);
+        ffi::sqlite3_close(dest_db);
+
+        if rc == ffi::SQLITE_BUSY || rc == ffi::SQLITE_LOCKED {
+            return Err(format!(
+                "Backup to {} failed: the database stayed locked for {} seconds",
+                dest.display(),
+                (BUSY_WAIT * BUSY_RETRIES).as_secs()
+            ));
+        }
+        if rc != ffi::SQLITE_DONE {
+            return Err(format!("Backup to {} failed: {}", dest.display(), message));
+        }
+    }
+
+    Ok(())
+}
+
+unsafe fn error_message(db: *mut ffi::sqlite3) -> String {
+    if db.is_null() {
+        return "out of memory".to_string();
+    }
+    unsafe { CStr::from_ptr(ffi::sqlite3_errmsg(db)) }
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Backs up the database before something destructive happens, keeping the newest
+/// `backup_retention` automatic backups. Does nothing when retention is 0.
+pub async fn auto_backup(
+    pool: &SqlitePool,
+    config: &Config,
+    reason: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if config.backup_retention == 0 {
+        return Ok(());
+    }
+
+    let dir = backup_dir(config);
+    let dest = dir.join(format!(
+        "{}{}-{}.db",
+        AUTO_PREFIX,
+        Local::now().format("%Y%m%d-%H%M%S%.3f"),
+        reason
+    ));
+    backup_to(pool, &dest, db::passphrase(config)?.as_deref()).await?;
+
+    // Timestamps sort lexically, so the oldest come first
+    let mut backups: Vec<PathBuf> = std::fs::read_dir(&dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(AUTO_PREFIX) && name.ends_with(".db"))
+        })
+        .collect();
+    backups.sort();
+
+    let excess = backups
+        .len()
+        .saturating_sub(config.backup_retention as usize);
+    for old in &backups[..excess] {
+        std::fs::remove_file(old)?;
+    }
+
+    Ok(())
+}
+
+/// Number of files in the backups directory that are encrypted, or not for `false`.
+pub fn count_backups(config: &Config, encrypted: bool) -> usize {
+    std::fs::read_dir(backup_dir(config))
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| db::is_encrypted(&entry.path()) == Some(encrypted))
+                .count()
+        })
+        .unwrap_or(0)
+}
+
+/// Where `slog backup` writes when no destination is given.
+pub fn default_destination(config: &Config) -> PathBuf {
+    backup_dir(config).join(format!("slog-{}.db", Local::now().format("%Y%m%d-%H%M%S")))
+}
+
+/// Migration state of a backup file compared to this build.
+pub enum SchemaCheck {
+    /// Same migrations as this build
+    Current,
+    /// Older; this many migrations will be applied after restoring
+    Older(usize),
+}
+
+/// Checks that `file` is a slog database this build can use, encrypted like the configured one.
+pub async fn check_schema(
+    file: &Path,
+    config: &Config,
+) -> Result<SchemaCheck, Box<dyn std::error::Error>> {
+    if !file.is_file() {
+        return Err(format!("No such file: {}", file.display()).into());
+    }
+
+    // Backups keep the encryption of the database they were made from
+    let passphrase = db::passphrase(config)?;
+    match (db::is_encrypted(file), &passphrase) {
+        (Some(true), None) => {
+            return Err(format!(
+                "{} is encrypted, but the database isn't. Encrypt the database with \
+                 `slog db encrypt` first",
+                file.display()
+            )
+            .into());
+        }
+        (Some(false), Some(_)) => {
+            return Err(format!(
+                "{} isn't encrypted, but the database is. Decrypt the database with \
+                 `slog db decrypt` first",
+                file.display()
+            )
+            .into());
+        }
+        _ => {}
+    }
+
+    let opts =
+        SqliteConnectOptions::from_str(&format!("sqlite:{}", file.display()))?.read_only(true);
+    let pool = SqlitePool::connect_with(db::keyed(opts, passphrase.as_deref()))
+        .await
+        .map_err(|e| format!("{} is not a SQLite database: {}", file.display(), e))?;
+    if passphrase.is_some() {
+        db::check_passphrase(&pool, file).await?;
+    }
+
+    let has_sleep: Option<(String,)> =
+        sqlx::query_as("SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'sleep'")
+            .fetch_optional(&pool)
+            .await
+            .map_err(|e| format!("{} is not a SQLite database: {}", file.display(), e))?;
+    if has_sleep.is_none() {
+        return Err(format!("{} is not a slog database (no sleep table)", file.display()).into());
+    }
+
+    let applied = db::applied_migrations(&pool).await?;
+    pool.close().await;
+
+    if let Some(unknown) = applied
+        .iter()
+        .find(|row| !db::MIGRATOR.iter().any(|m| m.version == row.version))
+    {
+        return Err(format!(
+            "{} was made by a newer slog (unknown migration {} {}); upgrade slog to restore it",
+            file.display(),
+            unknown.version,
+            unknown.description
+        )
+        .into());
+    }
+
+    let pending = db::MIGRATOR
+        .iter()
+        .filter(|m| !applied.iter().any(|row| row.version == m.version))
+        .count();
+    Ok(if pending == 0 {
+        SchemaCheck::Current
+    } else {
+        SchemaCheck::Older(pending)
+    })
+}
+
+/// Replaces the configured database with the contents of `file`.
+/// The caller is expected to have checked the file with `check_schema` first.
+pub async fn restore_from(file: &Path, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let passphrase = db::passphrase(config)?;
+    let opts =
+        SqliteConnectOptions::from_str(&format!("sqlite:{}", file.display()))?.read_only(true);
+    let source = SqlitePool::connect_with(db::keyed(opts, passphrase.as_deref())).await?;
+    backup_to(
+        &source,
+        Path::new(&config.db_file_path),
+        passphrase.as_deref(),
+    )
+    .await?;
+    source.close().await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn gives_up_on_a_locked_destination() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            db_file_path: dir.path().join("slog.db").display().to_string(),
+            ..Config::default()
+        };
+        let pool = db::connect(&config).await.unwrap();
+
+        let dest = dir.path().join("copy.db");
+        let opts = SqliteConnectOptions::from_str(&format!("sqlite:{}", dest.display()))
+            .unwrap()
+            .create_if_missing(true);
+        let other = SqlitePool::connect_with(opts).await.unwrap();
+        let mut lock = other.begin().await.unwrap();
+        sqlx::query("CREATE TABLE held (id INTEGER)")
+            .execute(&mut *lock)
+            .await
+            .unwrap();
+
+        let error = backup_to(&pool, &dest, None).await.unwrap_err();
+        assert!(error.to_string().contains("stayed locked"), "{}", error);
+
+        lock.rollback().await.unwrap();
+        backup_to(&pool, &dest, None).await.unwrap();
+    }
+}
@@ -1,102 +1,533 @@
 use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use std::collections::BTreeMap;
 use std::fs;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+/// How `record` picks its default start and end times.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, strum_macros::Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum TimeDefaults {
+    /// Always use `start_time_default` and `end_time_default`
+    #[default]
+    Static,
+    /// Median of the last `time_defaults_nights` nights
+    RollingMedian,
+    /// Median of the last `time_defaults_nights` nights on the same weekday
+    WeekdayMedian,
+}
+
+/// Where the key of an encrypted SQLite database comes from.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, strum_macros::Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum DbKey {
+    /// The database isn't encrypted
+    #[default]
+    None,
+    /// Ask for the passphrase, once per command
+    Prompt,
+    /// The `SLOG_DB_KEY` environment variable
+    Env,
+    /// The first line of `db_key_file`
+    File,
+}
+
+/// Fields that apply to the whole database and can't be overridden per profile.
+const SHARED_FIELDS: [&str; 5] = [
+    "db_file_path",
+    "db_url",
+    "db_key",
+    "db_key_file",
+    "default_profile",
+];
+
+/// Version of the config file layout written by this build. Fields that are only added
+/// don't need a bump (missing keys take their defaults); renames and changed meanings do,
+/// together with a step in `UPGRADES`.
+pub const CONFIG_VERSION: u32 = 1;
+
+/// Upgrade steps for the raw YAML; `UPGRADES[n]` turns a version `n` file into version `n + 1`.
+const UPGRADES: [fn(&mut Mapping); CONFIG_VERSION as usize] = [
+    // 0 -> 1: unversioned files. Nothing was renamed, the version key is all that's new
+    |_| {},
+];
+
+// Missing keys take their value from `Config::default()`
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
 pub struct Config {
+    /// Files written before versioning have no `version` key and count as version 0
+    #[serde(default)]
+    pub version: u32,
     pub start_time_default: String,
     pub end_time_default: String,
     pub db_file_path: String,
+    /// Postgres or flat-file storage to use instead of the SQLite database at `db_file_path`
+    pub db_url: Option<String>,
+    /// Where the passphrase of an encrypted database comes from (see `slog db encrypt`)
+    pub db_key: DbKey,
+    /// File holding the passphrase when `db_key` is `file`
+    pub db_key_file: Option<String>,
+    /// Profile used when `--profile` isn't given
+    pub default_profile: String,
     pub google_sheets_id: Option<String>,
     pub google_credentials_path: Option<String>,
+    pub time_defaults: TimeDefaults,
+    pub time_defaults_nights: u32,
+    /// Number of automatic backups to keep; 0 turns them off
+    pub backup_retention: u32,
+    /// Time of day (HH:MM) the reminder timer from `slog remind install` fires
+    pub reminder_time: String,
+    /// Calendar file rewritten after every change, for calendar apps to subscribe to
+    pub ics_feed_path: Option<String>,
+    // Goals evaluated by `slog goals` and after recording; unset goals aren't tracked
+    /// In bed by this time (HH:MM)
+    pub goal_bedtime: Option<String>,
+    /// At least this many hours of sleep
+    pub goal_min_sleep_hours: Option<f64>,
+    /// Sleep efficiency of at least this many percent
+    pub goal_min_efficiency: Option<f64>,
+    pub goal_no_melatonin: bool,
+    pub goal_no_benadryl: bool,
+    pub goal_no_edible: bool,
+    /// Per-profile overrides of the fields above, by profile name
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Mapping>,
+    /// Keys this version doesn't know, e.g. from a newer slog; kept so saving doesn't lose them
+    #[serde(flatten, skip_serializing_if = "Mapping::is_empty")]
+    pub unknown: Mapping,
 }
 
 impl Default for Config {
     fn default() -> Self {
-        let home = dirs::home_dir().expect("Failed to get home directory");
-        let default_db = home
-            .join(".local")
-            .join("share")
-            .join("slog")
-            .join("slog.db")
-            .to_string_lossy()
-            .to_string();
+        // Left empty when there is no data directory; connecting reports it then
+        let default_db = data_dir()
+            .map(|dir| {
+                dir.join("slog")
+                    .join("slog.db")
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .unwrap_or_default();
 
         Config {
+            version: CONFIG_VERSION,
             start_time_default: "21:30".to_string(),
             end_time_default: "05:30".to_string(),
             db_file_path: default_db,
+            db_url: None,
+            db_key: DbKey::None,
+            db_key_file: None,
+            default_profile: crate::profiles::DEFAULT_PROFILE.to_string(),
             google_sheets_id: None,
             google_credentials_path: None,
+            time_defaults: TimeDefaults::default(),
+            time_defaults_nights: 14,
+            backup_retention: 10,
+            reminder_time: "10:00".to_string(),
+            ics_feed_path: None,
+            goal_bedtime: None,
+            goal_min_sleep_hours: None,
+            goal_min_efficiency: None,
+            goal_no_melatonin: false,
+            goal_no_benadryl: false,
+            goal_no_edible: false,
+            profiles: BTreeMap::new(),
+            unknown: Mapping::new(),
         }
     }
 }
 
-impl Config {
-    pub fn config_path() -> PathBuf {
-        let home = dirs::home_dir().expect("Failed to get home directory");
-        let config_dir = home.join(".config").join("slog");
+/// `$XDG_CONFIG_HOME`, falling back to `~/.config`.
+pub fn config_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
 
-        fs::create_dir_all(&config_dir).expect("Failed to create config directory");
-        config_dir.join("config.yml")
-    }
+/// `$XDG_DATA_HOME`, falling back to `~/.local/share`.
+fn data_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_DATA_HOME", ".local/share")
+}
+
+fn xdg_dir(var: &str, home_fallback: &str) -> Option<PathBuf> {
+    std::env::var_os(var)
+        .map(PathBuf::from)
+        // The spec says relative paths are invalid and should be ignored
+        .filter(|path| path.is_absolute())
+        .or_else(|| dirs::home_dir().map(|home| home.join(home_fallback)))
+}
 
-    pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
-        let config_path = Self::config_path();
+impl Config {
+    /// Where the config lives unless `--config` / `SLOG_CONFIG` says otherwise.
+    pub fn default_path() -> Result<PathBuf, String> {
+        config_dir()
+            .map(|dir| dir.join("slog").join("config.yml"))
+            .ok_or_else(|| {
+                "Could not determine the config directory. Set XDG_CONFIG_HOME or HOME, or pass --config"
+                    .to_string()
+            })
+    }
 
-        if !config_path.exists() {
+    /// Loads the config at `path`, writing the defaults there first if it doesn't exist yet.
+    /// Files from older versions are upgraded in place after a backup is made.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        if !path.exists() {
             let default_config = Config::default();
-            default_config.save()?;
-            Ok(default_config)
-        } else {
-            let contents = fs::read_to_string(&config_path)?;
-            let config: Config = serde_yaml::from_str(&contents)?;
-            Ok(config)
+            default_config.write(path)?;
+            eprintln!("Config saved to: {}", path.display());
+            return Ok(default_config);
+        }
+
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let invalid =
+            |e: serde_yaml::Error| format!("Invalid config file {}: {}", path.display(), e);
+
+        let mut mapping = match serde_yaml::from_str::<Value>(&contents).map_err(invalid)? {
+            Value::Mapping(mapping) => mapping,
+            Value::Null => Mapping::new(),
+            _ => {
+                return Err(
+                    format!("Invalid config file {}: expected a mapping", path.display()).into(),
+                );
+            }
+        };
+
+        let version = mapping.get("version").and_then(Value::as_u64).unwrap_or(0) as u32;
+        for upgrade in UPGRADES.iter().skip(version as usize) {
+            upgrade(&mut mapping);
+        }
+
+        let mut config: Config =
+            serde_yaml::from_value(Value::Mapping(mapping)).map_err(invalid)?;
+
+        for key in config.unknown.keys() {
+            eprintln!(
+                "⚠️  Ignoring unknown config key {} in {}",
+                serde_yaml::to_string(key).unwrap_or_default().trim_end(),
+                path.display()
+            );
         }
+
+        if version > CONFIG_VERSION {
+            eprintln!(
+                "⚠️  {} was written by a newer slog (config version {}, this build understands {})",
+                path.display(),
+                version,
+                CONFIG_VERSION
+            );
+        } else if version < CONFIG_VERSION {
+            let backup = PathBuf::from(format!("{}.v{}.bak", path.display(), version));
+            fs::copy(path, &backup)
+                .map_err(|e| format!("Failed to back up {}: {}", path.display(), e))?;
+
+            config.version = CONFIG_VERSION;
+            config.write(path)?;
+            eprintln!(
+                "Upgraded config from version {} to {} (backup: {})",
+                version,
+                CONFIG_VERSION,
+                backup.display()
+            );
+        }
+
+        Ok(config)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        self.write(path)?;
+        println!("Config saved to: {}", path.display());
+        Ok(())
     }
 
-    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let config_path = Self::config_path();
+    /// Writes the config to `path` without reporting it, for `load`, whose output must stay off
+    /// stdout so it doesn't end up in whatever a command prints there.
+    fn write(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
         let yaml = serde_yaml::to_string(self)?;
 
-        let mut file = fs::File::create(&config_path)?;
+        let mut file = fs::File::create(path)?;
         file.write_all(yaml.as_bytes())?;
-
-        println!("Config saved to: {}", config_path.display());
         Ok(())
     }
 
+    /// Names of all config fields, in file order. `version` is managed by slog itself.
+    pub fn fields() -> Vec<String> {
+        Config::default()
+            .to_mapping()
+            .keys()
+            .filter_map(|key| key.as_str().map(str::to_string))
+            .filter(|key| key != "version")
+            .collect()
+    }
+
+    pub fn get_field(&self, field: &str) -> Result<Value, String> {
+        Self::check_field(field)?;
+        Ok(self.to_mapping().get(field).cloned().unwrap_or(Value::Null))
+    }
+
+    /// Sets `field` from its command line representation. The value is parsed as YAML
+    /// first (numbers, `null`) and falls back to a plain string.
     pub fn update_field(&mut self, field: &str, value: String) -> Result<(), String> {
-        match field {
-            "start_time_default" => {
-                Self::validate_time_format(&value)?;
-                self.start_time_default = value;
-                Ok(())
+        Self::check_field(field)?;
+
+        let parsed =
+            serde_yaml::from_str::<Value>(&value).unwrap_or_else(|_| Value::String(value.clone()));
+        let mut last_error = String::new();
+        for candidate in [parsed, Value::String(value.clone())] {
+            match self.with_field(field, candidate) {
+                Ok(config) => {
+                    config.validate_field(field)?;
+                    *self = config;
+                    return Ok(());
+                }
+                Err(e) => last_error = e,
             }
-            "end_time_default" => {
-                Self::validate_time_format(&value)?;
-                self.end_time_default = value;
-                Ok(())
+        }
+
+        Err(format!("Invalid value for {}: {}", field, last_error))
+    }
+
+    /// Resets `field` to its default value, which is `null` for the optional fields.
+    pub fn unset_field(&mut self, field: &str) -> Result<(), String> {
+        let default = Config::default().get_field(field)?;
+        *self = self.with_field(field, default)?;
+        Ok(())
+    }
+
+    /// Checks every field and every profile override, returning `(field, problem)` pairs.
+    pub fn validate(&self) -> Vec<(String, String)> {
+        let mut problems: Vec<(String, String)> = Self::fields()
+            .into_iter()
+            .filter_map(|field| match self.validate_field(&field) {
+                Ok(()) => None,
+                Err(e) => Some((field, e)),
+            })
+            .collect();
+
+        for (profile, overrides) in &self.profiles {
+            let effective = match self.for_profile(profile) {
+                Ok(effective) => effective,
+                Err(e) => {
+                    problems.push((format!("profiles.{}", profile), e));
+                    continue;
+                }
+            };
+            for field in overrides.keys().filter_map(Value::as_str) {
+                if let Err(e) = effective.validate_field(field) {
+                    problems.push((format!("profiles.{}.{}", profile, field), e));
+                }
+            }
+        }
+        problems
+    }
+
+    /// The config as `profile` sees it: its overrides from `profiles` applied on top, and
+    /// `default_profile` set to it.
+    pub fn for_profile(&self, profile: &str) -> Result<Config, String> {
+        let mut config = self.clone();
+        if let Some(overrides) = self.profiles.get(profile) {
+            for (key, value) in overrides {
+                let field = key
+                    .as_str()
+                    .ok_or_else(|| format!("Invalid key in profile {}", profile))?;
+                Self::check_profile_field(field)?;
+                config = config.with_field(field, value.clone()).map_err(|e| {
+                    format!("Invalid value for {} in profile {}: {}", field, profile, e)
+                })?;
+            }
+        }
+        config.default_profile = profile.to_string();
+        Ok(config)
+    }
+
+    /// Sets `field` for `profile` only, like `update_field`.
+    pub fn update_profile_field(
+        &mut self,
+        profile: &str,
+        field: &str,
+        value: String,
+    ) -> Result<(), String> {
+        Self::check_profile_field(field)?;
+        let mut effective = self.for_profile(profile)?;
+        effective.update_field(field, value)?;
+        let value = effective.get_field(field)?;
+        self.profiles
+            .entry(profile.to_string())
+            .or_default()
+            .insert(Value::String(field.to_string()), value);
+        Ok(())
+    }
+
+    /// Removes the override of `field` for `profile`, so the top-level value applies again.
+    pub fn unset_profile_field(&mut self, profile: &str, field: &str) -> Result<(), String> {
+        Self::check_profile_field(field)?;
+        if let Some(overrides) = self.profiles.get_mut(profile) {
+            overrides.remove(field);
+            if overrides.is_empty() {
+                self.profiles.remove(profile);
+            }
+        }
+        Ok(())
+    }
+
+    fn check_profile_field(field: &str) -> Result<(), String> {
+        Self::check_field(field)?;
+        if SHARED_FIELDS.contains(&field) {
+            return Err(format!(
+                "{} applies to all profiles and can't be set per profile",
+                field
+            ));
+        }
+        Ok(())
+    }
+
+    fn validate_field(&self, field: &str) -> Result<(), String> {
+        match field {
+            "start_time_default" => Self::validate_time_format(&self.start_time_default),
+            "end_time_default" => Self::validate_time_format(&self.end_time_default),
+            "reminder_time" => Self::validate_time_format(&self.reminder_time),
+            "goal_bedtime" => match &self.goal_bedtime {
+                Some(time) => Self::validate_time_format(time),
+                None => Ok(()),
+            },
+            "goal_min_sleep_hours" => match self.goal_min_sleep_hours {
+                Some(hours) if !(0.0..=24.0).contains(&hours) => {
+                    Err("Must be between 0 and 24".to_string())
+                }
+                _ => Ok(()),
+            },
+            "goal_min_efficiency" => match self.goal_min_efficiency {
+                Some(percent) if !(0.0..=100.0).contains(&percent) => {
+                    Err("Must be between 0 and 100".to_string())
+                }
+                _ => Ok(()),
+            },
+            "default_profile" if self.default_profile.trim().is_empty() => {
+                Err("Must not be empty".to_string())
             }
             "db_file_path" => {
-                self.db_file_path = value;
-                Ok(())
+                if self.db_file_path.trim().is_empty() {
+                    Err("Path must not be empty".to_string())
+                } else if Path::new(&self.db_file_path).is_dir() {
+                    Err(format!("{} is a directory", self.db_file_path))
+                } else {
+                    Ok(())
+                }
             }
-            "google_sheets_id" => {
-                self.google_sheets_id = Some(value);
-                Ok(())
+            "db_url" => match &self.db_url {
+                Some(url) => crate::storage::Location::parse(url).map(|_| ()),
+                None => Ok(()),
+            },
+            "db_key" if self.db_key == DbKey::File && self.db_key_file.is_none() => {
+                Err("Set db_key_file first".to_string())
             }
-            "google_credentials_path" => {
-                self.google_credentials_path = Some(value);
-                Ok(())
+            "db_key_file" => match &self.db_key_file {
+                Some(path) if !Path::new(path).is_file() => {
+                    Err(format!("File does not exist: {}", path))
+                }
+                _ => Ok(()),
+            },
+            "google_credentials_path" => match &self.google_credentials_path {
+                Some(path) if !Path::new(path).is_file() => {
+                    Err(format!("File does not exist: {}", path))
+                }
+                _ => Ok(()),
+            },
+            "ics_feed_path" => match &self.ics_feed_path {
+                Some(path) if path.trim().is_empty() => Err("Path must not be empty".to_string()),
+                Some(path) if Path::new(path).is_dir() => Err(format!("{} is a directory", path)),
+                Some(path) => match Path::new(path).parent() {
+                    Some(dir) if !dir.as_os_str().is_empty() && !dir.is_dir() => {
+                        Err(format!("Directory does not exist: {}", dir.display()))
+                    }
+                    _ => Ok(()),
+                },
+                None => Ok(()),
+            },
+            "time_defaults_nights" if self.time_defaults_nights == 0 => {
+                Err("Must be at least 1".to_string())
             }
-            _ => Err(format!("Unknown field: {}", field)),
+            _ => Ok(()),
+        }
+    }
+
+    fn to_mapping(&self) -> Mapping {
+        match serde_yaml::to_value(self) {
+            Ok(Value::Mapping(mapping)) => mapping,
+            _ => Mapping::new(),
         }
     }
 
-    pub fn get_db_url(&self) -> String {
-        format!("sqlite:{}", self.db_file_path)
+    fn with_field(&self, field: &str, value: Value) -> Result<Config, String> {
+        let mut mapping = self.to_mapping();
+        mapping.insert(Value::String(field.to_string()), value);
+        serde_yaml::from_value(Value::Mapping(mapping)).map_err(|e| e.to_string())
+    }
+
+    fn check_field(field: &str) -> Result<(), String> {
+        let fields = Self::fields();
+        if fields.iter().any(|f| f == field) {
+            return Ok(());
+        }
+
+        let mut message = format!("Unknown field: {}", field);
+        if let Some(suggestion) = fields
+            .iter()
+            .map(|f| (edit_distance(field, f), f))
+            .filter(|(distance, _)| *distance <= 3)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, f)| f)
+        {
+            message.push_str(&format!(". Did you mean {}?", suggestion));
+        }
+        message.push_str(&format!("\nValid fields: {}", fields.join(", ")));
+        Err(message)
+    }
+
+    /// Where the calendar feed of the profile in use goes: its own `ics_feed_path`, or the
+    /// top-level one with the profile's name added (`sleep.ics` becomes `sleep-alex.ics`), so
+    /// profiles don't overwrite each other's feed. The `default` profile keeps the plain path.
+    pub fn feed_path(&self) -> Option<PathBuf> {
+        let path = PathBuf::from(self.ics_feed_path.as_ref()?);
+        let profile = &self.default_profile;
+        let own = self
+            .profiles
+            .get(profile)
+            .is_some_and(|overrides| overrides.contains_key("ics_feed_path"));
+        if own || profile == crate::profiles::DEFAULT_PROFILE {
+            return Some(path);
+        }
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match path.extension() {
+            Some(extension) => format!("{}-{}.{}", stem, profile, extension.to_string_lossy()),
+            None => format!("{}-{}", stem, profile),
+        };
+        Some(path.with_file_name(name))
+    }
+
+    /// URL of the SQLite database. Fails when `db_url` selects another storage, since history,
+    /// backups, sync and `slog db` work on the SQLite file.
+    pub fn get_db_url(&self) -> Result<String, String> {
+        if let Some(url) = &self.db_url {
+            let location = crate::storage::Location::parse(url)?;
+            return Err(format!(
+                "History, backups, sync and `slog db` need the SQLite database, but db_url \
+                 selects {} storage. Use --db to pick a SQLite file",
+                location.kind()
+            ));
+        }
+        if self.db_file_path.trim().is_empty() {
+            return Err(
+                "No database path configured. Set db_file_path, SLOG_DB or pass --db".to_string(),
+            );
+        }
+        Ok(format!("sqlite:{}", self.db_file_path))
     }
 
     fn validate_time_format(input: &str) -> Result<(), String> {
@@ -124,3 +555,43 @@ impl Config {
         Ok(())
     }
 }
+
+// Levenshtein distance, used to suggest the field a typo was meant to be
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_profile_has_its_own_feed() {
+        let mut config = Config {
+            ics_feed_path: Some("sleep.ics".to_string()),
+            ..Config::default()
+        };
+        let feed = |config: &Config, profile: &str| {
+            config.for_profile(profile).unwrap().feed_path().unwrap()
+        };
+        assert_eq!(feed(&config, "default"), Path::new("sleep.ics"));
+        assert_eq!(feed(&config, "alex"), Path::new("sleep-alex.ics"));
+
+        config
+            .update_profile_field("alex", "ics_feed_path", "alex.ics".to_string())
+            .unwrap();
+        assert_eq!(feed(&config, "alex"), Path::new("alex.ics"));
+    }
+}
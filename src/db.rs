@@ -0,0 +1,352 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use inquire::{Password, PasswordDisplayMode};
+use sqlx::migrate::Migrator;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
+
+use crate::backup;
+use crate::config::{Config, DbKey};
+
+/// The migrations in `./migrations`, embedded at compile time.
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Environment variable holding the passphrase when `db_key` is `env`.
+pub const KEY_VAR: &str = "SLOG_DB_KEY";
+
+/// What every unencrypted SQLite file starts with; encrypted ones start with a random salt.
+const PLAIN_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+/// Passphrase typed at the prompt, so commands that open the database more than once (backups,
+/// sync, restore) only ask once.
+static PROMPTED: Mutex<Option<String>> = Mutex::new(None);
+
+/// Opens (creating if needed) the database without touching its schema.
+pub async fn open(config: &Config) -> Result<SqlitePool, Box<dyn std::error::Error>> {
+    let db_url = config.get_db_url()?;
+
+    // Ensure database directory exists
+    if let Some(parent) = Path::new(&config.db_file_path).parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+
+    let passphrase = passphrase(config)?;
+    let path = Path::new(&config.db_file_path);
+    match (is_encrypted(path), &passphrase) {
+        (Some(true), None) => {
+            return Err(format!(
+                "{} is encrypted. Set db_key to where its passphrase comes from, \
+                 e.g. `slog config set db_key prompt`",
+                path.display()
+            )
+            .into());
+        }
+        (Some(false), Some(_)) => {
+            return Err(format!(
+                "{} is not encrypted, but db_key is {}. Encrypt it with `slog db encrypt` \
+                 or `slog config unset db_key`",
+                path.display(),
+                config.db_key
+            )
+            .into());
+        }
+        _ => {}
+    }
+
+    let opts = SqliteConnectOptions::from_str(&db_url)?.create_if_missing(true);
+    let pool = SqlitePool::connect_with(keyed(opts, passphrase.as_deref()))
+        .await
+        .map_err(|e| format!("Failed to open database {}: {}", config.db_file_path, e))?;
+    if passphrase.is_some() {
+        check_passphrase(&pool, path).await?;
+    }
+    Ok(pool)
+}
+
+/// The passphrase of the database as `db_key` says, `None` when it isn't encrypted.
+pub fn passphrase(config: &Config) -> Result<Option<String>, String> {
+    let passphrase = match config.db_key {
+        DbKey::None => return Ok(None),
+        DbKey::Env => std::env::var(KEY_VAR)
+            .map_err(|_| format!("db_key is env, but {} is not set", KEY_VAR))?,
+        DbKey::File => {
+            let path = config
+                .db_key_file
+                .as_ref()
+                .ok_or("db_key is file, but db_key_file is not set")?;
+            let contents = std::fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read key file {}: {}", path, e))?;
+            contents.lines().next().unwrap_or_default().to_string()
+        }
+        DbKey::Prompt => {
+            let mut prompted = PROMPTED.lock().map_err(|e| e.to_string())?;
+            if let Some(passphrase) = prompted.as_ref() {
+                return Ok(Some(passphrase.clone()));
+            }
+            let passphrase = Password::new("Database passphrase:")
+                .without_confirmation()
+                .with_display_mode(PasswordDisplayMode::Masked)
+                .prompt()
+                .map_err(|e| e.to_string())?;
+            *prompted = Some(passphrase.clone());
+            passphrase
+        }
+    };
+    if passphrase.is_empty() {
+        return Err("The database passphrase is empty".to_string());
+    }
+    Ok(Some(passphrase))
+}
+
+/// Asks for a new passphrase, twice.
+pub fn new_passphrase() -> Result<String, String> {
+    let passphrase = Password::new("New database passphrase:")
+        .with_display_mode(PasswordDisplayMode::Masked)
+        .with_custom_confirmation_message("Repeat it:")
+        .prompt()
+        .map_err(|e| e.to_string())?;
+    if passphrase.is_empty() {
+        return Err("The passphrase must not be empty".to_string());
+    }
+    Ok(passphrase)
+}
+
+/// `opts` with SQLCipher's `key` pragma set when there is a passphrase.
+pub fn keyed(opts: SqliteConnectOptions, passphrase: Option<&str>) -> SqliteConnectOptions {
+    match passphrase {
+        Some(passphrase) => opts.pragma("key", quote(passphrase)),
+        None => opts,
+    }
+}
+
+/// `value` as an SQL string literal.
+pub fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Whether the file at `path` is encrypted, `None` when it doesn't exist or is empty.
+pub fn is_encrypted(path: &Path) -> Option<bool> {
+    let mut header = [0u8; 16];
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .ok()?;
+    Some(&header != PLAIN_HEADER)
+}
+
+/// SQLCipher only notices a wrong key when the file is first read, as "file is not a database".
+pub async fn check_passphrase(pool: &SqlitePool, path: &Path) -> Result<(), String> {
+    sqlx::query("SELECT count(*) FROM sqlite_master")
+        .fetch_one(pool)
+        .await
+        .map(|_| ())
+        .map_err(|e| {
+            match e.as_database_error().and_then(|e| e.code()).as_deref() {
+                // SQLITE_NOTADB
+                Some("26") => format!("Wrong passphrase for {}", path.display()),
+                _ => format!("Failed to open database {}: {}", path.display(), e),
+            }
+        })
+}
+
+/// Rewrites the database encrypted with `passphrase`, or unencrypted when it's `None`. The
+/// copy is written next to it with `sqlcipher_export` and checked before it replaces the
+/// original, so a failure leaves the database as it was.
+pub async fn convert(
+    config: &Config,
+    passphrase: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let path = PathBuf::from(&config.db_file_path);
+    let copy = PathBuf::from(format!("{}.converting", config.db_file_path));
+    if copy.exists() {
+        std::fs::remove_file(&copy)?;
+    }
+
+    let pool = connect(config).await?;
+    let result = export(&pool, &copy, passphrase).await;
+    pool.close().await;
+    let result = match result {
+        Ok(()) => check_copy(&copy, passphrase).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&copy);
+        return Err(e);
+    }
+
+    std::fs::set_permissions(&copy, std::fs::metadata(&path)?.permissions())?;
+    std::fs::rename(&copy, &path)?;
+    Ok(())
+}
+
+/// Copies the database behind `pool` into a new file at `dest`.
+async fn export(
+    pool: &SqlitePool,
+    dest: &Path,
+    passphrase: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // ATTACH only applies to the connection it runs on
+    let mut conn = pool.acquire().await?;
+    sqlx::query(&format!(
+        "ATTACH DATABASE {} AS export KEY {}",
+        quote(&dest.to_string_lossy()),
+        quote(passphrase.unwrap_or_default())
+    ))
+    .execute(&mut *conn)
+    .await?;
+    let exported = sqlx::query("SELECT sqlcipher_export('export')")
+        .execute(&mut *conn)
+        .await;
+    sqlx::query("DETACH DATABASE export")
+        .execute(&mut *conn)
+        .await?;
+    exported.map_err(|e| format!("Failed to write {}: {}", dest.display(), e))?;
+    Ok(())
+}
+
+/// Opens the converted copy at `file` and checks it for corruption.
+async fn check_copy(
+    file: &Path,
+    passphrase: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let opts = SqliteConnectOptions::from_str(&format!("sqlite:{}", file.display()))?;
+    let pool = SqlitePool::connect_with(keyed(opts, passphrase)).await?;
+    let problems = integrity_check(&pool).await;
+    pool.close().await;
+    let problems = problems?;
+    if !problems.is_empty() {
+        return Err(format!(
+            "The converted copy {} is damaged: {}",
+            file.display(),
+            problems.join("; ")
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Opens the database and applies any pending migrations.
+pub async fn connect(config: &Config) -> Result<SqlitePool, Box<dyn std::error::Error>> {
+    let pool = open(config).await?;
+    migrate(&pool, config).await?;
+    Ok(pool)
+}
+
+/// Applies pending migrations, backing up existing databases first.
+pub async fn migrate(pool: &SqlitePool, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let applied = applied_migrations(pool).await?;
+    let pending = MIGRATOR
+        .iter()
+        .any(|m| !applied.iter().any(|row| row.version == m.version));
+    // A brand new database has nothing worth backing up
+    if pending && !applied.is_empty() {
+        backup::auto_backup(pool, config, "pre-migrate").await?;
+    }
+
+    MIGRATOR
+        .run(pool)
+        .await
+        .map_err(|e| format!("Failed to apply database migrations: {}", e))?;
+    Ok(())
+}
+
+/// A row of sqlx's `_sqlx_migrations` bookkeeping table.
+#[derive(Debug, sqlx::FromRow)]
+pub struct AppliedMigration {
+    pub version: i64,
+    pub description: String,
+    pub installed_on: String,
+    pub success: bool,
+    pub checksum: Vec<u8>,
+}
+
+pub async fn applied_migrations(pool: &SqlitePool) -> Result<Vec<AppliedMigration>, sqlx::Error> {
+    // The table only exists once the first migration has run
+    let table: Option<(String,)> = sqlx::query_as(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations'",
+    )
+    .fetch_optional(pool)
+    .await?;
+    if table.is_none() {
+        return Ok(Vec::new());
+    }
+
+    sqlx::query_as(
+        "SELECT version, description, installed_on, success, checksum
+         FROM _sqlx_migrations ORDER BY version",
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Prints every known migration and whether it has been applied. Returns the number pending.
+pub async fn print_status(pool: &SqlitePool) -> Result<usize, Box<dyn std::error::Error>> {
+    let applied: HashMap<i64, AppliedMigration> = applied_migrations(pool)
+        .await?
+        .into_iter()
+        .map(|migration| (migration.version, migration))
+        .collect();
+
+    let mut pending = 0;
+    println!(
+        "{:<16} {:<10} {:<22} Description",
+        "Version", "Status", "Applied"
+    );
+    println!("{}", "─".repeat(70));
+
+    for migration in MIGRATOR.iter() {
+        let (status, installed_on) = match applied.get(&migration.version) {
+            Some(row) if !row.success => ("FAILED", row.installed_on.as_str()),
+            Some(row) if row.checksum != migration.checksum.as_ref() => {
+                ("MODIFIED", row.installed_on.as_str())
+            }
+            Some(row) => ("applied", row.installed_on.as_str()),
+            None => {
+                pending += 1;
+                ("pending", "")
+            }
+        };
+        println!(
+            "{:<16} {:<10} {:<22} {}",
+            migration.version, status, installed_on, migration.description
+        );
+    }
+
+    // Applied by a newer slog than this one
+    for row in applied.values() {
+        if !MIGRATOR.iter().any(|m| m.version == row.version) {
+            println!(
+                "{:<16} {:<10} {:<22} {}",
+                row.version, "unknown", row.installed_on, row.description
+            );
+        }
+    }
+
+    Ok(pending)
+}
+
+/// Runs `VACUUM`, returning the file size before and after in bytes.
+pub async fn vacuum(
+    pool: &SqlitePool,
+    db_file_path: &str,
+) -> Result<(u64, u64), Box<dyn std::error::Error>> {
+    let before = std::fs::metadata(db_file_path)?.len();
+    sqlx::query("VACUUM").execute(pool).await?;
+    let after = std::fs::metadata(db_file_path)?.len();
+    Ok((before, after))
+}
+
+/// Runs `PRAGMA integrity_check`, returning the problems found (empty when the database is fine).
+pub async fn integrity_check(pool: &SqlitePool) -> Result<Vec<String>, sqlx::Error> {
+    let rows: Vec<(String,)> = sqlx::query_as("PRAGMA integrity_check")
+        .fetch_all(pool)
+        .await?;
+    Ok(rows
+        .into_iter()
+        .map(|(line,)| line)
+        .filter(|line| line != "ok")
+        .collect())
+}
@@ -0,0 +1,601 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{Datelike, Days, Local, Months, NaiveDate};
+use serde::Serialize;
+use strum::IntoEnumIterator;
+
+use crate::entry::Entry;
+use crate::markers::MarkerKind;
+use crate::stages::{self, Stage};
+use crate::storage::Storage;
+use crate::store::StoreError;
+use crate::{Exertion, Quality};
+
+/// Length of the period a digest covers.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PeriodKind {
+    /// Monday to Sunday
+    Week,
+    /// A calendar month
+    Month,
+}
+
+/// A range of nights, both ends included.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Period {
+    pub kind: PeriodKind,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+}
+
+impl Period {
+    /// The week or month containing `date`.
+    pub fn containing(kind: PeriodKind, date: NaiveDate) -> Self {
+        let from = match kind {
+            PeriodKind::Week => date - Days::new(date.weekday().num_days_from_monday() as u64),
+            PeriodKind::Month => date.with_day(1).expect("every month has a first day"),
+        };
+        Period {
+            kind,
+            from,
+            to: Self::next_from(kind, from) - Days::new(1),
+        }
+    }
+
+    pub fn previous(&self) -> Self {
+        Self::containing(self.kind, self.from - Days::new(1))
+    }
+
+    fn next_from(kind: PeriodKind, from: NaiveDate) -> NaiveDate {
+        match kind {
+            PeriodKind::Week => from + Days::new(7),
+            PeriodKind::Month => from + Months::new(1),
+        }
+    }
+
+    /// Nights of the period up to and including `last`'s, so a period still under way only
+    /// counts the nights that are over.
+    pub fn nights_through(&self, last: NaiveDate) -> i64 {
+        ((self.to.min(last) - self.from).num_days() + 1).max(0)
+    }
+
+    /// `Week 42, 2026` or `October 2026`.
+    pub fn title(&self) -> String {
+        match self.kind {
+            PeriodKind::Week => format!(
+                "Week {}, {} ({} – {})",
+                self.from.iso_week().week(),
+                self.from.iso_week().year(),
+                self.from.format("%b %-d"),
+                self.to.format("%b %-d")
+            ),
+            PeriodKind::Month => self.from.format("%B %Y").to_string(),
+        }
+    }
+}
+
+/// Averages over the nights of a period that have a start and a later end.
+#[derive(Debug, Default, Serialize)]
+pub struct Averages {
+    /// Dates with at least one entry
+    pub nights_logged: usize,
+    pub time_in_bed_minutes: Option<f64>,
+    pub total_sleep_minutes: Option<f64>,
+    pub efficiency: Option<f64>,
+}
+
+/// Average minutes per stage over the nights of a period with stage data.
+#[derive(Debug, Default, Serialize)]
+pub struct StageAverages {
+    pub nights: usize,
+    /// Keyed by stage name
+    pub minutes: BTreeMap<String, f64>,
+}
+
+/// A single night worth pointing out.
+#[derive(Debug, Serialize)]
+pub struct Night {
+    pub date: NaiveDate,
+    pub quality: String,
+    pub total_sleep_minutes: Option<i64>,
+    pub efficiency: Option<f64>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Doses {
+    pub melatonin_mg: f64,
+    pub melatonin_nights: usize,
+    pub benadryl_mg: f64,
+    pub benadryl_nights: usize,
+    pub edible_mg: f64,
+    pub edible_nights: usize,
+}
+
+/// The longest run of consecutive nights with some property.
+#[derive(Debug, Serialize)]
+pub struct Streak {
+    pub description: String,
+    pub nights: usize,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+}
+
+/// Everything `slog digest` reports.
+#[derive(Debug, Serialize)]
+pub struct Digest {
+    pub period: Period,
+    pub nights_in_period: i64,
+    pub nights_unknown: usize,
+    pub nights_away: usize,
+    pub current: Averages,
+    pub previous: Averages,
+    pub best: Option<Night>,
+    pub worst: Option<Night>,
+    pub doses: Doses,
+    pub stages: StageAverages,
+    /// Nights per exertion level, in the order of the levels
+    pub exertion: BTreeMap<String, usize>,
+    pub streaks: Vec<Streak>,
+}
+
+pub fn averages(entries: &[Entry]) -> Averages {
+    // Entries without a positive time in bed would only skew the averages; `slog doctor` reports them
+    let complete: Vec<&Entry> = entries
+        .iter()
+        .filter(|entry| {
+            entry
+                .time_in_bed_minutes()
+                .is_some_and(|minutes| minutes > 0)
+        })
+        .collect();
+    let mean = |values: Vec<f64>| {
+        (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+    };
+    // Like the duplicate check, a night is the date it started on, however many entries it has
+    let nights: BTreeSet<&str> = entries
+        .iter()
+        .map(|entry| entry.start.get(..10).unwrap_or(&entry.start))
+        .collect();
+    Averages {
+        nights_logged: nights.len(),
+        time_in_bed_minutes: mean(
+            complete
+                .iter()
+                .filter_map(|e| e.time_in_bed_minutes())
+                .map(|m| m as f64)
+                .collect(),
+        ),
+        total_sleep_minutes: mean(
+            complete
+                .iter()
+                .filter_map(|e| e.total_sleep_minutes())
+                .map(|m| m as f64)
+                .collect(),
+        ),
+        efficiency: mean(complete.iter().filter_map(|e| e.efficiency()).collect()),
+    }
+}
+
+fn night(entry: &Entry) -> Option<Night> {
+    Some(Night {
+        date: entry.start_datetime().ok()?.date(),
+        quality: entry.quality_name(),
+        total_sleep_minutes: entry.total_sleep_minutes(),
+        efficiency: entry.efficiency(),
+        notes: entry.notes.clone(),
+    })
+}
+
+/// Longest run of consecutive dates in `period` for which `holds` is true.
+fn longest_streak(
+    period: &Period,
+    description: &str,
+    holds: impl Fn(NaiveDate) -> bool,
+) -> Option<Streak> {
+    let mut best: Option<Streak> = None;
+    let mut run: Option<(NaiveDate, usize)> = None;
+    for date in period
+        .from
+        .iter_days()
+        .take_while(|date| *date <= period.to)
+    {
+        if !holds(date) {
+            run = None;
+            continue;
+        }
+        let (from, nights) = match run {
+            Some((from, nights)) => (from, nights + 1),
+            None => (date, 1),
+        };
+        run = Some((from, nights));
+        if best.as_ref().is_none_or(|best| nights > best.nights) {
+            best = Some(Streak {
+                description: description.to_string(),
+                nights,
+                from,
+                to: date,
+            });
+        }
+    }
+    // A single night isn't much of a streak
+    best.filter(|streak| streak.nights >= 2)
+}
+
+/// Builds the digest of `profile` for `period`, compared to the period before it.
+pub async fn build(
+    storage: &impl Storage,
+    profile: i64,
+    period: Period,
+) -> Result<Digest, StoreError> {
+    let entries = storage.between(profile, period.from, period.to).await?;
+    let previous = period.previous();
+    let previous = storage.between(profile, previous.from, previous.to).await?;
+    let marked = storage.markers(profile, period.from, period.to).await?;
+
+    // Best by quality, then efficiency; worst the other way round
+    let rank = |entry: &&Entry| {
+        (
+            entry.quality.unwrap_or(0),
+            (entry.efficiency().unwrap_or(0.0) * 10.0) as i64,
+        )
+    };
+    let best = entries.iter().max_by_key(rank).and_then(night);
+    let worst = entries.iter().min_by_key(rank).and_then(night);
+
+    let mut doses = Doses::default();
+    for entry in &entries {
+        for (dose, total, nights) in [
+            (
+                entry.melatonin,
+                &mut doses.melatonin_mg,
+                &mut doses.melatonin_nights,
+            ),
+            (
+                entry.benadryl,
+                &mut doses.benadryl_mg,
+                &mut doses.benadryl_nights,
+            ),
+            (entry.edible, &mut doses.edible_mg, &mut doses.edible_nights),
+        ] {
+            if let Some(dose) = dose.filter(|dose| *dose > 0.0) {
+                *total += dose as f64;
+                *nights += 1;
+            }
+        }
+    }
+
+    let mut staged = Vec::new();
+    for entry in &entries {
+        let spans = storage.stages(entry.id).await?;
+        if !spans.is_empty() {
+            staged.push(stages::totals(&spans));
+        }
+    }
+    let mut stage_averages = StageAverages {
+        nights: staged.len(),
+        ..Default::default()
+    };
+    for totals in &staged {
+        for (stage, minutes) in totals {
+            *stage_averages
+                .minutes
+                .entry(stage.to_string())
+                .or_insert(0.0) += *minutes as f64 / staged.len() as f64;
+        }
+    }
+
+    let exertion = Exertion::iter()
+        .map(|level| {
+            let nights = entries
+                .iter()
+                .filter(|entry| entry.exertion == Some(level.db_value()))
+                .count();
+            (level.to_string(), nights)
+        })
+        .collect();
+
+    let by_date: BTreeMap<NaiveDate, &Entry> = entries
+        .iter()
+        .filter_map(|entry| Some((entry.start_datetime().ok()?.date(), entry)))
+        .collect();
+    let good = Quality::Okay.db_value();
+    let streaks = [
+        longest_streak(&period, "nights logged in a row", |date| {
+            by_date.contains_key(&date)
+        }),
+        longest_streak(&period, "nights rated Okay or better", |date| {
+            by_date
+                .get(&date)
+                .is_some_and(|entry| entry.quality.is_some_and(|q| q >= good))
+        }),
+        longest_streak(&period, "nights without sleep aids", |date| {
+            by_date.get(&date).is_some_and(|entry| {
+                [entry.melatonin, entry.benadryl, entry.edible]
+                    .iter()
+                    .all(|dose| dose.unwrap_or(0.0) <= 0.0)
+            })
+        }),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    Ok(Digest {
+        period,
+        nights_in_period: period.nights_through(Local::now().date_naive() - Days::new(1)),
+        nights_unknown: marked
+            .values()
+            .filter(|kind| **kind == MarkerKind::Unknown)
+            .count(),
+        nights_away: marked
+            .values()
+            .filter(|kind| **kind == MarkerKind::Away)
+            .count(),
+        current: averages(&entries),
+        previous: averages(&previous),
+        best,
+        worst,
+        doses,
+        stages: stage_averages,
+        exertion,
+        streaks,
+    })
+}
+
+fn hours(minutes: Option<f64>) -> String {
+    match minutes {
+        Some(minutes) => format!(
+            "{}h {:02}m",
+            (minutes / 60.0) as i64,
+            (minutes % 60.0) as i64
+        ),
+        None => "N/A".to_string(),
+    }
+}
+
+fn percent(value: Option<f64>) -> String {
+    match value {
+        Some(value) => format!("{:.1}%", value),
+        None => "N/A".to_string(),
+    }
+}
+
+/// `+12m` / `-1.5%` against the previous period, empty when either side is missing.
+fn change(current: Option<f64>, previous: Option<f64>, unit: &str) -> String {
+    match (current, previous) {
+        (Some(current), Some(previous)) if unit == "m" => {
+            format!("{:+}m", (current - previous).round() as i64)
+        }
+        (Some(current), Some(previous)) => format!("{:+.1}{}", current - previous, unit),
+        _ => String::new(),
+    }
+}
+
+fn describe_night(night: &Night) -> String {
+    let mut line = format!(
+        "{} – {}, {} asleep, {} efficiency",
+        night.date.format("%a %Y-%m-%d"),
+        night.quality,
+        hours(night.total_sleep_minutes.map(|m| m as f64)),
+        percent(night.efficiency)
+    );
+    if let Some(notes) = &night.notes {
+        line.push_str(&format!(" \"{}\"", notes));
+    }
+    line
+}
+
+/// The lines shared by the text and Markdown output, as (heading, lines) sections.
+fn sections(digest: &Digest) -> Vec<(&'static str, Vec<String>)> {
+    let current = &digest.current;
+    let previous = &digest.previous;
+    let mut sections = Vec::new();
+
+    let mut overview = vec![format!(
+        "Nights logged: {} of {}",
+        current.nights_logged, digest.nights_in_period
+    )];
+    if digest.nights_unknown > 0 || digest.nights_away > 0 {
+        overview.push(format!(
+            "Marked: {} unknown, {} away",
+            digest.nights_unknown, digest.nights_away
+        ));
+    }
+    overview.push(format!(
+        "Average time in bed: {} {}",
+        hours(current.time_in_bed_minutes),
+        change(
+            current.time_in_bed_minutes,
+            previous.time_in_bed_minutes,
+            "m"
+        )
+    ));
+    overview.push(format!(
+        "Average sleep: {} {}",
+        hours(current.total_sleep_minutes),
+        change(
+            current.total_sleep_minutes,
+            previous.total_sleep_minutes,
+            "m"
+        )
+    ));
+    overview.push(format!(
+        "Average efficiency: {} {}",
+        percent(current.efficiency),
+        change(current.efficiency, previous.efficiency, "%")
+    ));
+    if previous.nights_logged > 0 {
+        overview.push(format!(
+            "(changes vs the previous {}, {} night(s) logged)",
+            match digest.period.kind {
+                PeriodKind::Week => "week",
+                PeriodKind::Month => "month",
+            },
+            previous.nights_logged
+        ));
+    }
+    sections.push(("Overview", overview));
+
+    if let (Some(best), Some(worst)) = (&digest.best, &digest.worst) {
+        sections.push((
+            "Best and worst",
+            vec![
+                format!("Best: {}", describe_night(best)),
+                format!("Worst: {}", describe_night(worst)),
+            ],
+        ));
+    }
+
+    let doses = &digest.doses;
+    sections.push((
+        "Sleep aids",
+        vec![
+            format!(
+                "Melatonin: {} mg over {} night(s)",
+                doses.melatonin_mg, doses.melatonin_nights
+            ),
+            format!(
+                "Benadryl: {} mg over {} night(s)",
+                doses.benadryl_mg, doses.benadryl_nights
+            ),
+            format!(
+                "Edible: {} mg over {} night(s)",
+                doses.edible_mg, doses.edible_nights
+            ),
+        ],
+    ));
+
+    let staged = &digest.stages;
+    if staged.nights > 0 {
+        let total: f64 = staged.minutes.values().sum();
+        let mut lines: Vec<String> = Stage::iter()
+            .filter_map(|stage| {
+                let minutes = *staged.minutes.get(&stage.to_string())?;
+                Some(format!(
+                    "{}: {} a night ({:.0}%)",
+                    stage,
+                    hours(Some(minutes)),
+                    minutes / total * 100.0
+                ))
+            })
+            .collect();
+        lines.push(format!("(over {} night(s) with stage data)", staged.nights));
+        sections.push(("Sleep stages", lines));
+    }
+
+    sections.push((
+        "Exertion",
+        Exertion::iter()
+            .map(|level| {
+                format!(
+                    "{}: {} night(s)",
+                    level,
+                    digest.exertion.get(&level.to_string()).unwrap_or(&0)
+                )
+            })
+            .collect(),
+    ));
+
+    if !digest.streaks.is_empty() {
+        sections.push((
+            "Streaks",
+            digest
+                .streaks
+                .iter()
+                .map(|streak| {
+                    format!(
+                        "{} {} ({} – {})",
+                        streak.nights,
+                        streak.description,
+                        streak.from.format("%b %-d"),
+                        streak.to.format("%b %-d")
+                    )
+                })
+                .collect(),
+        ));
+    }
+
+    sections
+}
+
+/// Plain text for the terminal.
+pub fn render_text(digest: &Digest) -> String {
+    let mut out = format!("😴 Sleep digest: {}\n", digest.period.title());
+    for (heading, lines) in sections(digest) {
+        out.push_str(&format!("\n{}\n{}\n", heading, "─".repeat(heading.len())));
+        for line in lines {
+            out.push_str(&format!("  {}\n", line.trim_end()));
+        }
+    }
+    out
+}
+
+/// Markdown, e.g. for a journal.
+pub fn render_markdown(digest: &Digest) -> String {
+    let mut out = format!("## Sleep digest: {}\n", digest.period.title());
+    for (heading, lines) in sections(digest) {
+        out.push_str(&format!("\n### {}\n\n", heading));
+        for line in lines {
+            out.push_str(&format!("- {}\n", line.trim_end()));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry;
+
+    fn entry(start: &str, end: &str) -> Entry {
+        Entry {
+            id: 0,
+            start: start.to_string(),
+            end: Some(end.to_string()),
+            minutes_to_fall_asleep: None,
+            awake_count: None,
+            time_awake: None,
+            time_in_bed_after_waking: None,
+            quality: None,
+            melatonin: None,
+            benadryl: None,
+            edible: None,
+            exertion: None,
+            notes: None,
+            source: None,
+            profile_id: 1,
+            uuid: None,
+            created_at: None,
+            updated_at: None,
+            origin: entry::MANUAL.to_string(),
+        }
+    }
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn nights_with_two_entries_are_logged_once() {
+        let entries = [
+            entry("2026-10-12 19:00:00", "2026-10-12 20:00:00"),
+            entry("2026-10-12 23:30:00", "2026-10-13 07:00:00"),
+            entry("2026-10-13 22:30:00", "2026-10-14 06:30:00"),
+        ];
+        assert_eq!(averages(&entries).nights_logged, 2);
+    }
+
+    #[test]
+    fn period_under_way_counts_the_nights_that_are_over() {
+        // Monday to Sunday
+        let week = Period::containing(PeriodKind::Week, date("2026-10-14"));
+        assert_eq!(week.nights_through(date("2026-10-14")), 3);
+        assert_eq!(week.nights_through(date("2026-10-25")), 7);
+        assert_eq!(week.nights_through(date("2026-10-11")), 0);
+
+        let month = Period::containing(PeriodKind::Month, date("2026-02-10"));
+        assert_eq!(month.nights_through(date("2026-03-01")), 28);
+    }
+}
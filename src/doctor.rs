@@ -0,0 +1,198 @@
+use std::collections::BTreeSet;
+
+use chrono::{Days, NaiveDate};
+
+use crate::entry::{DATETIME_FORMAT, Entry, Problem, Severity};
+use crate::storage::Storage;
+use crate::store::StoreError;
+
+/// A problem found in a specific entry.
+pub struct Finding {
+    pub entry: Entry,
+    pub problem: Problem,
+}
+
+/// Everything `slog doctor` found.
+pub struct Report {
+    pub findings: Vec<Finding>,
+    /// Runs of consecutive nights without an entry or marker, between the first and last entry
+    pub missing: Vec<(NaiveDate, NaiveDate)>,
+}
+
+/// Scans every entry of `profile` that isn't deleted.
+pub async fn scan(storage: &impl Storage, profile: i64) -> Result<Report, StoreError> {
+    let mut entries = storage.recent(profile, None).await?;
+    entries.reverse();
+
+    let mut findings = Vec::new();
+    for entry in &entries {
+        for problem in entry.problems() {
+            findings.push(Finding {
+                entry: entry.clone(),
+                problem,
+            });
+        }
+    }
+
+    // Sorted by start, so an overlap is always with an earlier entry that hasn't ended yet
+    let mut latest_end: Option<&Entry> = None;
+    for entry in &entries {
+        let Ok(start) = entry.start_datetime() else {
+            continue;
+        };
+
+        if let Some(previous) = latest_end
+            && let Ok(Some(previous_end)) = previous.end_datetime()
+            && previous_end > start
+        {
+            let same_night = previous.start.get(..10) == entry.start.get(..10);
+            findings.push(Finding {
+                entry: entry.clone(),
+                problem: Problem {
+                    severity: Severity::Invalid,
+                    message: format!(
+                        "{} entry #{} ({} → {})",
+                        if same_night { "duplicates" } else { "overlaps" },
+                        previous.id,
+                        previous.start,
+                        previous.end.as_deref().unwrap_or_default()
+                    ),
+                },
+            });
+        }
+
+        let ends_later = match (entry.end_datetime(), latest_end.map(|e| e.end_datetime())) {
+            (Ok(Some(end)), Some(Ok(Some(latest)))) => end > latest,
+            (Ok(Some(_)), _) => true,
+            _ => false,
+        };
+        if ends_later {
+            latest_end = Some(entry);
+        }
+    }
+
+    let mut missing = Vec::new();
+    for (from, to) in missing_nights(&entries) {
+        let marked = storage.markers(profile, from, to).await?;
+        missing.extend(split_runs(from, to, |date| !marked.contains_key(&date)));
+    }
+
+    Ok(Report { findings, missing })
+}
+
+fn missing_nights(entries: &[Entry]) -> Vec<(NaiveDate, NaiveDate)> {
+    let dates: BTreeSet<NaiveDate> = entries
+        .iter()
+        .filter_map(|entry| entry.start_datetime().ok())
+        .map(|start| start.date())
+        .collect();
+
+    let (Some(first), Some(last)) = (dates.first(), dates.last()) else {
+        return Vec::new();
+    };
+    split_runs(*first, *last, |date| !dates.contains(&date))
+}
+
+/// Runs of consecutive dates from `from` to `to` for which `is_missing` holds.
+fn split_runs(
+    from: NaiveDate,
+    to: NaiveDate,
+    is_missing: impl Fn(NaiveDate) -> bool,
+) -> Vec<(NaiveDate, NaiveDate)> {
+    let mut runs = Vec::new();
+    let mut run_start: Option<NaiveDate> = None;
+    for date in from.iter_days().take_while(|date| *date <= to) {
+        match (is_missing(date), run_start) {
+            (true, None) => run_start = Some(date),
+            (false, Some(start)) => {
+                runs.push((start, date - Days::new(1)));
+                run_start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = run_start {
+        runs.push((start, to));
+    }
+    runs
+}
+
+/// The mechanical repair for an entry, if there is one: a forgotten day rollover on the end,
+/// negative minutes and doses set to 0, quality and exertion clamped to their range.
+/// Returns the repaired entry and what was changed.
+pub fn repair(entry: &Entry) -> Option<(Entry, Vec<String>)> {
+    let mut fixed = entry.clone();
+    let mut changes = Vec::new();
+
+    if let (Ok(start), Ok(Some(end))) = (entry.start_datetime(), entry.end_datetime())
+        && end <= start
+    {
+        let next_day = end + Days::new(1);
+        if next_day > start && (next_day - start).num_hours() < 24 {
+            fixed.end = Some(next_day.format(DATETIME_FORMAT).to_string());
+            changes.push("moved end to the next day".to_string());
+        }
+    }
+
+    for (name, value) in [
+        ("minutes to fall asleep", &mut fixed.minutes_to_fall_asleep),
+        ("time awake", &mut fixed.time_awake),
+        (
+            "time in bed after waking",
+            &mut fixed.time_in_bed_after_waking,
+        ),
+    ] {
+        if let Some(v) = value
+            && *v < 0
+        {
+            *value = Some(0);
+            changes.push(format!("set negative {} to 0", name));
+        }
+    }
+    if let Some(count) = fixed.awake_count
+        && count < 0
+    {
+        fixed.awake_count = Some(0);
+        changes.push("set negative times woken up to 0".to_string());
+    }
+
+    for (name, value) in [
+        ("melatonin", &mut fixed.melatonin),
+        ("benadryl", &mut fixed.benadryl),
+        ("edible", &mut fixed.edible),
+    ] {
+        if let Some(v) = value
+            && *v < 0.0
+        {
+            *value = Some(0.0);
+            changes.push(format!("set negative {} dose to 0", name));
+        }
+    }
+
+    if let Some(quality) = fixed.quality
+        && !(-2..=2).contains(&quality)
+    {
+        fixed.quality = Some(quality.clamp(-2, 2));
+        changes.push(format!(
+            "clamped quality {} to {}",
+            quality,
+            quality.clamp(-2, 2)
+        ));
+    }
+    if let Some(exertion) = fixed.exertion
+        && !(-1..=1).contains(&exertion)
+    {
+        fixed.exertion = Some(exertion.clamp(-1, 1));
+        changes.push(format!(
+            "clamped exertion {} to {}",
+            exertion,
+            exertion.clamp(-1, 1)
+        ));
+    }
+
+    if changes.is_empty() {
+        None
+    } else {
+        Some((fixed, changes))
+    }
+}
@@ -0,0 +1,285 @@
+use chrono::NaiveDateTime;
+
+/// Format of `sleep.start` and `sleep.end`.
+pub const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Columns selected into an `Entry`.
+pub const COLUMNS: &str = "id, start, end, minutes_to_fall_asleep, awake_count, time_awake, time_in_bed_after_waking, quality, melatonin, benadryl, edible, exertion, notes, source, profile_id, uuid, created_at, updated_at, origin";
+
+/// Nights longer than this are probably typos.
+const MAX_PLAUSIBLE_HOURS: i64 = 14;
+/// Nights shorter than this are probably typos.
+const MIN_PLAUSIBLE_HOURS: i64 = 2;
+
+/// A row of the `sleep` table as stored.
+#[derive(Debug, Clone, sqlx::FromRow, serde::Serialize, serde::Deserialize)]
+pub struct Entry {
+    pub id: i64,
+    pub start: String,
+    pub end: Option<String>,
+    pub minutes_to_fall_asleep: Option<i32>,
+    pub awake_count: Option<i16>,
+    pub time_awake: Option<i32>,
+    pub time_in_bed_after_waking: Option<i32>,
+    pub quality: Option<i8>,
+    pub melatonin: Option<f32>,
+    pub benadryl: Option<f32>,
+    pub edible: Option<f32>,
+    pub exertion: Option<i8>,
+    pub notes: Option<String>,
+    /// Importer the entry came from, `None` when recorded by hand
+    pub source: Option<String>,
+    pub profile_id: i64,
+    /// Identifies the entry in every database (see `slog sync`); set by the storage on insert
+    #[serde(default)]
+    pub uuid: Option<String>,
+    /// When the entry was first and last saved; set by the storage
+    #[serde(default)]
+    pub created_at: Option<String>,
+    #[serde(default)]
+    pub updated_at: Option<String>,
+    /// How the entry was made: `manual`, `import` or `api` (written by another program)
+    #[serde(default = "default_origin")]
+    pub origin: String,
+}
+
+/// `origin` of entries recorded in slog.
+pub const MANUAL: &str = "manual";
+/// `origin` of imported entries.
+pub const IMPORT: &str = "import";
+
+/// `origin` assumed when none was stored, e.g. in change files written before it existed.
+pub fn default_origin() -> String {
+    MANUAL.to_string()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    /// Impossible data; refused on insert
+    Invalid,
+    /// Possible but unlikely; confirmed on insert
+    Suspicious,
+}
+
+#[derive(Debug, Clone)]
+pub struct Problem {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Problem {
+    fn invalid(message: impl Into<String>) -> Self {
+        Problem {
+            severity: Severity::Invalid,
+            message: message.into(),
+        }
+    }
+
+    fn suspicious(message: impl Into<String>) -> Self {
+        Problem {
+            severity: Severity::Suspicious,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let icon = match self.severity {
+            Severity::Invalid => "❌",
+            Severity::Suspicious => "⚠️ ",
+        };
+        write!(f, "{} {}", icon, self.message)
+    }
+}
+
+/// One line summary, e.g. `#12 2026-10-10 22:00:00 → 2026-10-11 06:00:00 Okay "notes"`.
+impl std::fmt::Display for Entry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "#{} {} → {} {}",
+            self.id,
+            self.start,
+            self.end.as_deref().unwrap_or("N/A"),
+            self.quality_name()
+        )?;
+        if let Some(notes) = &self.notes {
+            write!(f, " \"{}\"", notes)?;
+        }
+        Ok(())
+    }
+}
+
+impl Entry {
+    /// How the entry was made, with the importer if any, e.g. `import (oura)`.
+    pub fn source_description(&self) -> String {
+        match &self.source {
+            Some(source) => format!("{} ({})", self.origin, source),
+            None => self.origin.clone(),
+        }
+    }
+
+    /// Name of the quality rating, `Unknown` for missing or out of range values.
+    pub fn quality_name(&self) -> String {
+        self.quality
+            .and_then(crate::Quality::from_db_value)
+            .map(|quality| quality.to_string())
+            .unwrap_or_else(|| "Unknown".to_string())
+    }
+
+    /// Name of the exertion level, `Unknown` for missing or out of range values.
+    pub fn exertion_name(&self) -> String {
+        self.exertion
+            .and_then(crate::Exertion::from_db_value)
+            .map(|exertion| exertion.to_string())
+            .unwrap_or_else(|| "Unknown".to_string())
+    }
+
+    pub fn start_datetime(&self) -> Result<NaiveDateTime, chrono::ParseError> {
+        NaiveDateTime::parse_from_str(&self.start, DATETIME_FORMAT)
+    }
+
+    pub fn end_datetime(&self) -> Result<Option<NaiveDateTime>, chrono::ParseError> {
+        self.end
+            .as_deref()
+            .map(|end| NaiveDateTime::parse_from_str(end, DATETIME_FORMAT))
+            .transpose()
+    }
+
+    /// Minutes between start and end, if both are known.
+    pub fn time_in_bed_minutes(&self) -> Option<i64> {
+        let start = self.start_datetime().ok()?;
+        let end = self.end_datetime().ok()??;
+        Some((end - start).num_minutes())
+    }
+
+    /// Time in bed minus the time spent falling asleep, awake and lying in bed after waking.
+    pub fn total_sleep_minutes(&self) -> Option<i64> {
+        Some(
+            self.time_in_bed_minutes()?
+                - self.minutes_to_fall_asleep.unwrap_or(0) as i64
+                - self.time_awake.unwrap_or(0) as i64
+                - self.time_in_bed_after_waking.unwrap_or(0) as i64,
+        )
+    }
+
+    /// Share of the time in bed spent asleep, in percent.
+    pub fn efficiency(&self) -> Option<f64> {
+        let in_bed = self.time_in_bed_minutes()?;
+        if in_bed <= 0 {
+            return None;
+        }
+        Some(self.total_sleep_minutes()? as f64 / in_bed as f64 * 100.0)
+    }
+
+    /// Everything wrong or unlikely about this entry on its own.
+    pub fn problems(&self) -> Vec<Problem> {
+        let mut problems = Vec::new();
+
+        let start = match self.start_datetime() {
+            Ok(start) => Some(start),
+            Err(_) => {
+                problems.push(Problem::invalid(format!(
+                    "start is not a valid time: {}",
+                    self.start
+                )));
+                None
+            }
+        };
+        let end = match self.end_datetime() {
+            Ok(Some(end)) => Some(end),
+            Ok(None) => {
+                problems.push(Problem::suspicious("end time is missing"));
+                None
+            }
+            Err(_) => {
+                problems.push(Problem::invalid(format!(
+                    "end is not a valid time: {}",
+                    self.end.as_deref().unwrap_or_default()
+                )));
+                None
+            }
+        };
+
+        for (name, value) in [
+            ("minutes to fall asleep", self.minutes_to_fall_asleep),
+            ("time awake", self.time_awake),
+            ("time in bed after waking", self.time_in_bed_after_waking),
+            ("times woken up", self.awake_count.map(i32::from)),
+        ] {
+            if value.unwrap_or(0) < 0 {
+                problems.push(Problem::invalid(format!(
+                    "{} is negative ({})",
+                    name,
+                    value.unwrap_or(0)
+                )));
+            }
+        }
+
+        for (name, value) in [
+            ("melatonin", self.melatonin),
+            ("benadryl", self.benadryl),
+            ("edible", self.edible),
+        ] {
+            if value.unwrap_or(0.0) < 0.0 {
+                problems.push(Problem::invalid(format!(
+                    "{} dose is negative ({} mg)",
+                    name,
+                    value.unwrap_or(0.0)
+                )));
+            }
+        }
+
+        if let Some(quality) = self.quality
+            && !(-2..=2).contains(&quality)
+        {
+            problems.push(Problem::invalid(format!(
+                "quality {} is outside -2..2",
+                quality
+            )));
+        }
+        if let Some(exertion) = self.exertion
+            && !(-1..=1).contains(&exertion)
+        {
+            problems.push(Problem::invalid(format!(
+                "exertion {} is outside -1..1",
+                exertion
+            )));
+        }
+
+        if let (Some(start), Some(end)) = (start, end) {
+            let in_bed = (end - start).num_minutes();
+            if in_bed <= 0 {
+                problems.push(Problem::invalid(format!(
+                    "end ({}) is not after start ({})",
+                    end.format("%Y-%m-%d %H:%M"),
+                    start.format("%Y-%m-%d %H:%M")
+                )));
+            } else {
+                let not_sleeping = self.minutes_to_fall_asleep.unwrap_or(0) as i64
+                    + self.time_awake.unwrap_or(0) as i64
+                    + self.time_in_bed_after_waking.unwrap_or(0) as i64;
+                if not_sleeping > in_bed {
+                    problems.push(Problem::invalid(format!(
+                        "time not sleeping ({} min) exceeds time in bed ({} min)",
+                        not_sleeping, in_bed
+                    )));
+                }
+                if in_bed > MAX_PLAUSIBLE_HOURS * 60 {
+                    problems.push(Problem::suspicious(format!(
+                        "{:.1} hours in bed is unusually long",
+                        in_bed as f64 / 60.0
+                    )));
+                } else if in_bed < MIN_PLAUSIBLE_HOURS * 60 {
+                    problems.push(Problem::suspicious(format!(
+                        "{:.1} hours in bed is unusually short",
+                        in_bed as f64 / 60.0
+                    )));
+                }
+            }
+        }
+
+        problems
+    }
+}
@@ -0,0 +1,510 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::entry::{DATETIME_FORMAT, Entry};
+use crate::markers::MarkerKind;
+use crate::profiles::{self, Profile};
+use crate::stages::{self, Span};
+use crate::storage::Storage;
+use crate::store::{self, Save, StoreError};
+
+/// File with the profiles, the night markers and the next entry id.
+const META_FILE: &str = "slog.json";
+
+/// Entries as plain JSON, one file per month of start (`2026-10.json`), so a directory under
+/// git or a sync tool gets small, readable diffs. Deleting removes the entry for good.
+pub struct FileStorage {
+    dir: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Meta {
+    next_id: i64,
+    profiles: Vec<StoredProfile>,
+    #[serde(default)]
+    markers: Vec<StoredMarker>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredMarker {
+    profile_id: i64,
+    date: String,
+    /// `MarkerKind::db_value`
+    kind: String,
+}
+
+/// An entry as written to its month's file, with its sleep stages if it has any.
+#[derive(Clone, Serialize, Deserialize)]
+struct Stored {
+    #[serde(flatten)]
+    entry: Entry,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    stages: Vec<StoredSpan>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct StoredSpan {
+    /// `Stage::db_value`
+    stage: String,
+    start: String,
+    end: String,
+}
+
+impl From<&Span> for StoredSpan {
+    fn from(span: &Span) -> Self {
+        StoredSpan {
+            stage: span.stage.db_value().to_string(),
+            start: span.start.format(DATETIME_FORMAT).to_string(),
+            end: span.end.format(DATETIME_FORMAT).to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredProfile {
+    id: i64,
+    name: String,
+    created_at: String,
+}
+
+impl FileStorage {
+    /// Opens `dir`, creating it with the default profile if needed.
+    pub fn open(dir: &Path) -> Result<Self, String> {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        let storage = FileStorage {
+            dir: dir.to_path_buf(),
+        };
+        if !dir.join(META_FILE).exists() {
+            storage.save_meta(&Meta {
+                next_id: 1,
+                profiles: vec![StoredProfile {
+                    id: 1,
+                    name: profiles::DEFAULT_PROFILE.to_string(),
+                    created_at: now(),
+                }],
+                markers: Vec::new(),
+            })?;
+        }
+        Ok(storage)
+    }
+
+    fn meta(&self) -> Result<Meta, String> {
+        read_json(&self.dir.join(META_FILE))
+    }
+
+    fn save_meta(&self, meta: &Meta) -> Result<(), String> {
+        write_json(&self.dir.join(META_FILE), meta)
+    }
+
+    /// Every entry of every profile, with its stages.
+    fn all(&self) -> Result<Vec<Stored>, String> {
+        let files = std::fs::read_dir(&self.dir)
+            .map_err(|e| format!("Failed to read {}: {}", self.dir.display(), e))?;
+        let mut entries = Vec::new();
+        for file in files {
+            let path = file.map_err(|e| e.to_string())?.path();
+            let is_month = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .is_some_and(|stem| {
+                    chrono::NaiveDate::parse_from_str(&format!("{}-01", stem), "%Y-%m-%d").is_ok()
+                });
+            if is_month
+                && path
+                    .extension()
+                    .is_some_and(|extension| extension == "json")
+            {
+                entries.extend(read_json::<Vec<Stored>>(&path)?);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Every entry of `profile`, without stages.
+    fn entries(&self, profile: i64) -> Result<Vec<Entry>, String> {
+        Ok(self
+            .all()?
+            .into_iter()
+            .map(|stored| stored.entry)
+            .filter(|entry| entry.profile_id == profile)
+            .collect())
+    }
+
+    /// Rewrites the files of `months` from `all`, removing those left empty.
+    fn save(&self, all: &[Stored], months: BTreeSet<String>) -> Result<(), String> {
+        for month in months {
+            let path = self.dir.join(format!("{}.json", month));
+            let mut entries: Vec<&Stored> = all
+                .iter()
+                .filter(|stored| self::month(&stored.entry) == month)
+                .collect();
+            if entries.is_empty() {
+                if path.exists() {
+                    std::fs::remove_file(&path)
+                        .map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+                }
+                continue;
+            }
+            entries.sort_by(|a, b| (&a.entry.start, a.entry.id).cmp(&(&b.entry.start, b.entry.id)));
+            write_json(&path, &entries)?;
+        }
+        Ok(())
+    }
+
+    /// Refuses if `entry` overlaps an entry of the same profile in `all`, other than `exclude`.
+    fn check_overlaps(all: &[Stored], entry: &Entry, exclude: &[i64]) -> Result<(), StoreError> {
+        let mut overlaps: Vec<Entry> = all
+            .iter()
+            .map(|stored| &stored.entry)
+            .filter(|other| {
+                other.profile_id == entry.profile_id
+                    && !exclude.contains(&other.id)
+                    && store::overlaps(other, &entry.start, entry.end.as_deref())
+            })
+            .cloned()
+            .collect();
+        if overlaps.is_empty() {
+            return Ok(());
+        }
+        overlaps.sort_by(|a, b| a.start.cmp(&b.start));
+        Err(StoreError::Overlap(overlaps))
+    }
+
+    /// Adds `entry` to `all` with a new id and drops the marker for its night, returning the id
+    /// and the months to rewrite.
+    fn add(
+        &self,
+        all: &mut Vec<Stored>,
+        meta: &mut Meta,
+        entry: &Entry,
+    ) -> Result<(i64, BTreeSet<String>), StoreError> {
+        store::validate(entry)?;
+        Self::check_overlaps(all, entry, &[])?;
+        let id = meta.next_id;
+        meta.next_id += 1;
+        all.push(Stored {
+            entry: Entry {
+                id,
+                uuid: Some(new_uuid()),
+                created_at: Some(now()),
+                updated_at: Some(now()),
+                ..entry.clone()
+            },
+            stages: Vec::new(),
+        });
+        // A real entry supersedes "unknown" or "didn't sleep here"
+        let date = entry.start.get(..10).unwrap_or(&entry.start);
+        meta.markers
+            .retain(|marker| !(marker.profile_id == entry.profile_id && marker.date == date));
+        Ok((id, BTreeSet::from([month(entry)])))
+    }
+
+    /// Replaces the entry with `entry.id` in `all`, returning the months to rewrite.
+    fn replace(all: &mut [Stored], entry: &Entry) -> Result<BTreeSet<String>, StoreError> {
+        let slot = all
+            .iter_mut()
+            .map(|stored| &mut stored.entry)
+            .find(|other| other.id == entry.id)
+            .ok_or_else(|| StoreError::Other(format!("No entry with id {}", entry.id)))?;
+        let months = BTreeSet::from([month(slot), month(entry)]);
+        *slot = Entry {
+            uuid: slot.uuid.clone(),
+            created_at: slot.created_at.clone(),
+            updated_at: Some(now()),
+            origin: slot.origin.clone(),
+            ..entry.clone()
+        };
+        Ok(months)
+    }
+
+    /// Overwrites the earliest of `existing` with `merged` in `all` and drops the others,
+    /// returning the months to rewrite.
+    fn combine(
+        all: &mut Vec<Stored>,
+        merged: &Entry,
+        existing: &[Entry],
+    ) -> Result<BTreeSet<String>, StoreError> {
+        store::validate(merged)?;
+        let ids: Vec<i64> = existing.iter().map(|entry| entry.id).collect();
+        Self::check_overlaps(all, merged, &ids)?;
+
+        let mut months = Self::replace(all, merged)?;
+        months.extend(existing.iter().map(month));
+        all.retain(|stored| stored.entry.id == merged.id || !ids.contains(&stored.entry.id));
+        Ok(months)
+    }
+
+    /// Checks and applies an update of `entry` to `all`, returning the months to rewrite.
+    fn change(all: &mut [Stored], entry: &Entry) -> Result<BTreeSet<String>, StoreError> {
+        store::validate(entry)?;
+        Self::check_overlaps(all, entry, &[entry.id])?;
+        Self::replace(all, entry)
+    }
+}
+
+impl Storage for FileStorage {
+    fn kind(&self) -> &'static str {
+        "flat-file"
+    }
+
+    async fn profiles(&self) -> Result<Vec<Profile>, StoreError> {
+        let all = self.all()?;
+        Ok(self
+            .meta()?
+            .profiles
+            .into_iter()
+            .map(|profile| Profile {
+                entries: all
+                    .iter()
+                    .filter(|stored| stored.entry.profile_id == profile.id)
+                    .count() as i64,
+                name: profile.name,
+                created_at: profile.created_at,
+            })
+            .collect())
+    }
+
+    async fn profile_id(&self, name: &str) -> Result<Option<i64>, StoreError> {
+        Ok(self
+            .meta()?
+            .profiles
+            .iter()
+            .find(|profile| profile.name == name)
+            .map(|profile| profile.id))
+    }
+
+    async fn add_profile(&self, name: &str) -> Result<i64, StoreError> {
+        let mut meta = self.meta()?;
+        let name = profiles::check_name(name)?;
+        if meta.profiles.iter().any(|profile| profile.name == name) {
+            return Err(profiles::exists(name).into());
+        }
+        let id = meta
+            .profiles
+            .iter()
+            .map(|profile| profile.id)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        meta.profiles.push(StoredProfile {
+            id,
+            name: name.to_string(),
+            created_at: now(),
+        });
+        self.save_meta(&meta)?;
+        Ok(id)
+    }
+
+    async fn rename_profile(&self, name: &str, new_name: &str) -> Result<(), StoreError> {
+        let mut meta = self.meta()?;
+        let new_name = profiles::check_name(new_name)?;
+        if meta.profiles.iter().any(|profile| profile.name == new_name) {
+            return Err(profiles::exists(new_name).into());
+        }
+        let profile = meta
+            .profiles
+            .iter_mut()
+            .find(|profile| profile.name == name)
+            .ok_or_else(|| format!("No profile named {}", name))?;
+        profile.name = new_name.to_string();
+        self.save_meta(&meta)?;
+        Ok(())
+    }
+
+    async fn remove_profile(&self, name: &str) -> Result<(), StoreError> {
+        let mut meta = self.meta()?;
+        let id = meta
+            .profiles
+            .iter()
+            .find(|profile| profile.name == name)
+            .map(|profile| profile.id)
+            .ok_or_else(|| format!("No profile named {}", name))?;
+        let entries = self.entries(id)?.len();
+        if entries > 0 {
+            return Err(profiles::not_empty(name, entries as i64).into());
+        }
+        meta.profiles.retain(|profile| profile.id != id);
+        meta.markers.retain(|marker| marker.profile_id != id);
+        self.save_meta(&meta)?;
+        Ok(())
+    }
+
+    async fn recent(&self, profile: i64, limit: Option<usize>) -> Result<Vec<Entry>, StoreError> {
+        let mut entries = self.entries(profile)?;
+        entries.sort_by(|a, b| b.start.cmp(&a.start));
+        entries.truncate(limit.unwrap_or(usize::MAX));
+        Ok(entries)
+    }
+
+    async fn get(&self, profile: i64, id: i64) -> Result<Option<Entry>, StoreError> {
+        Ok(self
+            .entries(profile)?
+            .into_iter()
+            .find(|entry| entry.id == id))
+    }
+
+    async fn insert(&self, entry: &Entry) -> Result<i64, StoreError> {
+        let mut all = self.all()?;
+        let mut meta = self.meta()?;
+        let (id, months) = self.add(&mut all, &mut meta, entry)?;
+        self.save(&all, months)?;
+        self.save_meta(&meta)?;
+        Ok(id)
+    }
+
+    async fn update(&self, entry: &Entry) -> Result<(), StoreError> {
+        let mut all = self.all()?;
+        let months = Self::change(&mut all, entry)?;
+        self.save(&all, months)?;
+        Ok(())
+    }
+
+    async fn merge(&self, merged: &Entry, existing: &[Entry]) -> Result<(), StoreError> {
+        let mut all = self.all()?;
+        let months = Self::combine(&mut all, merged, existing)?;
+        self.save(&all, months)?;
+        Ok(())
+    }
+
+    async fn delete(&self, ids: &[i64]) -> Result<(), StoreError> {
+        let mut all = self.all()?;
+        let months: BTreeSet<String> = all
+            .iter()
+            .filter(|stored| ids.contains(&stored.entry.id))
+            .map(|stored| month(&stored.entry))
+            .collect();
+        all.retain(|stored| !ids.contains(&stored.entry.id));
+        self.save(&all, months)?;
+        Ok(())
+    }
+
+    async fn markers(
+        &self,
+        profile: i64,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<BTreeMap<NaiveDate, MarkerKind>, StoreError> {
+        Ok(self
+            .meta()?
+            .markers
+            .iter()
+            .filter(|marker| marker.profile_id == profile)
+            .filter_map(|marker| {
+                Some((
+                    NaiveDate::parse_from_str(&marker.date, "%Y-%m-%d").ok()?,
+                    MarkerKind::from_db_value(&marker.kind)?,
+                ))
+            })
+            .filter(|(date, _)| from <= *date && *date <= to)
+            .collect())
+    }
+
+    async fn set_marker(
+        &self,
+        profile: i64,
+        date: NaiveDate,
+        kind: MarkerKind,
+    ) -> Result<(), StoreError> {
+        let mut meta = self.meta()?;
+        let date = date.format("%Y-%m-%d").to_string();
+        meta.markers
+            .retain(|marker| !(marker.profile_id == profile && marker.date == date));
+        meta.markers.push(StoredMarker {
+            profile_id: profile,
+            date,
+            kind: kind.db_value().to_string(),
+        });
+        meta.markers
+            .sort_by(|a, b| (a.profile_id, &a.date).cmp(&(b.profile_id, &b.date)));
+        self.save_meta(&meta)?;
+        Ok(())
+    }
+
+    async fn stages(&self, id: i64) -> Result<Vec<Span>, StoreError> {
+        Ok(self
+            .all()?
+            .iter()
+            .filter(|stored| stored.entry.id == id)
+            .flat_map(|stored| &stored.stages)
+            .filter_map(|span| stages::span(&span.stage, &span.start, &span.end))
+            .collect())
+    }
+
+    async fn save_night(&self, save: &Save, spans: &[Span]) -> Result<i64, StoreError> {
+        let mut all = self.all()?;
+        let mut meta = self.meta()?;
+        let (id, months) = match save {
+            Save::Insert(entry) => self.add(&mut all, &mut meta, entry)?,
+            Save::Update(entry) => (entry.id, Self::change(&mut all, entry)?),
+            Save::Merge(merged, existing) => {
+                (merged.id, Self::combine(&mut all, merged, existing)?)
+            }
+            Save::Replace(entry, old) => {
+                let mut months: BTreeSet<String> = all
+                    .iter()
+                    .filter(|stored| stored.entry.id == *old)
+                    .map(|stored| month(&stored.entry))
+                    .collect();
+                all.retain(|stored| stored.entry.id != *old);
+                let (id, added) = self.add(&mut all, &mut meta, entry)?;
+                months.extend(added);
+                (id, months)
+            }
+        };
+        if !spans.is_empty()
+            && let Some(stored) = all.iter_mut().find(|stored| stored.entry.id == id)
+        {
+            stored.stages = spans.iter().map(StoredSpan::from).collect();
+        }
+        self.save(&all, months)?;
+        self.save_meta(&meta)?;
+        Ok(id)
+    }
+}
+
+/// `YYYY-MM` of the entry's start, naming its file.
+fn month(entry: &Entry) -> String {
+    entry.start.get(..7).unwrap_or("undated").to_string()
+}
+
+/// A random (version 4) UUID.
+fn new_uuid() -> String {
+    let mut bytes: [u8; 16] = rand::random();
+    bytes[6] = bytes[6] & 0x0f | 0x40;
+    bytes[8] = bytes[8] & 0x3f | 0x80;
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+fn now() -> String {
+    chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+fn read_json<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<T, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    serde_json::from_str(&contents)
+        .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+}
+
+/// Writes `value` as pretty JSON, replacing the file in one step.
+fn write_json<T: Serialize + ?Sized>(path: &Path, value: &T) -> Result<(), String> {
+    let mut json = serde_json::to_string_pretty(value).map_err(|e| e.to_string())?;
+    json.push('\n');
+    let partial = path.with_extension("json.partial");
+    std::fs::write(&partial, json)
+        .map_err(|e| format!("Failed to write {}: {}", partial.display(), e))?;
+    std::fs::rename(&partial, path)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, Days, NaiveDate, NaiveTime, Timelike};
+
+use crate::config::Config;
+use crate::entry::Entry;
+use crate::storage::Storage;
+use crate::store::StoreError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Aid {
+    Melatonin,
+    Benadryl,
+    Edible,
+}
+
+impl Aid {
+    fn name(&self) -> &'static str {
+        match self {
+            Aid::Melatonin => "melatonin",
+            Aid::Benadryl => "benadryl",
+            Aid::Edible => "edible",
+        }
+    }
+
+    fn dose(&self, entry: &Entry) -> f32 {
+        match self {
+            Aid::Melatonin => entry.melatonin,
+            Aid::Benadryl => entry.benadryl,
+            Aid::Edible => entry.edible,
+        }
+        .unwrap_or(0.0)
+    }
+}
+
+/// A goal from the `goal_*` config fields.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Goal {
+    /// In bed by this time
+    Bedtime(NaiveTime),
+    MinSleepHours(f64),
+    MinEfficiency(f64),
+    NoAid(Aid),
+}
+
+impl std::fmt::Display for Goal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Goal::Bedtime(time) => write!(f, "In bed by {}", time.format("%H:%M")),
+            Goal::MinSleepHours(hours) => write!(f, "At least {}h of sleep", hours),
+            Goal::MinEfficiency(percent) => write!(f, "Efficiency of at least {}%", percent),
+            Goal::NoAid(aid) => write!(f, "No {}", aid.name()),
+        }
+    }
+}
+
+/// Minutes since noon, so that bedtimes before and after midnight compare correctly.
+fn minutes_since_noon(time: NaiveTime) -> u32 {
+    (time.hour() * 60 + time.minute() + 12 * 60) % (24 * 60)
+}
+
+impl Goal {
+    /// The goals set in `config`, in the order of the config fields.
+    pub fn from_config(config: &Config) -> Vec<Goal> {
+        let mut goals = Vec::new();
+        if let Some(time) = config
+            .goal_bedtime
+            .as_deref()
+            .and_then(|time| NaiveTime::parse_from_str(time, "%H:%M").ok())
+        {
+            goals.push(Goal::Bedtime(time));
+        }
+        if let Some(hours) = config.goal_min_sleep_hours {
+            goals.push(Goal::MinSleepHours(hours));
+        }
+        if let Some(percent) = config.goal_min_efficiency {
+            goals.push(Goal::MinEfficiency(percent));
+        }
+        for (enabled, aid) in [
+            (config.goal_no_melatonin, Aid::Melatonin),
+            (config.goal_no_benadryl, Aid::Benadryl),
+            (config.goal_no_edible, Aid::Edible),
+        ] {
+            if enabled {
+                goals.push(Goal::NoAid(aid));
+            }
+        }
+        goals
+    }
+
+    /// Whether `entry` meets the goal, or `None` when the entry lacks the data to tell.
+    pub fn check(&self, entry: &Entry) -> Option<bool> {
+        match self {
+            Goal::Bedtime(time) => {
+                let start = entry.start_datetime().ok()?.time();
+                Some(minutes_since_noon(start) <= minutes_since_noon(*time))
+            }
+            Goal::MinSleepHours(hours) => Some(entry.total_sleep_minutes()? as f64 >= hours * 60.0),
+            Goal::MinEfficiency(percent) => Some(entry.efficiency()? >= *percent),
+            Goal::NoAid(aid) => Some(aid.dose(entry) <= 0.0),
+        }
+    }
+
+    /// The value the goal looks at for `entry`, e.g. `23:05` or `6h 40m`.
+    pub fn actual(&self, entry: &Entry) -> String {
+        let value = match self {
+            Goal::Bedtime(_) => entry
+                .start_datetime()
+                .ok()
+                .map(|start| start.format("%H:%M").to_string()),
+            Goal::MinSleepHours(_) => entry
+                .total_sleep_minutes()
+                .map(|minutes| format!("{}h {:02}m", minutes / 60, minutes % 60)),
+            Goal::MinEfficiency(_) => entry
+                .efficiency()
+                .map(|efficiency| format!("{:.1}%", efficiency)),
+            Goal::NoAid(aid) => Some(format!("{} mg", aid.dose(entry))),
+        };
+        value.unwrap_or_else(|| "N/A".to_string())
+    }
+}
+
+/// How a goal has been doing over every recorded night.
+pub struct Progress {
+    pub goal: Goal,
+    /// Nights met in a row up to the most recent recorded night
+    pub current_streak: usize,
+    /// The most recent night the goal was evaluated for
+    pub last_night: Option<NaiveDate>,
+    pub longest_streak: usize,
+    pub hits: usize,
+    pub nights: usize,
+    /// Monday of each week, with nights met and nights evaluated that week
+    pub weekly: BTreeMap<NaiveDate, (usize, usize)>,
+}
+
+impl Progress {
+    pub fn hit_rate(&self) -> Option<f64> {
+        (self.nights > 0).then(|| self.hits as f64 / self.nights as f64 * 100.0)
+    }
+}
+
+/// Evaluates `goals` over every night recorded for `profile`. A night without an entry breaks streaks,
+/// unless it is marked (unknown or didn't sleep here); those are left out.
+pub async fn evaluate(
+    storage: &impl Storage,
+    profile: i64,
+    goals: &[Goal],
+) -> Result<Vec<Progress>, StoreError> {
+    let mut entries = storage.recent(profile, None).await?;
+    entries.reverse();
+
+    // The first entry of each night
+    let mut nights: BTreeMap<NaiveDate, &Entry> = BTreeMap::new();
+    for entry in &entries {
+        if let Ok(start) = entry.start_datetime() {
+            nights.entry(start.date()).or_insert(entry);
+        }
+    }
+
+    let mut progress: Vec<Progress> = goals
+        .iter()
+        .map(|goal| Progress {
+            goal: goal.clone(),
+            current_streak: 0,
+            last_night: None,
+            longest_streak: 0,
+            hits: 0,
+            nights: 0,
+            weekly: BTreeMap::new(),
+        })
+        .collect();
+
+    let (Some(first), Some(last)) = (nights.keys().next(), nights.keys().next_back()) else {
+        return Ok(progress);
+    };
+    let marked = storage.markers(profile, *first, *last).await?;
+
+    for date in first.iter_days().take_while(|date| date <= last) {
+        let Some(entry) = nights.get(&date) else {
+            if !marked.contains_key(&date) {
+                for progress in &mut progress {
+                    progress.current_streak = 0;
+                }
+            }
+            continue;
+        };
+
+        let week = date - Days::new(date.weekday().num_days_from_monday() as u64);
+        for progress in &mut progress {
+            let Some(hit) = progress.goal.check(entry) else {
+                continue;
+            };
+            let weekly = progress.weekly.entry(week).or_insert((0, 0));
+            weekly.1 += 1;
+            progress.nights += 1;
+            progress.last_night = Some(date);
+            if hit {
+                weekly.0 += 1;
+                progress.hits += 1;
+                progress.current_streak += 1;
+                progress.longest_streak = progress.longest_streak.max(progress.current_streak);
+            } else {
+                progress.current_streak = 0;
+            }
+        }
+    }
+
+    Ok(progress)
+}
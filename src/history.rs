@@ -0,0 +1,494 @@
+use chrono::NaiveDate;
+use sqlx::{SqliteExecutor, SqlitePool};
+
+use crate::store::{self, StoreError};
+
+/// Columns of `sleep` captured in `sleep_history.previous`.
+const COLUMNS: [&str; 20] = [
+    "id",
+    "start",
+    "minutes_to_fall_asleep",
+    "end",
+    "awake_count",
+    "time_awake",
+    "time_in_bed_after_waking",
+    "quality",
+    "melatonin",
+    "benadryl",
+    "edible",
+    "exertion",
+    "notes",
+    "source",
+    "profile_id",
+    "uuid",
+    "created_at",
+    "updated_at",
+    "origin",
+    "deleted_at",
+];
+
+/// A row of `sleep_history`, with the start of the entry it concerns.
+#[derive(Debug, sqlx::FromRow)]
+pub struct HistoryEvent {
+    pub id: i64,
+    pub entry_id: i64,
+    pub action: String,
+    pub changed_at: String,
+    pub previous: Option<String>,
+    pub undone_at: Option<String>,
+    /// Shared by the changes made together, which are undone together
+    pub batch: Option<i64>,
+    pub start: Option<String>,
+}
+
+const EVENT_QUERY: &str = "
+    SELECT h.id, h.entry_id, h.action, h.changed_at, h.previous, h.undone_at, h.batch,
+           COALESCE(json_extract(h.previous, '$.start'), s.start) AS start
+    FROM sleep_history h
+    LEFT JOIN sleep s ON s.id = h.entry_id";
+
+/// Condition on `EVENT_QUERY` for events of one profile. Purged entries are only in the history,
+/// and history from before profiles existed belongs to the default profile.
+const PROFILE_FILTER: &str =
+    "COALESCE(s.profile_id, json_extract(h.previous, '$.profile_id'), 1) = ?";
+
+/// Soft-deletes an entry; it stays in the table until purged and can be brought back.
+pub async fn soft_delete(executor: impl SqliteExecutor<'_>, id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE sleep SET deleted_at = datetime('now', 'localtime'),
+             updated_at = datetime('now', 'localtime')
+         WHERE id = ? AND deleted_at IS NULL",
+    )
+    .bind(id)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Soft-deletes the entries with these ids in one transaction, as a batch `undo` brings back
+/// as a whole.
+pub async fn soft_delete_all(pool: &SqlitePool, ids: &[i64]) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let last_before = last_event(&mut tx).await?;
+    for id in ids {
+        soft_delete(&mut *tx, *id).await?;
+    }
+    batch_since(&mut tx, last_before).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Puts the history rows added after `last_before` (from `last_event`) in one batch.
+pub async fn batch_since(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    last_before: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE sleep_history SET batch = (SELECT MIN(id) FROM sleep_history WHERE id > ?1)
+         WHERE id > ?1",
+    )
+    .bind(last_before)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Id of the latest history row, 0 if there is none.
+pub async fn last_event(tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>) -> Result<i64, sqlx::Error> {
+    let (id,): (i64,) = sqlx::query_as("SELECT COALESCE(MAX(id), 0) FROM sleep_history")
+        .fetch_one(&mut **tx)
+        .await?;
+    Ok(id)
+}
+
+/// Brings back a soft-deleted entry of `profile`. Returns false if there was no such deleted
+/// entry. Refuses if the entry overlaps one added since it was deleted.
+pub async fn restore_entry(
+    pool: &SqlitePool,
+    profile: i64,
+    id: i64,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let mut tx = pool.begin().await?;
+    let result = sqlx::query(
+        "UPDATE sleep SET deleted_at = NULL, updated_at = datetime('now', 'localtime')
+         WHERE id = ? AND profile_id = ? AND deleted_at IS NOT NULL",
+    )
+    .bind(id)
+    .bind(profile)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(db) if db.is_unique_violation() => {
+            "Another entry with the same start and end exists; delete it first".to_string()
+        }
+        e => e.to_string(),
+    })?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    if let Some(overlaps) = overlaps_of(&mut tx, id).await? {
+        return Err(format!("Cannot restore entry {}: it {}", id, overlaps).into());
+    }
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// The overlap entry `id` has with other entries now, if it isn't deleted.
+async fn overlaps_of(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    id: i64,
+) -> Result<Option<StoreError>, sqlx::Error> {
+    let row: Option<(i64, String, Option<String>)> = sqlx::query_as(
+        "SELECT profile_id, start, end FROM sleep WHERE id = ? AND deleted_at IS NULL",
+    )
+    .bind(id)
+    .fetch_optional(&mut **tx)
+    .await?;
+    let Some((profile, start, end)) = row else {
+        return Ok(None);
+    };
+
+    let overlaps = store::overlapping(&mut **tx, profile, &start, end.as_deref(), &[id]).await?;
+    Ok((!overlaps.is_empty()).then_some(StoreError::Overlap(overlaps)))
+}
+
+/// Most recent history events of `profile`, optionally only those for entries starting on `date`.
+pub async fn events(
+    pool: &SqlitePool,
+    profile: i64,
+    date: Option<NaiveDate>,
+    count: usize,
+) -> Result<Vec<HistoryEvent>, sqlx::Error> {
+    match date {
+        Some(date) => {
+            let query = format!(
+                "{} WHERE {} AND DATE(COALESCE(json_extract(h.previous, '$.start'), s.start)) = ?
+                 ORDER BY h.id DESC LIMIT ?",
+                EVENT_QUERY, PROFILE_FILTER
+            );
+            sqlx::query_as(&query)
+                .bind(profile)
+                .bind(date.format("%Y-%m-%d").to_string())
+                .bind(count as i64)
+                .fetch_all(pool)
+                .await
+        }
+        None => {
+            let query = format!(
+                "{} WHERE {} ORDER BY h.id DESC LIMIT ?",
+                EVENT_QUERY, PROFILE_FILTER
+            );
+            sqlx::query_as(&query)
+                .bind(profile)
+                .bind(count as i64)
+                .fetch_all(pool)
+                .await
+        }
+    }
+}
+
+/// Reverts the most recent change to an entry of `profile` that hasn't been undone yet, or
+/// all of its batch, and returns the events reverted. Repeated calls walk further back through
+/// the history.
+pub async fn undo(
+    pool: &SqlitePool,
+    profile: i64,
+) -> Result<Vec<HistoryEvent>, Box<dyn std::error::Error>> {
+    let mut tx = pool.begin().await?;
+
+    let query = format!(
+        "{} WHERE h.undone_at IS NULL AND {} ORDER BY h.id DESC LIMIT 1",
+        EVENT_QUERY, PROFILE_FILTER
+    );
+    let event: Option<HistoryEvent> = sqlx::query_as(&query)
+        .bind(profile)
+        .fetch_optional(&mut *tx)
+        .await?;
+    let Some(event) = event else {
+        return Ok(Vec::new());
+    };
+    let (event_id, batch) = (event.id, event.batch);
+    let events = match batch {
+        Some(batch) => {
+            let query = format!(
+                "{} WHERE h.batch = ? AND h.undone_at IS NULL ORDER BY h.id DESC",
+                EVENT_QUERY
+            );
+            sqlx::query_as(&query)
+                .bind(batch)
+                .fetch_all(&mut *tx)
+                .await?
+        }
+        None => vec![event],
+    };
+    let last_before = last_event(&mut tx).await?;
+
+    for event in &events {
+        revert(&mut tx, event).await?;
+    }
+    // Only once the whole batch is back, since its entries can't overlap each other
+    for event in &events {
+        if let Some(overlaps) = overlaps_of(&mut tx, event.entry_id).await? {
+            return Err(format!(
+                "Cannot undo {}: entry {} would {}",
+                describe(event),
+                event.entry_id,
+                overlaps
+            )
+            .into());
+        }
+    }
+
+    // Marks the events and the history rows the revert itself produced, so they aren't undone
+    // next. Later changes to other profiles' entries stay undoable.
+    sqlx::query(
+        "UPDATE sleep_history SET undone_at = datetime('now', 'localtime')
+         WHERE (id = ? OR batch = ? OR id > ?) AND undone_at IS NULL",
+    )
+    .bind(event_id)
+    .bind(batch)
+    .bind(last_before)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(events)
+}
+
+/// Puts the entry of `event` back the way it was before.
+async fn revert(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    event: &HistoryEvent,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let result = match (event.action.as_str(), &event.previous) {
+        ("insert", _) => {
+            sqlx::query(
+                "UPDATE sleep SET deleted_at = datetime('now', 'localtime'),
+                     updated_at = datetime('now', 'localtime')
+                 WHERE id = ? AND deleted_at IS NULL",
+            )
+            .bind(event.entry_id)
+            .execute(&mut **tx)
+            .await
+        }
+        ("purge", Some(previous)) => {
+            let values: Vec<String> = COLUMNS.iter().map(|column| restored(column)).collect();
+            let sql = format!(
+                "INSERT INTO sleep ({}) VALUES ({})",
+                COLUMNS.join(", "),
+                values.join(", ")
+            );
+            let result = sqlx::query(&sql).bind(previous).execute(&mut **tx).await;
+            if result.is_ok() {
+                // The stages were purged with the entry
+                sqlx::query(
+                    "INSERT INTO sleep_stages (sleep_id, stage, start, end)
+                     SELECT ?2, json_extract(value, '$.stage'), json_extract(value, '$.start'),
+                         json_extract(value, '$.end')
+                     FROM json_each(?1, '$.stages')",
+                )
+                .bind(previous)
+                .bind(event.entry_id)
+                .execute(&mut **tx)
+                .await?;
+            }
+            result
+        }
+        (_, Some(previous)) => {
+            // These never change
+            let assignments: Vec<String> = COLUMNS
+                .iter()
+                .filter(|column| !matches!(**column, "id" | "uuid" | "created_at" | "origin"))
+                .map(|column| format!("{} = {}", column, restored(column)))
+                .collect();
+            let sql = format!("UPDATE sleep SET {} WHERE id = ?2", assignments.join(", "));
+            sqlx::query(&sql)
+                .bind(previous)
+                .bind(event.entry_id)
+                .execute(&mut **tx)
+                .await
+        }
+        (action, None) => {
+            return Err(format!("Cannot undo {} without its previous row", action).into());
+        }
+    };
+
+    result.map_err(|e| match e {
+        sqlx::Error::Database(db) if db.is_unique_violation() => {
+            "Cannot undo: another entry with the same start and end exists".to_string()
+        }
+        e => e.to_string(),
+    })?;
+    Ok(())
+}
+
+/// SQL for the value `column` gets back from the previous row in `?1` when undoing. Reverting
+/// is a change of its own, and rows from before these columns existed get what the migrations
+/// gave every other row.
+fn restored(column: &str) -> String {
+    match column {
+        "updated_at" => "datetime('now', 'localtime')".to_string(),
+        "uuid" => format!("COALESCE(json_extract(?1, '$.uuid'), {})", store::NEW_UUID),
+        "created_at" => "COALESCE(json_extract(?1, '$.created_at'), json_extract(?1, '$.end'),
+            json_extract(?1, '$.start'))"
+            .to_string(),
+        "origin" => "COALESCE(json_extract(?1, '$.origin'),
+            IIF(json_extract(?1, '$.source') IS NULL, 'manual', 'import'))"
+            .to_string(),
+        _ => format!("json_extract(?1, '$.{}')", column),
+    }
+}
+
+/// One line description of an event, e.g. `delete entry 5 (2026-10-10 22:00:00)`.
+pub fn describe(event: &HistoryEvent) -> String {
+    format!(
+        "{} entry {} ({})",
+        event.action,
+        event.entry_id,
+        event.start.as_deref().unwrap_or("unknown")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::db;
+    use crate::entry::{self, Entry};
+    use crate::stages::{self, Span, Stage};
+
+    fn night(day: u32) -> Entry {
+        Entry {
+            id: 0,
+            start: format!("2026-01-{:02} 22:00:00", day),
+            end: Some(format!("2026-01-{:02} 06:00:00", day + 1)),
+            minutes_to_fall_asleep: None,
+            awake_count: None,
+            time_awake: None,
+            time_in_bed_after_waking: None,
+            quality: None,
+            melatonin: None,
+            benadryl: None,
+            edible: None,
+            exertion: None,
+            notes: None,
+            source: None,
+            profile_id: 1,
+            uuid: None,
+            created_at: None,
+            updated_at: None,
+            origin: entry::MANUAL.to_string(),
+        }
+    }
+
+    async fn live(pool: &SqlitePool) -> usize {
+        store::recent(pool, 1, None).await.unwrap().len()
+    }
+
+    #[tokio::test]
+    async fn undo_reverts_a_batch_as_a_whole() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            db_file_path: dir.path().join("slog.db").display().to_string(),
+            ..Config::default()
+        };
+        let pool = db::connect(&config).await.unwrap();
+        let mut ids = Vec::new();
+        for day in 10..13 {
+            ids.push(store::insert(&pool, &night(day)).await.unwrap());
+        }
+
+        soft_delete_all(&pool, &ids).await.unwrap();
+        assert_eq!(live(&pool).await, 0);
+
+        let undone = undo(&pool, 1).await.unwrap();
+        assert_eq!(undone.len(), 3);
+        assert!(undone.iter().all(|event| event.action == "delete"));
+        assert_eq!(live(&pool).await, 3);
+
+        // The next undo goes on before the batch, to the last insert
+        let undone = undo(&pool, 1).await.unwrap();
+        assert_eq!(undone.len(), 1);
+        assert_eq!(undone[0].action, "insert");
+        assert_eq!(live(&pool).await, 2);
+    }
+
+    #[tokio::test]
+    async fn undo_brings_back_a_replaced_night() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            db_file_path: dir.path().join("slog.db").display().to_string(),
+            ..Config::default()
+        };
+        let pool = db::connect(&config).await.unwrap();
+        let old = store::insert(&pool, &night(10)).await.unwrap();
+        let replacement = Entry {
+            quality: Some(2),
+            ..night(10)
+        };
+        let new = store::save(&pool, &store::Save::Replace(replacement, old), &[])
+            .await
+            .unwrap();
+        let entries = store::recent(&pool, 1, None).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].id, new);
+
+        let undone = undo(&pool, 1).await.unwrap();
+        assert_eq!(undone.len(), 2);
+        let entries = store::recent(&pool, 1, None).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].id, old);
+    }
+
+    #[tokio::test]
+    async fn undoing_a_purge_brings_back_the_stages() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            db_file_path: dir.path().join("slog.db").display().to_string(),
+            ..Config::default()
+        };
+        let pool = db::connect(&config).await.unwrap();
+        let id = store::insert(&pool, &night(10)).await.unwrap();
+        let time = |value: &str| {
+            chrono::NaiveDateTime::parse_from_str(value, entry::DATETIME_FORMAT).unwrap()
+        };
+        let spans = [
+            Span {
+                stage: Stage::Light,
+                start: time("2026-01-10 22:10:00"),
+                end: time("2026-01-11 01:00:00"),
+            },
+            Span {
+                stage: Stage::Deep,
+                start: time("2026-01-11 01:00:00"),
+                end: time("2026-01-11 06:00:00"),
+            },
+        ];
+        let mut tx = pool.begin().await.unwrap();
+        stages::replace(&mut tx, id, &spans).await.unwrap();
+        tx.commit().await.unwrap();
+
+        sqlx::query("DELETE FROM sleep WHERE id = ?")
+            .bind(id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(stages::for_entry(&pool, id).await.unwrap().is_empty());
+
+        let undone = undo(&pool, 1).await.unwrap();
+        assert_eq!(undone[0].action, "purge");
+        let restored = stages::for_entry(&pool, id).await.unwrap();
+        assert_eq!(
+            restored
+                .iter()
+                .map(|span| (span.stage, span.start, span.end))
+                .collect::<Vec<_>>(),
+            spans
+                .iter()
+                .map(|span| (span.stage, span.start, span.end))
+                .collect::<Vec<_>>()
+        );
+    }
+}
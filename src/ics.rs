@@ -0,0 +1,141 @@
+use std::path::Path;
+
+use chrono::{Local, NaiveDateTime, TimeZone, Utc};
+
+use crate::entry::{DATETIME_FORMAT, Entry};
+use crate::storage::Storage;
+
+/// Format of `DTSTART`, `DTEND` and `DTSTAMP`, always in UTC so calendars in any time zone
+/// place the event correctly.
+const ICS_UTC_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// `time` (local, as stored) in UTC. Times skipped by a DST change don't exist locally and are
+/// taken as an hour later; repeated ones as their first occurrence.
+fn to_utc(time: NaiveDateTime) -> String {
+    let local = Local.from_local_datetime(&time).earliest().or_else(|| {
+        Local
+            .from_local_datetime(&(time + chrono::TimeDelta::hours(1)))
+            .earliest()
+    });
+    match local {
+        Some(local) => local.with_timezone(&Utc).format(ICS_UTC_FORMAT).to_string(),
+        None => time.format(ICS_UTC_FORMAT).to_string(),
+    }
+}
+
+/// Escapes a TEXT value (RFC 5545 section 3.3.11).
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Appends a content line, folded so no line is longer than 75 octets.
+fn push_line(out: &mut String, line: &str) {
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            length = 1;
+        }
+        out.push(c);
+        length += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+fn duration(minutes: i64) -> String {
+    format!("{}h {:02}m", minutes / 60, minutes % 60)
+}
+
+/// A calendar with one event per entry that has a valid end.
+pub fn render(entries: &[Entry]) -> String {
+    let stamp = Utc::now().format(ICS_UTC_FORMAT).to_string();
+    let mut out = String::new();
+    for line in [
+        "BEGIN:VCALENDAR",
+        "VERSION:2.0",
+        "PRODID:-//slog//Sleep Log//EN",
+        "CALSCALE:GREGORIAN",
+        "X-WR-CALNAME:Sleep",
+    ] {
+        push_line(&mut out, line);
+    }
+
+    for entry in entries {
+        // Entries ending before they start can't be placed; `slog doctor` reports them
+        let (Ok(start), Ok(Some(end))) = (entry.start_datetime(), entry.end_datetime()) else {
+            continue;
+        };
+        if end <= start {
+            continue;
+        }
+        let mut description = vec![format!("Quality: {}", entry.quality_name())];
+        if let Some(efficiency) = entry.efficiency() {
+            description.push(format!("Efficiency: {:.1}%", efficiency));
+        }
+        if let Some(minutes) = entry.total_sleep_minutes() {
+            description.push(format!("Asleep: {}", duration(minutes)));
+        }
+        if let Some(notes) = &entry.notes {
+            description.push(format!("Notes: {}", notes));
+        }
+        description.push(format!("Source: {}", entry.source_description()));
+
+        push_line(&mut out, "BEGIN:VEVENT");
+        // The uuid keeps the event the same in feeds written from any synced database
+        match &entry.uuid {
+            Some(uuid) => push_line(&mut out, &format!("UID:slog-{}", uuid)),
+            None => push_line(&mut out, &format!("UID:slog-entry-{}", entry.id)),
+        }
+        push_line(&mut out, &format!("DTSTAMP:{}", stamp));
+        for (property, time) in [
+            ("CREATED", &entry.created_at),
+            ("LAST-MODIFIED", &entry.updated_at),
+        ] {
+            if let Some(time) = time
+                .as_deref()
+                .and_then(|time| NaiveDateTime::parse_from_str(time, DATETIME_FORMAT).ok())
+            {
+                push_line(&mut out, &format!("{}:{}", property, to_utc(time)));
+            }
+        }
+        push_line(&mut out, &format!("DTSTART:{}", to_utc(start)));
+        push_line(&mut out, &format!("DTEND:{}", to_utc(end)));
+        push_line(
+            &mut out,
+            &format!(
+                "SUMMARY:{}",
+                escape(&format!("Sleep ({})", entry.quality_name()))
+            ),
+        );
+        push_line(
+            &mut out,
+            &format!("DESCRIPTION:{}", escape(&description.join("\n"))),
+        );
+        push_line(&mut out, "TRANSP:TRANSPARENT");
+        push_line(&mut out, "END:VEVENT");
+    }
+
+    push_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+/// Writes every entry of `profile` to `path` as a calendar feed, replacing the file in one step so a calendar
+/// app reading it never sees half a file.
+pub async fn write_feed(
+    storage: &impl Storage,
+    profile: i64,
+    path: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut entries = storage.recent(profile, None).await?;
+    entries.reverse();
+
+    let partial = path.with_extension("ics.partial");
+    std::fs::write(&partial, render(&entries))
+        .map_err(|e| format!("Failed to write {}: {}", partial.display(), e))?;
+    std::fs::rename(&partial, path)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(())
+}
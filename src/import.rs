@@ -0,0 +1,962 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use chrono::{DateTime, NaiveDateTime, TimeDelta};
+use quick_xml::events::Event;
+
+use crate::entry::{self, DATETIME_FORMAT, Entry};
+use crate::stages::{Span, Stage};
+use crate::storage::Storage;
+use crate::store::{self, Save, StoreError};
+
+/// Apple Health samples further apart than this belong to different nights.
+const APPLE_HEALTH_NIGHT_GAP_HOURS: i64 = 2;
+
+/// A night read from an export.
+pub struct Night {
+    /// The entry to save, with `source` set and id 0
+    pub entry: Entry,
+    /// Sleep stages, when the device measured them
+    pub stages: Vec<Span>,
+}
+
+impl From<Entry> for Night {
+    fn from(entry: Entry) -> Self {
+        Night {
+            entry,
+            stages: Vec::new(),
+        }
+    }
+}
+
+/// An export format `slog import` understands.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Format {
+    /// `export.xml` from the Health app's "Export All Health Data"
+    AppleHealth,
+    /// `sleep-*.json` from a Fitbit account export, or a Web API sleep log response
+    Fitbit,
+    /// Sleep data from Oura as JSON (API or data export) or CSV
+    Oura,
+    /// `sleep-export.csv` from Sleep as Android's backup
+    SleepAsAndroid,
+}
+
+impl Format {
+    /// Value stored in `sleep.source` for entries imported from this format.
+    pub fn source(&self) -> &'static str {
+        match self {
+            Format::AppleHealth => "apple_health",
+            Format::Fitbit => "fitbit",
+            Format::Oura => "oura",
+            Format::SleepAsAndroid => "sleep_as_android",
+        }
+    }
+
+    /// Guesses the format from the first few kilobytes of the file.
+    pub fn detect(path: &Path) -> Result<Format, String> {
+        let mut head = String::new();
+        {
+            use std::io::Read;
+            let file = std::fs::File::open(path)
+                .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+            let mut bytes = Vec::new();
+            file.take(16 * 1024)
+                .read_to_end(&mut bytes)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            head.push_str(&String::from_utf8_lossy(&bytes));
+        }
+        let head = head.trim_start_matches('\u{feff}').trim_start();
+
+        if head.starts_with('<') && head.contains("HealthData") {
+            return Ok(Format::AppleHealth);
+        }
+        if head.starts_with("Id,Tz,From,To") {
+            return Ok(Format::SleepAsAndroid);
+        }
+        if head.starts_with('{') || head.starts_with('[') {
+            if head.contains("\"logId\"") || head.contains("\"minutesAsleep\"") {
+                return Ok(Format::Fitbit);
+            }
+            if head.contains("\"bedtime_start\"") {
+                return Ok(Format::Oura);
+            }
+        }
+        let first_line = head.lines().next().unwrap_or_default().to_lowercase();
+        if first_line.contains("bedtime start") || first_line.contains("bedtime_start") {
+            return Ok(Format::Oura);
+        }
+        Err(format!(
+            "Could not tell the format of {}. Pass --format",
+            path.display()
+        ))
+    }
+
+    /// Reads the nights in `path`, oldest first.
+    pub fn parse(&self, path: &Path) -> Result<Vec<Night>, Box<dyn std::error::Error>> {
+        let mut nights = match self {
+            Format::AppleHealth => apple_health(path)?,
+            Format::Fitbit => fitbit(&read_json(path)?)?,
+            Format::Oura => {
+                let contents = read_to_string(path)?;
+                if contents.trim_start().starts_with(['{', '[']) {
+                    oura_json(&serde_json::from_str(&contents)?)?
+                } else {
+                    oura_csv(&contents)?
+                }
+            }
+            Format::SleepAsAndroid => sleep_as_android(&read_to_string(path)?)?,
+        };
+        for night in &mut nights {
+            night.entry.source = Some(self.source().to_string());
+        }
+        nights.sort_by(|a, b| a.entry.start.cmp(&b.entry.start));
+        Ok(nights)
+    }
+}
+
+/// What to do with an imported night that overlaps an entry from elsewhere (recorded by hand
+/// or imported from another device). Nights overlapping an entry from the same source are
+/// always updated, so importing the same file twice changes nothing.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum MergeRule {
+    /// Keep the existing entry and drop the imported night
+    Skip,
+    /// Take bed times, latency and time awake from the device, keep everything else
+    DeviceTimes,
+    /// Replace the existing entry with the imported night, keeping what the device lacks
+    Replace,
+}
+
+/// What happened to one imported night.
+pub enum Outcome {
+    Inserted(i64),
+    /// An earlier import of the same night was updated
+    Updated(i64),
+    /// Combined with an existing entry using `--merge device-times` or `replace`
+    Merged(i64),
+    Skipped(String),
+}
+
+/// Saves one imported `night` and its stages in one transaction, applying `rule` when it
+/// overlaps entries from elsewhere.
+pub async fn save(
+    storage: &impl Storage,
+    night: &Night,
+    rule: MergeRule,
+) -> Result<Outcome, StoreError> {
+    let (save, outcome): (Save, fn(i64) -> Outcome) =
+        match plan(storage, &night.entry, rule).await? {
+            Plan::Insert => (Save::Insert(night.entry.clone()), Outcome::Inserted),
+            Plan::Update(entry) => (Save::Update(entry), Outcome::Updated),
+            Plan::DeviceTimes(entry) => (Save::Update(entry), Outcome::Merged),
+            Plan::Merge(entry, existing) => (Save::Merge(entry, existing), Outcome::Merged),
+            Plan::Skip(reason) => return Ok(Outcome::Skipped(reason)),
+        };
+    match storage.save_night(&save, &night.stages).await {
+        Ok(id) => Ok(outcome(id)),
+        Err(e @ (StoreError::Overlap(_) | StoreError::Invalid(_))) => {
+            Ok(Outcome::Skipped(e.to_string()))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// What `save` would do with `night`, without writing anything.
+pub async fn preview(
+    storage: &impl Storage,
+    night: &Night,
+    rule: MergeRule,
+) -> Result<Outcome, StoreError> {
+    Ok(match plan(storage, &night.entry, rule).await? {
+        Plan::Insert => Outcome::Inserted(0),
+        Plan::Update(entry) => Outcome::Updated(entry.id),
+        Plan::DeviceTimes(entry) => Outcome::Merged(entry.id),
+        Plan::Merge(entry, _) => Outcome::Merged(entry.id),
+        Plan::Skip(reason) => Outcome::Skipped(reason),
+    })
+}
+
+enum Plan {
+    Insert,
+    /// Refresh an earlier import of the same night
+    Update(Entry),
+    /// Overwrite an existing entry's times with the device's
+    DeviceTimes(Entry),
+    /// The merged entry and the entries it replaces
+    Merge(Entry, Vec<Entry>),
+    Skip(String),
+}
+
+async fn plan(storage: &impl Storage, night: &Entry, rule: MergeRule) -> Result<Plan, StoreError> {
+    let problems = night.problems();
+    if let Some(problem) = problems
+        .iter()
+        .find(|problem| problem.severity == crate::entry::Severity::Invalid)
+    {
+        return Ok(Plan::Skip(problem.message.clone()));
+    }
+
+    let existing = storage.overlapping(night).await?;
+    let Some(first) = existing.first() else {
+        return Ok(Plan::Insert);
+    };
+    let source = night.source.as_deref().unwrap_or_default();
+
+    if let [only] = existing.as_slice() {
+        // Imported before from the same device: refresh it
+        if only.source.as_deref() == Some(source) {
+            let mut updated = night.clone();
+            updated.id = only.id;
+            return Ok(Plan::Update(updated));
+        }
+        // Merged into a manual entry before: refresh the device's part
+        if only
+            .source
+            .as_deref()
+            .is_some_and(|existing| existing.ends_with(&format!("+{}", source)))
+        {
+            return Ok(Plan::Update(with_device_times(only, night)));
+        }
+    }
+
+    Ok(match rule {
+        MergeRule::Skip => Plan::Skip(format!(
+            "overlaps entry #{} ({})",
+            first.id,
+            first.source.as_deref().unwrap_or("manual")
+        )),
+        MergeRule::DeviceTimes if existing.len() > 1 => Plan::Skip(format!(
+            "overlaps {} entries; use --merge replace to combine them",
+            existing.len()
+        )),
+        MergeRule::DeviceTimes => Plan::DeviceTimes(with_device_times(first, night)),
+        MergeRule::Replace => {
+            let mut replaced = store::merged(night, &existing);
+            replaced.start = night.start.clone();
+            replaced.end = night.end.clone();
+            replaced.source = night.source.clone();
+            Plan::Merge(replaced, existing)
+        }
+    })
+}
+
+/// `existing` with the times the device measured from `night`. Ratings, doses, exertion and
+/// notes stay as they were; the source becomes e.g. `manual+fitbit`.
+fn with_device_times(existing: &Entry, night: &Entry) -> Entry {
+    let mut merged = existing.clone();
+    merged.start = night.start.clone();
+    merged.end = night.end.clone();
+    merged.minutes_to_fall_asleep = night
+        .minutes_to_fall_asleep
+        .or(existing.minutes_to_fall_asleep);
+    merged.awake_count = night.awake_count.or(existing.awake_count);
+    merged.time_awake = night.time_awake.or(existing.time_awake);
+    merged.time_in_bed_after_waking = night
+        .time_in_bed_after_waking
+        .or(existing.time_in_bed_after_waking);
+    merged.quality = existing.quality.or(night.quality);
+    merged.notes = existing.notes.clone().or(night.notes.clone());
+
+    let source = night.source.as_deref().unwrap_or_default();
+    let base = existing
+        .source
+        .as_deref()
+        .and_then(|existing| existing.split('+').next())
+        .unwrap_or("manual");
+    merged.source = Some(format!("{}+{}", base, source));
+    merged
+}
+
+/// A night with only its times set; `import_file` assigns the profile.
+fn night(start: NaiveDateTime, end: NaiveDateTime) -> Entry {
+    Entry {
+        id: 0,
+        start: start.format(DATETIME_FORMAT).to_string(),
+        end: Some(end.format(DATETIME_FORMAT).to_string()),
+        minutes_to_fall_asleep: None,
+        awake_count: None,
+        time_awake: None,
+        time_in_bed_after_waking: None,
+        quality: None,
+        melatonin: None,
+        benadryl: None,
+        edible: None,
+        exertion: None,
+        notes: None,
+        source: None,
+        profile_id: 0,
+        uuid: None,
+        created_at: None,
+        updated_at: None,
+        origin: entry::IMPORT.to_string(),
+    }
+}
+
+fn read_to_string(path: &Path) -> Result<String, String> {
+    std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+}
+
+fn read_json(path: &Path) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    serde_json::from_str(&read_to_string(path)?)
+        .map_err(|e| format!("{} is not valid JSON: {}", path.display(), e).into())
+}
+
+/// Parses a time with a UTC offset (RFC 3339 or Apple's `2026-10-10 23:02:11 -0700`) as the
+/// device's local time, dropping the offset, so nights read the same as from Fitbit and Sleep as
+/// Android, which export local times; a time without an offset is taken as it is.
+fn parse_time(value: &str) -> Option<NaiveDateTime> {
+    let value = value.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Some(time.naive_local());
+    }
+    if let Ok(time) = DateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S %z") {
+        return Some(time.naive_local());
+    }
+    [
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%d %H:%M",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+}
+
+fn minutes(delta: TimeDelta) -> i32 {
+    delta.num_minutes() as i32
+}
+
+/// What an Apple Health sleep analysis record says.
+#[derive(Clone, Copy, PartialEq)]
+enum SampleKind {
+    InBed,
+    /// Asleep, with the stage on watches that track stages
+    Asleep(Option<Stage>),
+    Awake,
+}
+
+/// One `HKCategoryTypeIdentifierSleepAnalysis` record.
+struct SleepSample {
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+    kind: SampleKind,
+    device: String,
+}
+
+/// Reads sleep analysis records from Apple Health's `export.xml` and groups them into nights.
+/// When both a phone and a watch tracked the same night, the device with the most samples wins.
+fn apple_health(path: &Path) -> Result<Vec<Night>, Box<dyn std::error::Error>> {
+    let mut reader = quick_xml::Reader::from_file(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut buf = Vec::new();
+    let mut samples = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Eof => break,
+            Event::Empty(e) | Event::Start(e) if e.name().as_ref() == b"Record" => {
+                let attribute = |name: &str| -> Result<Option<String>, quick_xml::Error> {
+                    Ok(match e.try_get_attribute(name)? {
+                        Some(attribute) => Some(attribute.unescape_value()?.into_owned()),
+                        None => None,
+                    })
+                };
+                if attribute("type")?.as_deref() != Some("HKCategoryTypeIdentifierSleepAnalysis") {
+                    continue;
+                }
+                let (Some(start), Some(end), Some(value)) = (
+                    attribute("startDate")?.as_deref().and_then(parse_time),
+                    attribute("endDate")?.as_deref().and_then(parse_time),
+                    attribute("value")?,
+                ) else {
+                    continue;
+                };
+                let kind = match value.trim_start_matches("HKCategoryValueSleepAnalysis") {
+                    "InBed" => SampleKind::InBed,
+                    "Awake" => SampleKind::Awake,
+                    "AsleepCore" => SampleKind::Asleep(Some(Stage::Light)),
+                    "AsleepDeep" => SampleKind::Asleep(Some(Stage::Deep)),
+                    "AsleepREM" => SampleKind::Asleep(Some(Stage::Rem)),
+                    "Asleep" | "AsleepUnspecified" => SampleKind::Asleep(None),
+                    _ => continue,
+                };
+                samples.push(SleepSample {
+                    start,
+                    end,
+                    kind,
+                    device: attribute("sourceName")?.unwrap_or_default(),
+                });
+            }
+            _ => {}
+        }
+        buf.clear();
+    }
+    samples.sort_by_key(|sample| sample.start);
+
+    let mut clusters: Vec<Vec<SleepSample>> = Vec::new();
+    for sample in samples {
+        match clusters.last_mut() {
+            Some(cluster)
+                if sample.start
+                    <= cluster.iter().map(|s| s.end).max().unwrap_or(sample.start)
+                        + TimeDelta::hours(APPLE_HEALTH_NIGHT_GAP_HOURS) =>
+            {
+                cluster.push(sample)
+            }
+            _ => clusters.push(vec![sample]),
+        }
+    }
+
+    Ok(clusters
+        .into_iter()
+        .filter_map(|cluster| {
+            let mut per_device: BTreeMap<&str, Vec<&SleepSample>> = BTreeMap::new();
+            for sample in &cluster {
+                per_device.entry(&sample.device).or_default().push(sample);
+            }
+            let samples = per_device
+                .into_values()
+                .max_by_key(|samples| samples.len())?;
+            apple_health_night(&samples)
+        })
+        .collect())
+}
+
+fn apple_health_night(samples: &[&SleepSample]) -> Option<Night> {
+    let start = samples.iter().map(|sample| sample.start).min()?;
+    let end = samples.iter().map(|sample| sample.end).max()?;
+    let mut entry = night(start, end);
+
+    // Asleep samples (sorted by start) joined where they touch or overlap
+    let mut asleep: Vec<(NaiveDateTime, NaiveDateTime)> = Vec::new();
+    for sample in samples
+        .iter()
+        .filter(|sample| matches!(sample.kind, SampleKind::Asleep(_)))
+    {
+        match asleep.last_mut() {
+            Some(last) if sample.start <= last.1 => last.1 = last.1.max(sample.end),
+            _ => asleep.push((sample.start, sample.end)),
+        }
+    }
+    let (Some(first), Some(last)) = (asleep.first(), asleep.last()) else {
+        // Only "in bed" samples, from a phone without sleep tracking
+        return Some(entry.into());
+    };
+
+    let gaps: Vec<TimeDelta> = asleep
+        .windows(2)
+        .map(|pair| pair[1].0 - pair[0].1)
+        .filter(|gap| gap.num_minutes() >= 1)
+        .collect();
+    entry.minutes_to_fall_asleep = Some(minutes(first.0 - start));
+    entry.awake_count = Some(gaps.len() as i16);
+    entry.time_awake = Some(gaps.iter().map(|gap| minutes(*gap)).sum());
+    entry.time_in_bed_after_waking = Some(minutes(end - last.1));
+
+    // Awake samples only mean something next to staged ones
+    let staged = samples
+        .iter()
+        .any(|sample| matches!(sample.kind, SampleKind::Asleep(Some(_))));
+    let stages = samples
+        .iter()
+        .filter(|_| staged)
+        .filter_map(|sample| {
+            let stage = match sample.kind {
+                SampleKind::Asleep(stage) => stage?,
+                SampleKind::Awake => Stage::Awake,
+                SampleKind::InBed => return None,
+            };
+            Some(Span {
+                stage,
+                start: sample.start,
+                end: sample.end,
+            })
+        })
+        .collect();
+    Some(Night { entry, stages })
+}
+
+/// Reads main sleeps from Fitbit: an array of sleep logs (account export) or an object with a
+/// `sleep` array (Web API). Naps are left out.
+fn fitbit(json: &serde_json::Value) -> Result<Vec<Night>, String> {
+    let logs = json
+        .get("sleep")
+        .unwrap_or(json)
+        .as_array()
+        .ok_or("Expected a list of Fitbit sleep logs")?;
+
+    let mut nights = Vec::new();
+    for log in logs {
+        if log.get("mainSleep").and_then(|main| main.as_bool()) == Some(false) {
+            continue;
+        }
+        let time = |name: &str| log.get(name).and_then(|v| v.as_str()).and_then(parse_time);
+        let (Some(start), Some(end)) = (time("startTime"), time("endTime")) else {
+            return Err(format!(
+                "Fitbit sleep log without startTime/endTime: {}",
+                log
+            ));
+        };
+        let number = |name: &str| log.get(name).and_then(|v| v.as_i64()).map(|v| v as i32);
+
+        let mut entry = night(start, end);
+        entry.minutes_to_fall_asleep = number("minutesToFallAsleep");
+        entry.time_awake = number("minutesAwake");
+        entry.time_in_bed_after_waking = number("minutesAfterWakeup");
+        // "wake" for logs with sleep stages, "awake" for classic logs
+        entry.awake_count = ["wake", "awake"].iter().find_map(|level| {
+            log.pointer(&format!("/levels/summary/{}/count", level))
+                .and_then(|count| count.as_i64())
+                .map(|count| count as i16)
+        });
+
+        // Only logs of type "stages" have stages; classic ones say asleep/restless/awake
+        let stages = log
+            .pointer("/levels/data")
+            .and_then(|data| data.as_array())
+            .filter(|_| log.get("type").and_then(|kind| kind.as_str()) == Some("stages"))
+            .map(|data| data.iter().filter_map(fitbit_span).collect())
+            .unwrap_or_default();
+        nights.push(Night { entry, stages });
+    }
+    Ok(nights)
+}
+
+/// One `{"dateTime", "level", "seconds"}` item of a Fitbit stages log.
+fn fitbit_span(item: &serde_json::Value) -> Option<Span> {
+    let start = parse_time(item.get("dateTime")?.as_str()?)?;
+    let stage = match item.get("level")?.as_str()? {
+        "wake" => Stage::Awake,
+        "rem" => Stage::Rem,
+        "light" => Stage::Light,
+        "deep" => Stage::Deep,
+        _ => return None,
+    };
+    Some(Span {
+        stage,
+        start,
+        end: start + TimeDelta::seconds(item.get("seconds")?.as_i64()?),
+    })
+}
+
+/// Spans from Oura's five-minute hypnogram string (1 deep, 2 light, 3 REM, 4 awake),
+/// starting at `start`.
+fn oura_spans(hypnogram: &str, start: NaiveDateTime) -> Vec<Span> {
+    let mut spans: Vec<Span> = Vec::new();
+    for (i, phase) in hypnogram.chars().enumerate() {
+        let stage = match phase {
+            '1' => Stage::Deep,
+            '2' => Stage::Light,
+            '3' => Stage::Rem,
+            '4' => Stage::Awake,
+            _ => continue,
+        };
+        let from = start + TimeDelta::minutes(5 * i as i64);
+        let to = from + TimeDelta::minutes(5);
+        match spans.last_mut() {
+            Some(last) if last.stage == stage && last.end == from => last.end = to,
+            _ => spans.push(Span {
+                stage,
+                start: from,
+                end: to,
+            }),
+        }
+    }
+    spans
+}
+
+fn seconds_to_minutes(seconds: f64) -> i32 {
+    (seconds / 60.0).round() as i32
+}
+
+/// Reads long sleeps from Oura JSON: API v2 (`data` with `type`), the older export (`sleep`
+/// with `is_longest`) or a bare array. Naps and rest periods are left out.
+fn oura_json(json: &serde_json::Value) -> Result<Vec<Night>, String> {
+    let periods = json
+        .get("data")
+        .or_else(|| json.get("sleep"))
+        .unwrap_or(json)
+        .as_array()
+        .ok_or("Expected a list of Oura sleep periods")?;
+
+    let mut nights = Vec::new();
+    for period in periods {
+        let kind = period.get("type").and_then(|kind| kind.as_str());
+        if kind.is_some_and(|kind| kind != "long_sleep")
+            || period.get("is_longest").and_then(|v| v.as_i64()) == Some(0)
+        {
+            continue;
+        }
+        let time = |name: &str| {
+            period
+                .get(name)
+                .and_then(|v| v.as_str())
+                .and_then(parse_time)
+        };
+        let (Some(start), Some(end)) = (time("bedtime_start"), time("bedtime_end")) else {
+            return Err(format!(
+                "Oura sleep period without bedtime_start/bedtime_end: {}",
+                period
+            ));
+        };
+        let seconds = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| period.get(*name).and_then(|v| v.as_f64()))
+                .map(seconds_to_minutes)
+        };
+
+        let mut entry = night(start, end);
+        entry.minutes_to_fall_asleep = seconds(&["latency", "onset_latency"]);
+        entry.time_awake = seconds(&["awake_time", "awake"]);
+
+        // `sleep_phase_5_min` in API v2, `hypnogram_5min` in the older export
+        let stages = ["sleep_phase_5_min", "hypnogram_5min"]
+            .iter()
+            .find_map(|name| period.get(*name).and_then(|v| v.as_str()))
+            .map(|hypnogram| oura_spans(hypnogram, start))
+            .unwrap_or_default();
+        nights.push(Night { entry, stages });
+    }
+    Ok(nights)
+}
+
+/// Reads Oura CSV with one night per row. Headers are matched loosely, so both
+/// `Bedtime Start` and `bedtime_start` work; durations are in seconds.
+fn oura_csv(contents: &str) -> Result<Vec<Night>, Box<dyn std::error::Error>> {
+    let mut reader = csv::Reader::from_reader(contents.as_bytes());
+    let headers: Vec<String> = reader
+        .headers()?
+        .iter()
+        .map(|header| header.trim().to_lowercase().replace([' ', '-'], "_"))
+        .collect();
+    let column = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|name| headers.iter().position(|header| header == name))
+    };
+    let (Some(start_column), Some(end_column)) =
+        (column(&["bedtime_start"]), column(&["bedtime_end"]))
+    else {
+        return Err("Oura CSV needs Bedtime Start and Bedtime End columns".into());
+    };
+    let latency_column = column(&["latency", "sleep_latency", "onset_latency"]);
+    let awake_column = column(&["awake_time", "awake"]);
+
+    let mut nights = Vec::new();
+    for (i, record) in reader.records().enumerate() {
+        let record = record?;
+        let time = |column: usize| record.get(column).and_then(parse_time);
+        let (Some(start), Some(end)) = (time(start_column), time(end_column)) else {
+            return Err(format!("Row {}: bedtime start or end is not a valid time", i + 2).into());
+        };
+        let seconds = |column: Option<usize>| {
+            column
+                .and_then(|column| record.get(column))
+                .and_then(|value| value.trim().parse::<f64>().ok())
+                .map(seconds_to_minutes)
+        };
+
+        let mut entry = night(start, end);
+        entry.minutes_to_fall_asleep = seconds(latency_column);
+        entry.time_awake = seconds(awake_column);
+        nights.push(entry.into());
+    }
+    Ok(nights)
+}
+
+/// Maps Sleep as Android's 0-5 star rating onto quality -2..2.
+fn rating_to_quality(rating: f64) -> Option<i8> {
+    (rating > 0.0).then(|| (rating.round().clamp(1.0, 5.0) as i8) - 3)
+}
+
+/// Reads Sleep as Android's `sleep-export.csv`, where each night is a header row followed by a
+/// data row (the columns after `Comment` differ per night). Times are in the phone's time zone.
+fn sleep_as_android(contents: &str) -> Result<Vec<Night>, Box<dyn std::error::Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(contents.as_bytes());
+
+    let mut nights = Vec::new();
+    let mut headers: Option<csv::StringRecord> = None;
+    for record in reader.records() {
+        let record = record?;
+        if record.get(0) == Some("Id") {
+            headers = Some(record);
+            continue;
+        }
+        let Some(headers) = &headers else {
+            return Err("Sleep as Android export must start with an Id,Tz,From,To header".into());
+        };
+        let field = |name: &str| {
+            headers
+                .iter()
+                .position(|header| header == name)
+                .and_then(|column| record.get(column))
+                .map(str::trim)
+        };
+        let time = |name: &str| {
+            field(name)
+                .and_then(|value| NaiveDateTime::parse_from_str(value, "%d. %m. %Y %H:%M").ok())
+        };
+        let (Some(start), Some(end)) = (time("From"), time("To")) else {
+            return Err(format!(
+                "Sleep as Android night {} has no valid From/To",
+                field("Id").unwrap_or("?")
+            )
+            .into());
+        };
+
+        let mut entry = night(start, end);
+        entry.quality = field("Rating")
+            .and_then(|rating| rating.parse::<f64>().ok())
+            .and_then(rating_to_quality);
+        // Minutes awake the app took off the length of the night
+        entry.time_awake = field("LenAdjust")
+            .and_then(|adjust| adjust.parse::<f64>().ok())
+            .map(|adjust| adjust.abs().round() as i32);
+        entry.notes = field("Comment")
+            .filter(|comment| !comment.is_empty())
+            .map(str::to_string);
+        nights.push(entry.into());
+    }
+    Ok(nights)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The nights in `tests/fixtures/import/{name}`, checking it is taken for `format`.
+    fn fixture(name: &str, format: Format) -> Vec<Night> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/import")
+            .join(name);
+        assert_eq!(Format::detect(&path).unwrap(), format, "{}", name);
+        let nights = format.parse(&path).unwrap();
+        for night in &nights {
+            assert_eq!(night.entry.source.as_deref(), Some(format.source()));
+        }
+        nights
+    }
+
+    /// Start, end, latency and time awake.
+    fn times(night: &Night) -> (&str, &str, Option<i32>, Option<i32>) {
+        (
+            &night.entry.start,
+            night.entry.end.as_deref().unwrap_or_default(),
+            night.entry.minutes_to_fall_asleep,
+            night.entry.time_awake,
+        )
+    }
+
+    fn spans(night: &Night) -> Vec<(Stage, String, String)> {
+        night
+            .stages
+            .iter()
+            .map(|span| {
+                (
+                    span.stage,
+                    span.start.format(DATETIME_FORMAT).to_string(),
+                    span.end.format(DATETIME_FORMAT).to_string(),
+                )
+            })
+            .collect()
+    }
+
+    fn span(stage: Stage, start: &str, end: &str) -> (Stage, String, String) {
+        (stage, start.to_string(), end.to_string())
+    }
+
+    #[test]
+    fn apple_health_export() {
+        // The watch has more samples than the phone the first night, and its stages
+        let nights = fixture("apple_health_export.xml", Format::AppleHealth);
+        let [watch, phone] = &nights[..] else {
+            panic!("expected 2 nights, got {}", nights.len())
+        };
+        assert_eq!(
+            times(watch),
+            (
+                "2026-10-10 22:50:00",
+                "2026-10-11 06:40:00",
+                Some(15),
+                Some(15)
+            )
+        );
+        assert_eq!(watch.entry.awake_count, Some(1));
+        assert_eq!(watch.entry.time_in_bed_after_waking, Some(20));
+        assert_eq!(
+            spans(watch),
+            vec![
+                span(Stage::Light, "2026-10-10 23:05:00", "2026-10-11 02:10:00"),
+                span(Stage::Awake, "2026-10-11 02:10:00", "2026-10-11 02:25:00"),
+                span(Stage::Deep, "2026-10-11 02:25:00", "2026-10-11 04:00:00"),
+                span(Stage::Rem, "2026-10-11 04:00:00", "2026-10-11 06:20:00"),
+                span(Stage::Awake, "2026-10-11 06:20:00", "2026-10-11 06:40:00"),
+            ]
+        );
+
+        // Only "in bed" from the phone
+        assert_eq!(
+            times(phone),
+            ("2026-10-11 23:30:00", "2026-10-12 07:05:00", None, None)
+        );
+        assert!(phone.stages.is_empty());
+    }
+
+    #[test]
+    fn fitbit_sleep() {
+        // The nap is left out
+        let nights = fixture("fitbit_sleep.json", Format::Fitbit);
+        let [staged, classic] = &nights[..] else {
+            panic!("expected 2 nights, got {}", nights.len())
+        };
+        assert_eq!(
+            times(staged),
+            (
+                "2026-10-10 23:02:30",
+                "2026-10-11 06:45:00",
+                Some(12),
+                Some(50)
+            )
+        );
+        assert_eq!(staged.entry.awake_count, Some(26));
+        assert_eq!(staged.entry.time_in_bed_after_waking, Some(8));
+        let stages = spans(staged);
+        assert_eq!(stages.len(), 18);
+        assert_eq!(
+            stages[0],
+            span(Stage::Awake, "2026-10-10 23:02:30", "2026-10-10 23:14:30")
+        );
+        assert_eq!(
+            stages[17],
+            span(Stage::Awake, "2026-10-11 06:25:30", "2026-10-11 06:45:00")
+        );
+
+        assert_eq!(
+            times(classic),
+            (
+                "2026-10-11 23:40:00",
+                "2026-10-12 07:10:00",
+                Some(5),
+                Some(33)
+            )
+        );
+        assert_eq!(classic.entry.awake_count, Some(3));
+        assert!(classic.stages.is_empty());
+    }
+
+    #[test]
+    fn oura_sleep_json() {
+        // The nap is left out, and the -07:00 times stay as the ring recorded them
+        let nights = fixture("oura_sleep.json", Format::Oura);
+        let [first, second] = &nights[..] else {
+            panic!("expected 2 nights, got {}", nights.len())
+        };
+        assert_eq!(
+            times(first),
+            (
+                "2026-10-10 23:11:04",
+                "2026-10-11 06:58:34",
+                Some(16),
+                Some(41)
+            )
+        );
+        let stages = spans(first);
+        assert_eq!(stages.len(), 20);
+        assert_eq!(
+            stages[0],
+            span(Stage::Awake, "2026-10-10 23:11:04", "2026-10-10 23:26:04")
+        );
+        assert_eq!(
+            stages[1],
+            span(Stage::Light, "2026-10-10 23:26:04", "2026-10-10 23:56:04")
+        );
+        assert_eq!(
+            stages[19],
+            span(Stage::Awake, "2026-10-11 06:51:04", "2026-10-11 06:56:04")
+        );
+
+        assert_eq!(
+            times(second),
+            (
+                "2026-10-11 22:48:12",
+                "2026-10-12 06:31:42",
+                Some(9),
+                Some(31)
+            )
+        );
+        assert!(second.stages.is_empty());
+    }
+
+    #[test]
+    fn oura_sleep_csv() {
+        let nights = fixture("oura_sleep.csv", Format::Oura);
+        assert_eq!(
+            nights.iter().map(times).collect::<Vec<_>>(),
+            vec![
+                (
+                    "2026-10-10 23:11:04",
+                    "2026-10-11 06:58:34",
+                    Some(16),
+                    Some(41)
+                ),
+                (
+                    "2026-10-11 22:48:12",
+                    "2026-10-12 06:31:42",
+                    Some(9),
+                    Some(31)
+                ),
+            ]
+        );
+        assert!(nights.iter().all(|night| night.stages.is_empty()));
+    }
+
+    #[test]
+    fn sleep_as_android_export() {
+        let nights = fixture("sleep_as_android.csv", Format::SleepAsAndroid);
+        let [first, second] = &nights[..] else {
+            panic!("expected 2 nights, got {}", nights.len())
+        };
+        assert_eq!(
+            times(first),
+            ("2026-10-10 23:12:00", "2026-10-11 06:48:00", None, Some(18))
+        );
+        assert_eq!(first.entry.quality, Some(1));
+        assert_eq!(first.entry.notes.as_deref(), Some("#home woke up once"));
+        assert_eq!(
+            times(second),
+            ("2026-10-11 23:41:00", "2026-10-12 07:02:00", None, Some(0))
+        );
+        assert_eq!(second.entry.quality, Some(0));
+        assert_eq!(second.entry.notes, None);
+        assert!(nights.iter().all(|night| night.stages.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn saves_the_stages_with_the_night() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = crate::config::Config {
+            db_file_path: dir.path().join("slog.db").display().to_string(),
+            ..Default::default()
+        };
+        let storage =
+            crate::storage::SqliteStorage::new(crate::db::connect(&config).await.unwrap());
+        let mut night = fixture("fitbit_sleep.json", Format::Fitbit).remove(0);
+        night.entry.profile_id = 1;
+
+        let Outcome::Inserted(id) = save(&storage, &night, MergeRule::Skip).await.unwrap() else {
+            panic!("not inserted")
+        };
+        assert_eq!(storage.stages(id).await.unwrap().len(), 18);
+
+        // Importing again refreshes the entry and replaces its stages
+        night.stages.truncate(5);
+        let Outcome::Updated(again) = save(&storage, &night, MergeRule::Skip).await.unwrap() else {
+            panic!("not updated")
+        };
+        assert_eq!(again, id);
+        assert_eq!(storage.stages(id).await.unwrap().len(), 5);
+    }
+}
@@ -1,22 +1,33 @@
+mod backup;
 mod config;
+mod db;
+mod digest;
+mod doctor;
+mod entry;
+mod files;
+mod goals;
+mod history;
+mod ics;
+mod import;
+mod markers;
+mod postgres;
+mod profiles;
+mod remind;
+mod show;
+mod stages;
+mod storage;
+mod store;
+mod sync;
+mod time_defaults;
+mod tui;
 // mod sheets;
 
-use std::fmt;
-use std::str::FromStr;
+use std::path::{Path, PathBuf};
 
-use tokio::runtime::Runtime;
+use chrono::{Datelike, Days, Local, NaiveDate, NaiveDateTime, NaiveTime};
 
-use sqlx::ConnectOptions;
-use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
-
-use chrono::{Datelike, Days, Local, NaiveDate, NaiveDateTime, Utc};
-use std::fmt::{Display, Formatter};
-
-use inquire::formatter::CustomTypeFormatter;
 use inquire::{
-    Confirm, CustomType, DateSelect, MultiSelect, Select, Text,
-    error::{CustomUserError, InquireResult},
-    required,
+    Confirm, CustomType, DateSelect, MultiSelect, Select, Text, error::InquireResult,
     validator::Validation,
 };
 
@@ -25,7 +36,14 @@ use strum::IntoEnumIterator;
 
 use clap::{Parser, Subcommand};
 
-use config::Config;
+use config::{Config, DbKey};
+use digest::{Period, PeriodKind};
+use entry::{Entry, Severity};
+use goals::Goal;
+use markers::MarkerKind;
+use storage::Storage;
+use store::StoreError;
+use time_defaults::DefaultTimes;
 // use sheets::SheetsExporter;
 
 #[derive(Debug, EnumIter, strum_macros::Display)]
@@ -47,6 +65,10 @@ impl Quality {
             Quality::Perfection => 2,
         }
     }
+
+    fn from_db_value(value: i8) -> Option<Self> {
+        Quality::iter().find(|quality| quality.db_value() == value)
+    }
 }
 
 #[derive(Debug, EnumIter, strum_macros::Display)]
@@ -64,6 +86,10 @@ impl Exertion {
             Exertion::Exhausted => 1,
         }
     }
+
+    fn from_db_value(value: i8) -> Option<Self> {
+        Exertion::iter().find(|exertion| exertion.db_value() == value)
+    }
 }
 
 // Validates time format HH:MM
@@ -113,6 +139,24 @@ fn normalize_time_to_hhmm(time: &str) -> String {
 #[command(name = "slog")]
 #[command(about = "CLI Sleep Log - Track your sleep data", long_about = None)]
 struct Cli {
+    /// Config file to use instead of $XDG_CONFIG_HOME/slog/config.yml
+    #[arg(long, global = true, env = "SLOG_CONFIG", value_name = "PATH")]
+    config: Option<PathBuf>,
+    /// Database file to use instead of `db_file_path` from the config
+    #[arg(long, global = true, env = "SLOG_DB", value_name = "PATH")]
+    db: Option<PathBuf>,
+    /// Postgres or flat-file storage to use instead of `db_url` from the config
+    #[arg(
+        long,
+        global = true,
+        env = "SLOG_DB_URL",
+        value_name = "URL",
+        conflicts_with = "db"
+    )]
+    db_url: Option<String>,
+    /// Profile to use instead of `default_profile` from the config
+    #[arg(long, global = true, env = "SLOG_PROFILE", value_name = "NAME")]
+    profile: Option<String>,
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -120,289 +164,2204 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Record new sleep data
-    Record,
-    /// Delete an entry by date
-    Delete,
+    Record {
+        /// Start from the most recent earlier entry and only edit the fields you pick
+        #[arg(long, conflicts_with = "like")]
+        like_last: bool,
+        /// Start from the entry recorded on this date (YYYY-MM-DD)
+        #[arg(long, value_name = "DATE")]
+        like: Option<NaiveDate>,
+    },
+    /// Delete entries by date, id or date range
+    Delete {
+        /// Delete the entry with this id
+        #[arg(long, conflicts_with_all = ["date", "from", "to"])]
+        id: Option<i64>,
+        /// Delete the entries starting on this date (YYYY-MM-DD)
+        #[arg(long, conflicts_with_all = ["from", "to"])]
+        date: Option<NaiveDate>,
+        /// Delete entries starting on or after this date (YYYY-MM-DD)
+        #[arg(long)]
+        from: Option<NaiveDate>,
+        /// Delete entries starting on or before this date (YYYY-MM-DD)
+        #[arg(long)]
+        to: Option<NaiveDate>,
+        /// Don't ask for confirmation
+        #[arg(long, short)]
+        yes: bool,
+    },
+    /// Show everything recorded for a night, compared to the 30 nights before it
+    Show {
+        /// `last` (default), an entry id or the date the night started (YYYY-MM-DD)
+        #[arg(value_parser = show::parse_target)]
+        target: Option<show::Target>,
+        /// Print JSON (a list of entries) instead of text
+        #[arg(long)]
+        json: bool,
+    },
     /// List recent sleep entries
     List {
         /// Number of entries to display (default: 10)
         count: Option<usize>,
     },
-    /// Edit configuration settings
+    /// View and edit configuration settings
+    #[command(args_conflicts_with_subcommands = true)]
     Config {
-        /// Configuration field to edit (start_time_default, end_time_default, db_file_path, google_sheets_id, google_credentials_path)
-        field: String,
+        #[command(subcommand)]
+        action: Option<ConfigCommand>,
+        /// Configuration field to edit (shorthand for `slog config set FIELD VALUE`)
+        field: Option<String>,
         /// New value for the field
-        value: String,
+        value: Option<String>,
     },
     /// Show current configuration
     ShowConfig,
+    /// Check all entries for invalid or suspicious data, overlaps and missing nights
+    Doctor {
+        /// Apply mechanical repairs (day rollover on end, negative values, out of range ratings)
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Revert the most recent change (repeat to go further back)
+    Undo,
+    /// Show the change history of entries
+    History {
+        /// Number of changes to display (default: 20)
+        count: Option<usize>,
+        /// Only show changes to the entry on this date (YYYY-MM-DD)
+        #[arg(long)]
+        date: Option<NaiveDate>,
+    },
+    /// Bring back a deleted entry
+    RestoreEntry {
+        /// Id of the deleted entry (see `slog history`)
+        id: i64,
+    },
+    /// Back up the database (default: into the backups directory next to it)
+    Backup {
+        /// File to write the backup to
+        dest: Option<PathBuf>,
+    },
+    /// Replace the database with a backup
+    Restore {
+        /// Backup file to restore
+        file: PathBuf,
+        /// Don't ask for confirmation
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Browse, filter, edit and delete entries in a full-screen view
+    Tui,
+    /// Show how each goal from the config is going: streaks and weekly hit rates
+    Goals {
+        /// Number of recent weeks to show hit rates for (default: 8)
+        #[arg(long, default_value_t = 8)]
+        weeks: usize,
+    },
+    /// Walk through recent nights without an entry and record or mark each one
+    Backfill {
+        /// How far back to look: a number of days or weeks (14d, 2w) or a date (YYYY-MM-DD)
+        #[arg(long, default_value = "14d", value_parser = parse_since)]
+        since: NaiveDate,
+    },
+    /// Summarize a week or month of sleep
+    #[command(group(clap::ArgGroup::new("period").args(["week", "month"])))]
+    Digest {
+        /// Summarize the week (Monday to Sunday)
+        #[arg(long)]
+        week: bool,
+        /// Summarize the calendar month
+        #[arg(long)]
+        month: bool,
+        /// A night in the period to summarize (default: last night)
+        #[arg(long, value_name = "DATE")]
+        date: Option<NaiveDate>,
+        /// Output format
+        #[arg(long, value_enum, default_value_t = DigestFormat::Text)]
+        format: DigestFormat,
+    },
+    /// Notify if last night hasn't been logged yet
+    #[command(args_conflicts_with_subcommands = true)]
+    Remind {
+        #[command(subcommand)]
+        action: Option<RemindCommand>,
+        /// Print the reminder instead of sending a desktop notification (for cron)
+        #[arg(long)]
+        stdout: bool,
+    },
+    /// Import nights from Apple Health, Fitbit, Oura or Sleep as Android exports
+    Import {
+        /// Export file to read
+        file: PathBuf,
+        /// Format of the file (default: guessed from its contents)
+        #[arg(long, value_enum)]
+        format: Option<import::Format>,
+        /// What to do with nights that overlap an entry recorded by hand or by another device
+        #[arg(long, value_enum, default_value_t = import::MergeRule::Skip)]
+        merge: import::MergeRule,
+        /// Show what would be imported without changing the database
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Export entries, e.g. as a calendar
+    Export {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = ExportFormat::Ics)]
+        format: ExportFormat,
+        /// File to write (default: standard output)
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Exchange entries with another slog database or a directory of change files
+    ///
+    /// Only entries are exchanged. Sleep stages and night markers stay in the database that
+    /// recorded them, so when a duplicate night is settled, the version kept has stages only in
+    /// its own database.
+    Sync {
+        /// Another slog database, or a directory (e.g. in a git repository or a Syncthing folder)
+        target: PathBuf,
+        /// How to settle entries changed on both sides, or different entries for the same night
+        #[arg(long, value_enum, default_value_t = sync::Resolution::Newest)]
+        conflicts: sync::Resolution,
+        /// Show what would be exchanged without changing either side
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// List, add, rename and remove profiles (one per person logging in this database)
+    Profile {
+        #[command(subcommand)]
+        action: Option<ProfileCommand>,
+    },
+    /// Inspect and maintain the database
+    Db {
+        #[command(subcommand)]
+        action: DbCommand,
+    },
     // /// Export all data to Google Sheets
     // Export,
 }
 
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Print the value of a field
+    Get {
+        /// Configuration field
+        field: String,
+    },
+    /// Set a field
+    Set {
+        /// Configuration field
+        field: String,
+        /// New value for the field
+        value: String,
+    },
+    /// Reset a field to its default (optional fields become unset)
+    Unset {
+        /// Configuration field
+        field: String,
+    },
+    /// List all fields and their values
+    List,
+    /// Open the config file in $VISUAL / $EDITOR and validate it afterwards
+    Edit,
+    /// Check every field for problems
+    Validate,
+    /// Print the config file path
+    Path,
+}
+
+#[derive(Subcommand)]
+enum ProfileCommand {
+    /// List profiles and how many entries each has
+    List,
+    /// Create a profile
+    Add {
+        /// Name of the new profile
+        name: String,
+    },
+    /// Rename a profile
+    Rename {
+        /// Current name
+        name: String,
+        /// New name
+        new_name: String,
+    },
+    /// Remove a profile without entries
+    Remove {
+        /// Name of the profile
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum DbCommand {
+    /// Show applied and pending migrations
+    Status,
+    /// Apply pending migrations
+    Migrate,
+    /// Rebuild the database file to reclaim unused space
+    Vacuum,
+    /// Check the database file for corruption
+    IntegrityCheck,
+    /// Print the database file path
+    Path,
+    /// Encrypt the database; asks for a new passphrase unless --key-file or --key-env gives one
+    Encrypt {
+        /// Take the passphrase from the first line of this file from now on (db_key: file)
+        #[arg(long, conflicts_with = "key_env")]
+        key_file: Option<PathBuf>,
+        /// Take the passphrase from SLOG_DB_KEY from now on (db_key: env)
+        #[arg(long)]
+        key_env: bool,
+    },
+    /// Decrypt the database, so it opens without a passphrase again
+    Decrypt,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum DigestFormat {
+    Text,
+    Markdown,
+    Json,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum ExportFormat {
+    /// iCalendar, one event per night
+    Ics,
+}
+
+#[derive(Subcommand)]
+enum RemindCommand {
+    /// Write a systemd user timer that runs `slog remind` daily at `reminder_time`
+    Install,
+}
+
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cli = Cli::parse();
+async fn main() {
+    if let Err(e) = run(Cli::parse()).await {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
 
+async fn run(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let config_path = match cli.config {
+        Some(path) => path,
+        None => Config::default_path()?,
+    };
+    // Config for the commands that use the database, with `--db` and the profile's overrides
+    // applied
+    let load_config = || -> Result<Config, Box<dyn std::error::Error>> {
+        let mut config = Config::load(&config_path)?;
+        if let Some(db) = &cli.db {
+            config.db_file_path = db.to_string_lossy().to_string();
+            config.db_url = None;
+        }
+        if let Some(url) = &cli.db_url {
+            storage::Location::parse(url)?;
+            config.db_url = Some(url.clone());
+        }
+        let profile = cli
+            .profile
+            .clone()
+            .unwrap_or_else(|| config.default_profile.clone());
+        Ok(config.for_profile(&profile)?)
+    };
+    // Commands that can change entries, after which the calendar feed is rewritten
+    let changes_entries = matches!(
+        &cli.command,
+        None | Some(
+            Commands::Record { .. }
+                | Commands::Delete { .. }
+                | Commands::Doctor { fix: true }
+                | Commands::Undo
+                | Commands::RestoreEntry { .. }
+                | Commands::Restore { .. }
+                | Commands::Tui
+                | Commands::Backfill { .. }
+                | Commands::Import { dry_run: false, .. }
+                | Commands::Sync { dry_run: false, .. }
+        )
+    );
     match cli.command {
-        Some(Commands::Config { field, value }) => {
-            edit_config(field, value)?;
+        Some(Commands::Config {
+            action,
+            field,
+            value,
+        }) => {
+            let action = match (action, field, value) {
+                (Some(action), _, _) => action,
+                (None, Some(field), Some(value)) => ConfigCommand::Set { field, value },
+                (None, Some(field), None) => ConfigCommand::Get { field },
+                (None, None, _) => ConfigCommand::List,
+            };
+            config_command(&config_path, action, cli.profile.as_deref())?;
         }
         Some(Commands::ShowConfig) => {
-            show_config()?;
+            show_config(&config_path, cli.profile.as_deref())?;
         }
-        Some(Commands::Delete) => {
-            delete_entry().await?;
+        Some(Commands::Doctor { fix }) => {
+            run_doctor(&load_config()?, fix).await?;
+        }
+        Some(Commands::Undo) => {
+            undo(&load_config()?).await?;
+        }
+        Some(Commands::History { count, date }) => {
+            show_history(&load_config()?, date, count.unwrap_or(20)).await?;
+        }
+        Some(Commands::RestoreEntry { id }) => {
+            restore_entry(&load_config()?, id).await?;
+        }
+        Some(Commands::Backup { dest }) => {
+            backup_database(&load_config()?, dest).await?;
+        }
+        Some(Commands::Restore { file, yes }) => {
+            restore_database(&load_config()?, &file, yes).await?;
+        }
+        Some(Commands::Tui) => {
+            tui::browse(&load_config()?).await?;
+        }
+        Some(Commands::Goals { weeks }) => {
+            show_goals(&load_config()?, weeks).await?;
+        }
+        Some(Commands::Backfill { since }) => {
+            backfill(&load_config()?, since).await?;
+        }
+        Some(Commands::Digest {
+            week: _,
+            month,
+            date,
+            format,
+        }) => {
+            let kind = if month {
+                PeriodKind::Month
+            } else {
+                PeriodKind::Week
+            };
+            show_digest(&load_config()?, kind, date, format).await?;
+        }
+        Some(Commands::Remind { action, stdout }) => match action {
+            Some(RemindCommand::Install) => {
+                install_reminder(
+                    &load_config()?,
+                    &config_path,
+                    cli.db.as_deref(),
+                    cli.profile.as_deref(),
+                )?;
+            }
+            None => remind(&load_config()?, stdout).await?,
+        },
+        Some(Commands::Import {
+            file,
+            format,
+            merge,
+            dry_run,
+        }) => {
+            import_file(&load_config()?, &file, format, merge, dry_run).await?;
+        }
+        Some(Commands::Export { format, output }) => {
+            export(&load_config()?, format, output.as_deref()).await?;
+        }
+        Some(Commands::Sync {
+            target,
+            conflicts,
+            dry_run,
+        }) => {
+            sync_with(&load_config()?, &target, conflicts, dry_run).await?;
+        }
+        Some(Commands::Profile { action }) => {
+            profile_command(
+                &load_config()?,
+                &config_path,
+                action.unwrap_or(ProfileCommand::List),
+            )
+            .await?;
+        }
+        Some(Commands::Db { action }) => {
+            db_command(&load_config()?, &config_path, action).await?;
+        }
+        Some(Commands::Delete {
+            id,
+            date,
+            from,
+            to,
+            yes,
+        }) => {
+            let target = match (id, date, from, to) {
+                (Some(id), _, _, _) => DeleteTarget::Id(id),
+                (_, Some(date), _, _) => DeleteTarget::Date(date),
+                (_, _, None, None) => DeleteTarget::Prompt,
+                (_, _, from, to) => DeleteTarget::Range(from, to),
+            };
+            delete_entry(&load_config()?, target, yes).await?;
+        }
+        Some(Commands::Show { target, json }) => {
+            show_night(&load_config()?, target.unwrap_or(show::Target::Last), json).await?;
         }
         Some(Commands::List { count }) => {
-            list_entries(count.unwrap_or(10)).await?;
+            list_entries(&load_config()?, count.unwrap_or(10)).await?;
         }
         // Some(Commands::Export) => {
         //     export_to_sheets().await?;
         // }
-        Some(Commands::Record) | None => {
-            record_sleep().await?;
+        Some(Commands::Record { like_last, like }) => {
+            let like = match (like_last, like) {
+                (_, Some(date)) => Some(Like::Date(date)),
+                (true, None) => Some(Like::Last),
+                (false, None) => None,
+            };
+            record_sleep(&load_config()?, like).await?;
+        }
+        None => {
+            record_sleep(&load_config()?, None).await?;
         }
     }
 
+    if changes_entries {
+        refresh_feed(&load_config()?).await?;
+    }
     Ok(())
 }
 
-fn edit_config(field: String, value: String) -> Result<(), Box<dyn std::error::Error>> {
-    let mut config = Config::load()?;
-    config.update_field(&field, value)?;
-    config.save()?;
-    println!("Updated {} successfully!", field);
-    Ok(())
-}
-
-fn show_config() -> Result<(), Box<dyn std::error::Error>> {
-    let config = Config::load()?;
-    println!("Current configuration:");
-    println!("  Config file: {}", Config::config_path().display());
-    println!("  start_time_default: {}", config.start_time_default);
-    println!("  end_time_default: {}", config.end_time_default);
-    println!("  db_file_path: {}", config.db_file_path);
-    println!(
-        "  google_sheets_id: {}",
-        config
-            .google_sheets_id
-            .as_ref()
-            .unwrap_or(&"Not set".to_string())
-    );
-    println!(
-        "  google_credentials_path: {}",
-        config
-            .google_credentials_path
-            .as_ref()
-            .unwrap_or(&"Not set".to_string())
-    );
+/// With `--profile`, `get` and `list` show the values that profile sees and `set`/`unset` change
+/// only its overrides.
+fn config_command(
+    config_path: &Path,
+    action: ConfigCommand,
+    profile: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match action {
+        ConfigCommand::Get { field } => {
+            let config = load_profile_config(config_path, profile)?;
+            println!("{}", format_config_value(&config.get_field(&field)?));
+        }
+        ConfigCommand::Set { field, value } => {
+            let mut config = Config::load(config_path)?;
+            match profile {
+                Some(profile) => config.update_profile_field(profile, &field, value)?,
+                None => config.update_field(&field, value)?,
+            }
+            config.save(config_path)?;
+            match profile {
+                Some(profile) => {
+                    println!("Updated {} for profile {} successfully!", field, profile)
+                }
+                None => println!("Updated {} successfully!", field),
+            }
+        }
+        ConfigCommand::Unset { field } => {
+            let mut config = Config::load(config_path)?;
+            match profile {
+                Some(profile) => config.unset_profile_field(profile, &field)?,
+                None => config.unset_field(&field)?,
+            }
+            config.save(config_path)?;
+            let config = load_profile_config(config_path, profile)?;
+            println!(
+                "Reset {} to {}",
+                field,
+                format_config_value(&config.get_field(&field)?)
+            );
+        }
+        ConfigCommand::List => show_config(config_path, profile)?,
+        ConfigCommand::Edit => edit_config_file(config_path)?,
+        ConfigCommand::Validate => {
+            let config = Config::load(config_path)?;
+            let problems = config.validate();
+            if problems.is_empty() {
+                println!("✓ Config is valid");
+            } else {
+                for (field, problem) in &problems {
+                    println!("❌ {}: {}", field, problem);
+                }
+                return Err(format!("Config has {} problem(s)", problems.len()).into());
+            }
+        }
+        ConfigCommand::Path => println!("{}", config_path.display()),
+    }
     Ok(())
 }
 
-// async fn export_to_sheets() -> Result<(), Box<dyn std::error::Error>> {
-//     let config = Config::load()?;
-//
-//     // Check if Google Sheets is configured
-//     let sheets_id = config.google_sheets_id.as_ref().ok_or(
-//         "Google Sheets ID not configured. Use: slog config google_sheets_id YOUR_SHEET_ID",
-//     )?;
-//     let credentials_path = config.google_credentials_path.as_ref()
-//         .ok_or("Google credentials path not configured. Use: slog config google_credentials_path /path/to/credentials.json")?;
-//
-//     // Connect to database
-//     let opts = SqliteConnectOptions::from_str(&config.get_db_url())?.create_if_missing(false);
-//     let pool = SqlitePool::connect_with(opts).await?;
-//
-//     // Create exporter and export
-//     println!("Connecting to Google Sheets...");
-//     let exporter = SheetsExporter::new(credentials_path, sheets_id.clone()).await?;
-//
-//     println!("Exporting data...");
-//     exporter.export_all_data(&pool).await?;
-//
-//     println!("Successfully exported all data to Google Sheets!");
-//     Ok(())
-// }
-
-async fn list_entries(count: usize) -> Result<(), Box<dyn std::error::Error>> {
+async fn run_doctor(config: &Config, fix: bool) -> Result<(), Box<dyn std::error::Error>> {
     println!("\n╔═══════════════════════════════════╗");
-    println!("║      📊 Recent Sleep Entries      ║");
+    println!("║         🩺 Sleep Log Doctor        ║");
     println!("╚═══════════════════════════════════╝\n");
 
-    // Load config
-    let config = Config::load()?;
+    let storage = storage::open(config).await?;
+    let profile = storage.active_profile(config).await?;
+    let report = doctor::scan(&storage, profile).await?;
+
+    let mut fixable = Vec::new();
+    for finding in &report.findings {
+        println!(
+            "#{:<5} {}  {}",
+            finding.entry.id,
+            finding
+                .entry
+                .start
+                .get(..10)
+                .unwrap_or(&finding.entry.start),
+            finding.problem
+        );
+        if let Some(repair) = doctor::repair(&finding.entry)
+            && !fixable
+                .iter()
+                .any(|(entry, _): &(Entry, Vec<String>)| entry.id == repair.0.id)
+        {
+            fixable.push(repair);
+        }
+    }
 
-    // Connect to database
-    let opts = SqliteConnectOptions::from_str(&config.get_db_url())?.create_if_missing(false);
-    let pool = SqlitePool::connect_with(opts).await?;
+    let nights: i64 = report
+        .missing
+        .iter()
+        .map(|(from, to)| (*to - *from).num_days() + 1)
+        .sum();
+    if !report.missing.is_empty() {
+        println!("\n📅 {} night(s) without an entry:", nights);
+        for (from, to) in &report.missing {
+            if from == to {
+                println!("   {}", from);
+            } else {
+                println!("   {} – {}", from, to);
+            }
+        }
+    }
 
-    // Query for recent entries
-    let query = r#"
-        SELECT
-            start,
-            end,
-            minutes_to_fall_asleep,
-            time_awake,
-            time_in_bed_after_waking,
-            quality
-        FROM sleep
-        ORDER BY start DESC
-        LIMIT ?
-    "#;
-
-    let entries: Vec<(String, Option<String>, i32, i32, i32, i8)> = sqlx::query_as(query)
-        .bind(count as i32)
-        .fetch_all(&pool)
-        .await?;
+    if report.findings.is_empty() && report.missing.is_empty() {
+        println!("✓ No problems found.");
+        return Ok(());
+    }
 
-    if entries.is_empty() {
-        println!("No sleep entries found.");
+    println!(
+        "\n{} problem(s), {} night(s) missing, {} entry(s) can be repaired automatically.",
+        report.findings.len(),
+        nights,
+        fixable.len()
+    );
+
+    if !fix {
+        if !fixable.is_empty() {
+            println!("Run `slog doctor --fix` to repair them.");
+        }
+        return Ok(());
+    }
+
+    if !fixable.is_empty() {
+        storage::auto_backup(&storage, config, "doctor").await?;
+    }
+    for (entry, changes) in &fixable {
+        match storage.update(entry).await {
+            Ok(()) => println!("🔧 #{}: {}", entry.id, changes.join(", ")),
+            Err(e @ (StoreError::Overlap(_) | StoreError::Invalid(_))) => {
+                println!("⚠️  #{}: not repaired, the result {}", entry.id, e)
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+async fn undo(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let pool = db::connect(config).await?;
+    let profile = profiles::active(&pool, config).await?;
+    let events = history::undo(&pool, profile).await?;
+    if events.is_empty() {
+        println!("Nothing to undo.");
+    }
+    for event in &events {
+        println!("✓ Undid {}", history::describe(event));
+    }
+    Ok(())
+}
+
+async fn show_history(
+    config: &Config,
+    date: Option<NaiveDate>,
+    count: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let pool = db::connect(config).await?;
+    let profile = profiles::active(&pool, config).await?;
+    let events = history::events(&pool, profile, date, count).await?;
+
+    if events.is_empty() {
+        println!("No history found.");
         return Ok(());
     }
 
     println!(
-        "{:<12} {:<8} {:<12} {:<10} {:<12}",
-        "Date", "Start", "Time in Bed", "Quality", "Efficiency"
+        "{:<6} {:<20} {:<8} {:<6} Start",
+        "#", "Changed", "Action", "Entry"
     );
     println!("{}", "─".repeat(70));
+    for event in events {
+        let line = format!(
+            "{:<6} {:<20} {:<8} {:<6} {:<20} {}",
+            event.id,
+            event.changed_at,
+            event.action,
+            event.entry_id,
+            event.start.as_deref().unwrap_or(""),
+            if event.undone_at.is_some() {
+                "(undone)"
+            } else {
+                ""
+            }
+        );
+        println!("{}", line.trim_end());
+    }
+    Ok(())
+}
 
-    for (start, end, minutes_to_fall_asleep, time_awake, time_in_bed_after_waking, quality) in
-        entries
-    {
-        let start_dt = NaiveDateTime::parse_from_str(&start, "%Y-%m-%d %H:%M:%S")?;
-        let date = start_dt.format("%Y-%m-%d").to_string();
-        let start_time = start_dt.format("%H:%M").to_string();
-
-        if let Some(end_str) = end {
-            let end_dt = NaiveDateTime::parse_from_str(&end_str, "%Y-%m-%d %H:%M:%S")?;
-            let total_time_in_bed = (end_dt - start_dt).num_minutes();
-            let total_sleep_minutes = total_time_in_bed
-                - minutes_to_fall_asleep as i64
-                - time_awake as i64
-                - time_in_bed_after_waking as i64;
-            let sleep_efficiency = (total_sleep_minutes as f64 / total_time_in_bed as f64) * 100.0;
+async fn restore_entry(config: &Config, id: i64) -> Result<(), Box<dyn std::error::Error>> {
+    let pool = db::connect(config).await?;
+    let profile = profiles::active(&pool, config).await?;
+    if history::restore_entry(&pool, profile, id).await? {
+        println!("✓ Entry {} restored.", id);
+    } else {
+        println!("❌ No deleted entry with id {}", id);
+    }
+    Ok(())
+}
 
-            let hours = total_time_in_bed / 60;
-            let mins = total_time_in_bed % 60;
-            let time_in_bed_str = format!("{}h {:02}m", hours, mins);
+async fn backup_database(
+    config: &Config,
+    dest: Option<PathBuf>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let pool = db::connect(config).await?;
+    let dest = dest.unwrap_or_else(|| backup::default_destination(config));
+    backup::backup_to(&pool, &dest, db::passphrase(config)?.as_deref()).await?;
+    println!("✓ Backed up {} to {}", config.db_file_path, dest.display());
+    Ok(())
+}
 
-            let quality_str = match quality {
-                -2 => "Devastation",
-                -1 => "Terrible",
-                0 => "Blah",
-                1 => "Okay",
-                2 => "Perfection",
-                _ => "Unknown",
-            };
+async fn restore_database(
+    config: &Config,
+    file: &Path,
+    yes: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let backup::SchemaCheck::Older(pending) = backup::check_schema(file, config).await? {
+        println!(
+            "ℹ️  {} is from an older slog; {} migration(s) will be applied after restoring.",
+            file.display(),
+            pending
+        );
+    }
 
-            println!(
-                "{:<12} {:<8} {:<12} {:<10} {:<12.1}%",
-                date, start_time, time_in_bed_str, quality_str, sleep_efficiency
-            );
-        } else {
-            println!(
-                "{:<12} {:<8} {:<12} {:<10} {:<12}",
-                date, start_time, "N/A", "N/A", "N/A"
-            );
+    if !yes {
+        let confirm = Confirm::new(&format!(
+            "Replace {} with {}?",
+            config.db_file_path,
+            file.display()
+        ))
+        .with_default(false)
+        .prompt()?;
+        if !confirm {
+            println!("Cancelled. No changes made.");
+            return Ok(());
         }
     }
 
-    println!();
+    if Path::new(&config.db_file_path).exists() {
+        let pool = db::open(config).await?;
+        backup::auto_backup(&pool, config, "pre-restore").await?;
+        pool.close().await;
+    }
+
+    backup::restore_from(file, config).await?;
+    db::connect(config).await?;
+    println!("✓ Restored {} from {}", config.db_file_path, file.display());
     Ok(())
 }
 
-async fn delete_entry() -> Result<(), Box<dyn std::error::Error>> {
+async fn show_goals(config: &Config, weeks: usize) -> Result<(), Box<dyn std::error::Error>> {
     println!("\n╔═══════════════════════════════════╗");
-    println!("║      🗑️  Delete Sleep Entry       ║");
+    println!("║            🎯 Sleep Goals          ║");
     println!("╚═══════════════════════════════════╝\n");
 
-    // Load config
-    let config = Config::load()?;
-
-    // Connect to database
-    let opts = SqliteConnectOptions::from_str(&config.get_db_url())?.create_if_missing(false);
-    let pool = SqlitePool::connect_with(opts).await?;
+    let goals = Goal::from_config(config);
+    if goals.is_empty() {
+        println!("No goals set. Set some with e.g.:");
+        println!("   slog config set goal_bedtime 22:30");
+        println!("   slog config set goal_min_sleep_hours 7");
+        println!("   slog config set goal_min_efficiency 85");
+        println!("   slog config set goal_no_benadryl true");
+        return Ok(());
+    }
 
-    // Prompt for date
-    let now = Local::now().fixed_offset();
-    let delete_date: NaiveDate = DateSelect::new("Select the date of the entry to delete:")
-        .with_default(
-            NaiveDate::from_ymd_opt(now.year(), now.month(), now.day())
-                .expect("Failed to get current date"),
-        )
-        .prompt()?;
+    let storage = storage::open(config).await?;
+    let profile = storage.active_profile(config).await?;
+    let progress = goals::evaluate(&storage, profile, &goals).await?;
 
-    // Look for entry on this date
-    let query = "SELECT id, start, end, quality, notes FROM sleep WHERE DATE(start) = ?";
-    let date_str = delete_date.format("%Y-%m-%d").to_string();
+    println!(
+        "{:<28} {:>8} {:>8} {:>10}",
+        "Goal", "Current", "Longest", "Hit rate"
+    );
+    println!("{}", "─".repeat(57));
+    for progress in &progress {
+        println!(
+            "{:<28} {:>8} {:>8} {:>10}",
+            progress.goal.to_string(),
+            progress.current_streak,
+            progress.longest_streak,
+            progress
+                .hit_rate()
+                .map(|rate| format!("{:.0}%", rate))
+                .unwrap_or_else(|| "N/A".to_string())
+        );
+    }
 
-    let entry: Option<(i64, String, Option<String>, i8, Option<String>)> = sqlx::query_as(query)
-        .bind(&date_str)
-        .fetch_optional(&pool)
-        .await?;
+    let mut recent: Vec<NaiveDate> = progress
+        .iter()
+        .flat_map(|progress| progress.weekly.keys().copied())
+        .collect();
+    recent.sort();
+    recent.dedup();
+    let recent = &recent[recent.len().saturating_sub(weeks)..];
+    if recent.is_empty() {
+        return Ok(());
+    }
 
-    match entry {
-        Some((id, start, end, quality, notes)) => {
-            println!("\n📋 Found entry:");
-            println!("   ID: {}", id);
-            println!("   Start: {}", start);
-            if let Some(end_time) = end {
-                println!("   End: {}", end_time);
-            }
-            println!("   Quality: {}", quality);
-            if let Some(note_text) = notes {
-                println!("   Notes: {}", note_text);
+    println!("\nWeekly hit rates (nights met / nights recorded):\n");
+    print!("{:<28}", "Week of");
+    for week in recent {
+        print!(" {:>6}", week.format("%m-%d").to_string());
+    }
+    println!();
+    for progress in &progress {
+        print!("{:<28}", progress.goal.to_string());
+        for week in recent {
+            match progress.weekly.get(week) {
+                Some((hits, nights)) => print!(" {:>6}", format!("{}/{}", hits, nights)),
+                None => print!(" {:>6}", "-"),
             }
+        }
+        println!();
+    }
+    Ok(())
+}
 
-            let confirm = Confirm::new("Are you sure you want to delete this entry?")
-                .with_default(false)
-                .prompt()?;
+/// After recording: which goals last night met, and the streaks that are running.
+async fn print_goal_feedback(
+    storage: &impl Storage,
+    config: &Config,
+    entry: &Entry,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let goals = Goal::from_config(config);
+    if goals.is_empty() {
+        return Ok(());
+    }
 
-            if confirm {
-                let delete_sql = "DELETE FROM sleep WHERE id = ?";
-                sqlx::query(delete_sql).bind(id).execute(&pool).await?;
-                println!("✓ Entry deleted successfully!");
-            } else {
-                println!("Cancelled. No changes made.");
-            }
-        }
-        None => {
-            println!("❌ No entry found for date: {}", date_str);
+    println!("\n=== Goals ===");
+    let night = entry.start_datetime()?.date();
+    let progress = goals::evaluate(storage, entry.profile_id, &goals).await?;
+    for progress in &progress {
+        let goal = &progress.goal;
+        // Streaks only continue from the newest night, not from a backfilled one
+        let latest = progress.last_night == Some(night);
+        match goal.check(entry) {
+            Some(true) if latest && progress.current_streak > 1 => println!(
+                "✅ {} ({}) – {} nights in a row",
+                goal,
+                goal.actual(entry),
+                progress.current_streak
+            ),
+            Some(true) => println!("✅ {} ({})", goal, goal.actual(entry)),
+            Some(false) => println!("❌ {} ({})", goal, goal.actual(entry)),
+            None => println!("➖ {} (not enough data)", goal),
         }
     }
-
     Ok(())
 }
 
-async fn record_sleep() -> Result<(), Box<dyn std::error::Error>> {
-    // Display welcome message
+/// What to do with a night `backfill` found without an entry.
+#[derive(Debug, Clone, Copy, EnumIter, strum_macros::Display)]
+enum BackfillAction {
+    #[strum(to_string = "Record (all prompts)")]
+    Record,
+    #[strum(to_string = "Quick entry (start-end and quality)")]
+    Quick,
+    #[strum(to_string = "Skip for now")]
+    Skip,
+    #[strum(to_string = "Unknown (slept, but don't remember the details)")]
+    Unknown,
+    #[strum(to_string = "Didn't sleep here")]
+    Away,
+    #[strum(to_string = "Stop")]
+    Stop,
+}
+
+async fn backfill(config: &Config, since: NaiveDate) -> Result<(), Box<dyn std::error::Error>> {
     println!("\n╔═══════════════════════════════════╗");
-    println!("║  🥱💤 SLOG (The Sleep Log) 💤🥱   ║");
-    println!("╚═══════════════════════════════════╝");
+    println!("║        📅 Backfill Missing        ║");
+    println!("╚═══════════════════════════════════╝\n");
+
+    let storage = storage::open(config).await?;
+    let profile = storage.active_profile(config).await?;
+    let yesterday = Local::now().date_naive() - Days::new(1);
+    let nights = markers::unlogged_nights(&storage, profile, since, yesterday).await?;
+
+    if nights.is_empty() {
+        println!(
+            "✓ Every night since {} is logged.",
+            since.format("%Y-%m-%d")
+        );
+        return Ok(());
+    }
     println!(
-        "                                v{}\n",
-        env!("CARGO_PKG_VERSION")
+        "{} night(s) since {} have no entry.",
+        nights.len(),
+        since.format("%Y-%m-%d")
     );
 
-    // Load config
-    let config = Config::load()?;
+    let mut recorded = 0;
+    let mut marked = 0;
+    for (i, date) in nights.iter().enumerate() {
+        println!(
+            "\n── {} ({}/{}) ──",
+            date.format("%A %Y-%m-%d"),
+            i + 1,
+            nights.len()
+        );
+        let action = Select::new(
+            "What happened this night?",
+            BackfillAction::iter().collect(),
+        )
+        .prompt()?;
 
-    // Ensure database directory exists
-    let db_path = std::path::Path::new(&config.db_file_path);
-    if let Some(parent) = db_path.parent() {
-        std::fs::create_dir_all(parent)?;
+        match action {
+            BackfillAction::Record => {
+                if record_night(&storage, config, profile, *date, None).await? {
+                    recorded += 1;
+                }
+            }
+            BackfillAction::Quick => {
+                let defaults = time_defaults::resolve(&storage, profile, config, *date).await?;
+                let record = prompt_quick_record(*date, &defaults, profile)?;
+                if save_record(&storage, config, record, None).await? {
+                    recorded += 1;
+                }
+            }
+            BackfillAction::Skip => {}
+            BackfillAction::Unknown => {
+                storage
+                    .set_marker(profile, *date, MarkerKind::Unknown)
+                    .await?;
+                marked += 1;
+            }
+            BackfillAction::Away => {
+                storage.set_marker(profile, *date, MarkerKind::Away).await?;
+                marked += 1;
+            }
+            BackfillAction::Stop => break,
+        }
     }
 
-    // creates database if doesn't exist
-    let opts = SqliteConnectOptions::from_str(&config.get_db_url())?.create_if_missing(true);
+    println!(
+        "\n✓ {} night(s) recorded, {} marked, {} still missing.",
+        recorded,
+        marked,
+        nights.len() - recorded - marked
+    );
+    Ok(())
+}
 
-    let pool = SqlitePool::connect_with(opts)
-        .await
-        .expect("Failed to connect with db");
+/// The one-line form of `backfill`: `23:00-07:00 okay`. Everything else keeps its default.
+fn prompt_quick_record(
+    date: NaiveDate,
+    defaults: &DefaultTimes,
+    profile: i64,
+) -> InquireResult<SleepRecord> {
+    let qualities: Vec<String> = Quality::iter().map(|q| q.to_string()).collect();
+    let input = Text::new("Start-end and quality:")
+        .with_default(&format!(
+            "{}-{} {}",
+            defaults.start.format("%H:%M"),
+            defaults.end.format("%H:%M"),
+            Quality::Okay
+        ))
+        .with_help_message(&format!("e.g. 23:15-06:45 okay ({})", qualities.join(", ")))
+        .with_validator(|input: &str| {
+            Ok(match parse_quick_record(input) {
+                Ok(_) => Validation::Valid,
+                Err(e) => Validation::Invalid(e.into()),
+            })
+        })
+        .prompt()?;
+
+    let (start, end, quality) = parse_quick_record(&input).expect("validated quick entry");
+    let mut record = SleepRecord::new(date, defaults, profile);
+    record.start = date.and_time(start);
+    // An end before the start is on the next morning
+    record.end = if end > start {
+        date.and_time(end)
+    } else {
+        next_day(date).and_time(end)
+    };
+    if let Some(quality) = quality {
+        record.quality = quality.db_value();
+    }
+    Ok(record)
+}
+
+fn parse_quick_record(input: &str) -> Result<(NaiveTime, NaiveTime, Option<Quality>), String> {
+    let mut parts = input.split_whitespace();
+    let times = parts.next().ok_or("Enter start-end, e.g. 23:15-06:45")?;
+    let (start, end) = times
+        .split_once('-')
+        .ok_or("Enter start-end, e.g. 23:15-06:45")?;
+    let start = parse_hhmm(start).map_err(|_| format!("Invalid start time: {}", start))?;
+    let end = parse_hhmm(end).map_err(|_| format!("Invalid end time: {}", end))?;
+
+    let quality = match parts.next() {
+        Some(name) => Some(
+            Quality::iter()
+                .find(|quality| quality.to_string().eq_ignore_ascii_case(name))
+                .ok_or_else(|| format!("Unknown quality: {}", name))?,
+        ),
+        None => None,
+    };
+    if let Some(extra) = parts.next() {
+        return Err(format!("Unexpected: {}", extra));
+    }
+    Ok((start, end, quality))
+}
+
+async fn show_digest(
+    config: &Config,
+    kind: PeriodKind,
+    date: Option<NaiveDate>,
+    format: DigestFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let storage = storage::open(config).await?;
+    let profile = storage.active_profile(config).await?;
+    let date = date.unwrap_or_else(|| Local::now().date_naive() - Days::new(1));
+    let digest = digest::build(&storage, profile, Period::containing(kind, date)).await?;
+
+    match format {
+        DigestFormat::Text => print!("{}", digest::render_text(&digest)),
+        DigestFormat::Markdown => print!("{}", digest::render_markdown(&digest)),
+        DigestFormat::Json => println!("{}", serde_json::to_string_pretty(&digest)?),
+    }
+    Ok(())
+}
+
+async fn import_file(
+    config: &Config,
+    file: &Path,
+    format: Option<import::Format>,
+    merge: import::MergeRule,
+    dry_run: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let format = match format {
+        Some(format) => format,
+        None => import::Format::detect(file)?,
+    };
+    let mut nights = format.parse(file)?;
+    println!(
+        "📥 {} night(s) in {} ({})",
+        nights.len(),
+        file.display(),
+        format.source()
+    );
+    if nights.is_empty() {
+        return Ok(());
+    }
+
+    let storage = storage::open(config).await?;
+    let profile = storage.active_profile(config).await?;
+    for night in &mut nights {
+        night.entry.profile_id = profile;
+    }
+    if !dry_run {
+        storage::auto_backup(&storage, config, "import").await?;
+    }
+
+    let (mut inserted, mut updated, mut merged, mut skipped) = (0, 0, 0, 0);
+    for night in &nights {
+        let outcome = if dry_run {
+            import::preview(&storage, night, merge).await?
+        } else {
+            import::save(&storage, night, merge).await?
+        };
+        let range = format!(
+            "{} → {}",
+            night.entry.start,
+            night.entry.end.as_deref().unwrap_or("N/A")
+        );
+        match outcome {
+            import::Outcome::Inserted(id) => {
+                inserted += 1;
+                if dry_run {
+                    println!("➕ {}", range);
+                } else {
+                    println!("➕ #{} {}", id, range);
+                }
+            }
+            import::Outcome::Updated(id) => {
+                updated += 1;
+                println!("🔄 #{} {} (imported before)", id, range);
+            }
+            import::Outcome::Merged(id) => {
+                merged += 1;
+                println!("🔀 #{} {} (merged)", id, range);
+            }
+            import::Outcome::Skipped(reason) => {
+                skipped += 1;
+                println!("⏭️  {} skipped: {}", range, reason);
+            }
+        }
+    }
+
+    println!(
+        "\n{}{} added, {} updated, {} merged, {} skipped.",
+        if dry_run { "Dry run: " } else { "" },
+        inserted,
+        updated,
+        merged,
+        skipped
+    );
+    if skipped > 0 && merge == import::MergeRule::Skip {
+        println!("Use --merge device-times or --merge replace to combine overlapping nights.");
+    }
+    Ok(())
+}
+
+async fn sync_with(
+    config: &Config,
+    target: &Path,
+    conflicts: sync::Resolution,
+    dry_run: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let pool = db::connect(config).await?;
+    if !target.exists() {
+        if target
+            .extension()
+            .is_some_and(|extension| extension == "db")
+        {
+            return Err(format!("No database at {}", target.display()).into());
+        }
+        std::fs::create_dir_all(target)
+            .map_err(|e| format!("Failed to create {}: {}", target.display(), e))?;
+    }
+    let target = target.canonicalize()?;
+    if Path::new(&config.db_file_path).canonicalize().ok().as_ref() == Some(&target) {
+        return Err("Cannot sync a database with itself".into());
+    }
+
+    let remote = if target.is_dir() {
+        sync::Target::Directory(sync::Directory::open(
+            &target,
+            &sync::device_id(&pool).await?,
+        )?)
+    } else {
+        let mut remote_config = config.clone();
+        remote_config.db_file_path = target.display().to_string();
+        let remote_pool = db::connect(&remote_config).await?;
+        sync::Target::Database(remote_pool, Box::new(remote_config))
+    };
+    println!("🔄 Syncing with {}\n", target.display());
+
+    let local_changes = sync::changes(&pool).await?;
+    let remote_changes = match &remote {
+        sync::Target::Database(remote_pool, _) => sync::changes(remote_pool).await?,
+        sync::Target::Directory(directory) => directory.changes(),
+    };
+    let base = sync::base(&pool, &target).await?;
+    let target_name = target
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| target.display().to_string());
+
+    let plan = sync::plan(
+        local_changes,
+        remote_changes,
+        &base,
+        matches!(remote, sync::Target::Database(..)),
+        |conflict| {
+            if conflicts == sync::Resolution::Newest || dry_run {
+                return Ok(conflict.newest());
+            }
+            let (local, remote) = match conflict {
+                sync::Conflict::Edited { local, remote }
+                | sync::Conflict::Overlap { local, remote } => (local, remote),
+            };
+            let options = vec![
+                format!("This database: {}", sync::summary(local)),
+                format!("{}: {}", target_name, sync::summary(remote)),
+            ];
+            println!("⚔️  {}", conflict.describe());
+            let choice = Select::new("Which version do you keep?", options).raw_prompt()?;
+            Ok(if choice.index == 0 {
+                sync::Side::Local
+            } else {
+                sync::Side::Remote
+            })
+        },
+    )?;
+
+    let matched = plan.rename_local.len() + plan.rename_remote.len();
+    if matched > 0 {
+        println!("🔗 {} entry(s) recorded on both sides matched up", matched);
+    }
+    for change in &plan.pull {
+        println!("⬇️  {}", sync::describe(change));
+    }
+    for change in &plan.push {
+        println!("⬆️  {}", sync::describe(change));
+    }
+    for (conflict, side) in &plan.conflicts {
+        let kept = match side {
+            sync::Side::Local => "kept this database's".to_string(),
+            sync::Side::Remote => format!("took {}'s", target_name),
+        };
+        println!("⚔️  {}: {}", conflict, kept);
+    }
+
+    if !dry_run {
+        if !plan.pull.is_empty() || !plan.rename_local.is_empty() {
+            backup::auto_backup(&pool, config, "sync").await?;
+            sync::apply(&pool, &plan.rename_local, &plan.pull).await?;
+        }
+        match &remote {
+            sync::Target::Database(remote_pool, remote_config) => {
+                if !plan.push.is_empty() || !plan.rename_remote.is_empty() {
+                    backup::auto_backup(remote_pool, remote_config, "sync").await?;
+                    sync::apply(remote_pool, &plan.rename_remote, &plan.push).await?;
+                }
+            }
+            sync::Target::Directory(directory) => directory.append(&plan.push)?,
+        }
+        sync::save_base(&pool, &target, &plan.merged).await?;
+        // The target syncs back with this database later, from the other side
+        if let sync::Target::Database(remote_pool, _) = &remote {
+            let local = Path::new(&config.db_file_path).canonicalize()?;
+            sync::save_base(remote_pool, &local, &plan.merged).await?;
+        }
+    }
+
+    println!(
+        "\n{}{} pulled, {} pushed, {} conflict(s).",
+        if dry_run { "Dry run: " } else { "" },
+        plan.pull.len(),
+        plan.push.len(),
+        plan.conflicts.len()
+    );
+    Ok(())
+}
+
+async fn export(
+    config: &Config,
+    format: ExportFormat,
+    output: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let storage = storage::open(config).await?;
+    let profile = storage.active_profile(config).await?;
+    match (format, output) {
+        (ExportFormat::Ics, Some(path)) => {
+            ics::write_feed(&storage, profile, path).await?;
+            eprintln!("✓ Wrote {}", path.display());
+        }
+        (ExportFormat::Ics, None) => {
+            let mut entries = storage.recent(profile, None).await?;
+            entries.reverse();
+            print!("{}", ics::render(&entries));
+        }
+    }
+    Ok(())
+}
+
+/// Rewrites the profile's calendar feed (see `Config::feed_path`), if one is configured.
+async fn refresh_feed(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(path) = config.feed_path() else {
+        return Ok(());
+    };
+    let storage = storage::open(config).await?;
+    let profile = storage.active_profile(config).await?;
+    ics::write_feed(&storage, profile, &path)
+        .await
+        .map_err(|e| format!("Failed to update the calendar feed: {}", e).into())
+}
+
+async fn remind(config: &Config, stdout: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let storage = storage::open(config).await?;
+    let profile = storage.active_profile(config).await?;
+    let today = Local::now().date_naive();
+    if remind::has_entry_for_last_night(&storage, profile, today).await? {
+        return Ok(());
+    }
+
+    let summary = "💤 Log last night's sleep";
+    let body = format!(
+        "There is no entry for the night of {} yet. Run `slog record`.",
+        (today - Days::new(1)).format("%Y-%m-%d")
+    );
+    if stdout {
+        println!("{}\n{}", summary, body);
+    } else if let Err(e) = remind::notify(summary, &body) {
+        // No notification daemon (e.g. over ssh); the message still gets through
+        eprintln!("⚠️  {}", e);
+        println!("{}\n{}", summary, body);
+    }
+    Ok(())
+}
+
+fn install_reminder(
+    config: &Config,
+    config_path: &Path,
+    db: Option<&Path>,
+    profile: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for path in remind::install(config, config_path, db, profile)? {
+        println!("✓ Wrote {}", path.display());
+    }
+    println!(
+        "\nEnable the daily reminder at {} with:\n   systemctl --user daemon-reload\n   systemctl --user enable --now {}",
+        config.reminder_time,
+        remind::timer_name()
+    );
+    println!("Run `slog remind install` again after changing `reminder_time`.");
+    Ok(())
+}
+
+async fn profile_command(
+    config: &Config,
+    config_path: &Path,
+    action: ProfileCommand,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let storage = storage::open(config).await?;
+    match action {
+        ProfileCommand::List => {
+            println!("{:<3}{:<20} {:>8}  Created", "", "Profile", "Entries");
+            println!("{}", "─".repeat(50));
+            for profile in storage.profiles().await? {
+                println!(
+                    "{:<3}{:<20} {:>8}  {}",
+                    if profile.name == config.default_profile {
+                        "*"
+                    } else {
+                        ""
+                    },
+                    profile.name,
+                    profile.entries,
+                    profile.created_at
+                );
+            }
+        }
+        ProfileCommand::Add { name } => {
+            storage.add_profile(&name).await?;
+            println!("✓ Added profile {}", name.trim());
+            println!("Log for it with `slog --profile {} record`.", name.trim());
+        }
+        ProfileCommand::Rename { name, new_name } => {
+            storage.rename_profile(&name, &new_name).await?;
+            let new_name = new_name.trim().to_string();
+
+            // Keep the config pointing at the profile
+            let mut file = Config::load(config_path)?;
+            let mut changed = false;
+            if file.default_profile == name {
+                file.default_profile = new_name.clone();
+                changed = true;
+            }
+            if let Some(overrides) = file.profiles.remove(&name) {
+                file.profiles.insert(new_name.clone(), overrides);
+                changed = true;
+            }
+            if changed {
+                file.save(config_path)?;
+            }
+            println!("✓ Renamed profile {} to {}", name, new_name);
+        }
+        ProfileCommand::Remove { name } => {
+            let file = Config::load(config_path)?;
+            if name == config.default_profile || name == file.default_profile {
+                return Err(format!(
+                    "{} is the profile in use; switch `default_profile` or use --profile first",
+                    name
+                )
+                .into());
+            }
+            storage::auto_backup(&storage, config, "remove-profile").await?;
+            storage.remove_profile(&name).await?;
+
+            let mut file = file;
+            if file.profiles.remove(&name).is_some() {
+                file.save(config_path)?;
+            }
+            println!("✓ Removed profile {}", name);
+        }
+    }
+    Ok(())
+}
+
+async fn db_command(
+    config: &Config,
+    config_path: &Path,
+    action: DbCommand,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match &action {
+        DbCommand::Path => {
+            println!("{}", config.db_file_path);
+            return Ok(());
+        }
+        DbCommand::Encrypt { key_file, key_env } => {
+            return encrypt_database(config, config_path, key_file.as_deref(), *key_env).await;
+        }
+        DbCommand::Decrypt => return decrypt_database(config, config_path).await,
+        _ => {}
+    }
+
+    // Not `db::connect`, so that `status` shows what is pending
+    let pool = db::open(config).await?;
+
+    match action {
+        DbCommand::Status => {
+            println!("Database: {}\n", config.db_file_path);
+            let pending = db::print_status(&pool).await?;
+            if pending > 0 {
+                println!(
+                    "\n{} pending migration(s). Run `slog db migrate` to apply.",
+                    pending
+                );
+            }
+        }
+        DbCommand::Migrate => {
+            let pending = db::print_status(&pool).await?;
+            db::migrate(&pool, config).await?;
+            println!("\n✓ Applied {} migration(s).", pending);
+        }
+        DbCommand::Vacuum => {
+            let (before, after) = db::vacuum(&pool, &config.db_file_path).await?;
+            println!(
+                "✓ Vacuumed {}: {} KiB → {} KiB",
+                config.db_file_path,
+                before / 1024,
+                after / 1024
+            );
+        }
+        DbCommand::IntegrityCheck => {
+            let problems = db::integrity_check(&pool).await?;
+            if problems.is_empty() {
+                println!("✓ Integrity check passed");
+            } else {
+                for problem in &problems {
+                    println!("❌ {}", problem);
+                }
+                return Err(format!("Integrity check found {} problem(s)", problems.len()).into());
+            }
+        }
+        DbCommand::Path | DbCommand::Encrypt { .. } | DbCommand::Decrypt => unreachable!(),
+    }
+
+    Ok(())
+}
+
+async fn encrypt_database(
+    config: &Config,
+    config_path: &Path,
+    key_file: Option<&Path>,
+    key_env: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    config.get_db_url()?;
+    let mut file = Config::load(config_path)?;
+    configured_database_only(config, &file)?;
+    let path = Path::new(&config.db_file_path);
+    match db::is_encrypted(path) {
+        None => return Err(format!("No database at {}", path.display()).into()),
+        Some(true) => return Err(format!("{} is already encrypted", path.display()).into()),
+        Some(false) => {}
+    }
+
+    // Where the passphrase comes from once the database is encrypted
+    let mut keyed = config.clone();
+    keyed.db_key_file = match key_file {
+        Some(file) => Some(std::path::absolute(file)?.display().to_string()),
+        None => None,
+    };
+    keyed.db_key = match (key_file, key_env) {
+        (Some(_), _) => DbKey::File,
+        (None, true) => DbKey::Env,
+        (None, false) => DbKey::Prompt,
+    };
+    let passphrase = match keyed.db_key {
+        DbKey::Prompt => db::new_passphrase()?,
+        _ => db::passphrase(&keyed)?.unwrap_or_default(),
+    };
+
+    // Opened without a key even if db_key was set ahead of time
+    let plain = Config {
+        db_key: DbKey::None,
+        ..config.clone()
+    };
+    db::convert(&plain, Some(&passphrase)).await?;
+
+    file.db_key = keyed.db_key;
+    file.db_key_file = keyed.db_key_file.clone();
+    file.save(config_path)?;
+
+    println!("🔒 Encrypted {}", path.display());
+    match keyed.db_key {
+        DbKey::File => println!(
+            "   The passphrase is read from {} (db_key: file)",
+            keyed.db_key_file.unwrap_or_default()
+        ),
+        DbKey::Env => println!(
+            "   The passphrase is read from {} (db_key: env)",
+            db::KEY_VAR
+        ),
+        _ => println!("   Every command asks for the passphrase (db_key: prompt)"),
+    }
+    let plain_backups = backup::count_backups(config, false);
+    if plain_backups > 0 {
+        println!(
+            "⚠️  {} backup(s) in {} are not encrypted; delete them once you don't need them.",
+            plain_backups,
+            backup::backup_dir(config).display()
+        );
+    }
+    Ok(())
+}
+
+/// Refuses to encrypt or decrypt a database picked with `--db` or `SLOG_DB`: `db_key` and
+/// `db_key_file` in the config file apply to every command, which would then fail to open the
+/// configured database.
+fn configured_database_only(config: &Config, file: &Config) -> Result<(), String> {
+    let (chosen, configured) = (
+        Path::new(&config.db_file_path),
+        Path::new(&file.db_file_path),
+    );
+    let same = match (chosen.canonicalize(), configured.canonicalize()) {
+        (Ok(chosen), Ok(configured)) => chosen == configured,
+        _ => chosen == configured,
+    };
+    if same {
+        return Ok(());
+    }
+    Err(format!(
+        "{} is not the configured database ({}), and the passphrase settings apply to that one. \
+         Point db_file_path at it first: slog config set db_file_path {}",
+        chosen.display(),
+        configured.display(),
+        chosen.display()
+    ))
+}
+
+async fn decrypt_database(
+    config: &Config,
+    config_path: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    config.get_db_url()?;
+    let mut file = Config::load(config_path)?;
+    configured_database_only(config, &file)?;
+    let path = Path::new(&config.db_file_path);
+    if db::is_encrypted(path) != Some(true) {
+        return Err(format!("{} is not encrypted", path.display()).into());
+    }
+
+    db::convert(config, None).await?;
+
+    file.db_key = DbKey::None;
+    file.db_key_file = None;
+    file.save(config_path)?;
+
+    println!("🔓 Decrypted {}", path.display());
+    let encrypted_backups = backup::count_backups(config, true);
+    if encrypted_backups > 0 {
+        println!(
+            "ℹ️  {} backup(s) in {} are still encrypted; restoring one needs the database \
+             encrypted with the same passphrase.",
+            encrypted_backups,
+            backup::backup_dir(config).display()
+        );
+    }
+    Ok(())
+}
+
+fn format_config_value(value: &serde_yaml::Value) -> String {
+    match value {
+        serde_yaml::Value::Null => "Not set".to_string(),
+        serde_yaml::Value::String(s) => s.clone(),
+        other => serde_yaml::to_string(other)
+            .map(|s| s.trim_end().to_string())
+            .unwrap_or_default(),
+    }
+}
+
+/// The config file, as `profile` sees it if given.
+fn load_profile_config(
+    config_path: &Path,
+    profile: Option<&str>,
+) -> Result<Config, Box<dyn std::error::Error>> {
+    let config = Config::load(config_path)?;
+    Ok(match profile {
+        Some(profile) => config.for_profile(profile)?,
+        None => config,
+    })
+}
+
+fn show_config(
+    config_path: &Path,
+    profile: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let config = load_profile_config(config_path, profile)?;
+    println!("Current configuration:");
+    println!("  Config file: {}", config_path.display());
+    if let Some(profile) = profile {
+        println!("  Profile: {}", profile);
+    }
+    for field in Config::fields() {
+        println!(
+            "  {}: {}",
+            field,
+            format_config_value(&config.get_field(&field)?)
+        );
+    }
+    Ok(())
+}
+
+// Opens the config file in the user's editor until it parses and validates (or they give up)
+fn edit_config_file(config_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    if !config_path.exists() {
+        // Creates the file with defaults
+        Config::load(config_path)?;
+    }
+
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut editor_args = editor.split_whitespace();
+    let program = editor_args.next().ok_or("$EDITOR is empty")?;
+    let editor_args: Vec<&str> = editor_args.collect();
+
+    loop {
+        let status = std::process::Command::new(program)
+            .args(&editor_args)
+            .arg(config_path)
+            .status()?;
+        if !status.success() {
+            return Err(format!("{} exited with {}", editor, status).into());
+        }
+
+        let problems: Vec<String> = match Config::load(config_path) {
+            Ok(config) => config
+                .validate()
+                .into_iter()
+                .map(|(field, problem)| format!("{}: {}", field, problem))
+                .collect(),
+            Err(e) => vec![e.to_string()],
+        };
+
+        if problems.is_empty() {
+            println!("✓ Config is valid");
+            return Ok(());
+        }
+
+        for problem in &problems {
+            println!("❌ {}", problem);
+        }
+        if !Confirm::new("Edit again?").with_default(true).prompt()? {
+            return Err("Config file has problems".into());
+        }
+    }
+}
+
+// async fn export_to_sheets() -> Result<(), Box<dyn std::error::Error>> {
+//     let config = Config::load()?;
+//
+//     // Check if Google Sheets is configured
+//     let sheets_id = config.google_sheets_id.as_ref().ok_or(
+//         "Google Sheets ID not configured. Use: slog config google_sheets_id YOUR_SHEET_ID",
+//     )?;
+//     let credentials_path = config.google_credentials_path.as_ref()
+//         .ok_or("Google credentials path not configured. Use: slog config google_credentials_path /path/to/credentials.json")?;
+//
+//     // Connect to database
+//     let pool = db::connect(&config).await?;
+//
+//     // Create exporter and export
+//     println!("Connecting to Google Sheets...");
+//     let exporter = SheetsExporter::new(credentials_path, sheets_id.clone()).await?;
+//
+//     println!("Exporting data...");
+//     exporter.export_all_data(&pool).await?;
+//
+//     println!("Successfully exported all data to Google Sheets!");
+//     Ok(())
+// }
+
+async fn list_entries(config: &Config, count: usize) -> Result<(), Box<dyn std::error::Error>> {
+    println!("\n╔═══════════════════════════════════╗");
+    println!("║      📊 Recent Sleep Entries      ║");
+    println!("╚═══════════════════════════════════╝\n");
+
+    // Connect to database
+    let storage = storage::open(config).await?;
+
+    // Query for recent entries
+    let profile = storage.active_profile(config).await?;
+    let entries = storage.recent(profile, Some(count)).await?;
+
+    if entries.is_empty() {
+        println!("No sleep entries found.");
+        return Ok(());
+    }
+
+    println!(
+        "{:<12} {:<8} {:<12} {:<10} {:<12}",
+        "Date", "Start", "Time in Bed", "Quality", "Efficiency"
+    );
+    println!("{}", "─".repeat(70));
+
+    for entry in entries {
+        let start_dt = entry.start_datetime()?;
+        let date = start_dt.format("%Y-%m-%d").to_string();
+        let start_time = start_dt.format("%H:%M").to_string();
+
+        if let (Some(total_time_in_bed), Some(sleep_efficiency)) =
+            (entry.time_in_bed_minutes(), entry.efficiency())
+        {
+            let hours = total_time_in_bed / 60;
+            let mins = total_time_in_bed % 60;
+            let time_in_bed_str = format!("{}h {:02}m", hours, mins);
+
+            let quality_str = entry.quality_name();
+
+            println!(
+                "{:<12} {:<8} {:<12} {:<10} {:<12.1}%",
+                date, start_time, time_in_bed_str, quality_str, sleep_efficiency
+            );
+        } else {
+            println!(
+                "{:<12} {:<8} {:<12} {:<10} {:<12}",
+                date, start_time, "N/A", "N/A", "N/A"
+            );
+        }
+    }
+
+    println!();
+    Ok(())
+}
+
+async fn show_night(
+    config: &Config,
+    target: show::Target,
+    json: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let storage = storage::open(config).await?;
+    let profile = storage.active_profile(config).await?;
+    let entries = show::find(&storage, profile, target).await?;
+
+    let mut reports = Vec::new();
+    for entry in entries {
+        reports.push(show::build(&storage, entry).await?);
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+        return Ok(());
+    }
+
+    if reports.is_empty() {
+        match target {
+            show::Target::Last => println!("No sleep entries found."),
+            show::Target::Id(id) => println!("No entry with id {}.", id),
+            show::Target::Date(date) => println!("No entry for the night of {}.", date),
+        }
+        return Ok(());
+    }
+    for report in &reports {
+        let date = report
+            .entry
+            .start_datetime()
+            .map(|start| start.format("%A %Y-%m-%d").to_string())
+            .unwrap_or_else(|_| report.entry.start.clone());
+        println!("\n🌙 Night of {}\n", date);
+        print!("{}", show::render_text(report));
+    }
+    println!();
+    Ok(())
+}
+
+/// Which entries `delete` removes.
+enum DeleteTarget {
+    /// Ask for a date
+    Prompt,
+    Id(i64),
+    Date(NaiveDate),
+    /// Inclusive range of start dates; open ends are unbounded
+    Range(Option<NaiveDate>, Option<NaiveDate>),
+}
+
+async fn delete_entry(
+    config: &Config,
+    target: DeleteTarget,
+    yes: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("\n╔═══════════════════════════════════╗");
+    println!("║      🗑️  Delete Sleep Entry       ║");
+    println!("╚═══════════════════════════════════╝\n");
+
+    // Connect to database
+    let storage = storage::open(config).await?;
+    let profile = storage.active_profile(config).await?;
+
+    let target = match target {
+        DeleteTarget::Prompt => {
+            // Prompt for date
+            let delete_date: NaiveDate = DateSelect::new("Select the date of the entry to delete:")
+                .with_default(Local::now().date_naive())
+                .prompt()?;
+            DeleteTarget::Date(delete_date)
+        }
+        target => target,
+    };
+
+    let format_date = |date: &NaiveDate| date.format("%Y-%m-%d").to_string();
+
+    let (candidates, description): (Vec<Entry>, String) = match &target {
+        DeleteTarget::Id(id) => (
+            storage.get(profile, *id).await?.into_iter().collect(),
+            format!("id: {}", id),
+        ),
+        DeleteTarget::Date(date) => (
+            storage.on_date(profile, *date).await?,
+            format!("date: {}", format_date(date)),
+        ),
+        DeleteTarget::Range(from, to) => {
+            let mut entries: Vec<Entry> = storage
+                .recent(profile, None)
+                .await?
+                .into_iter()
+                .filter(|entry| {
+                    let date = entry.start.get(..10).unwrap_or(&entry.start);
+                    from.is_none_or(|from| date >= format_date(&from).as_str())
+                        && to.is_none_or(|to| date <= format_date(&to).as_str())
+                })
+                .collect();
+            entries.reverse();
+            (
+                entries,
+                format!(
+                    "range: {} to {}",
+                    from.as_ref()
+                        .map(format_date)
+                        .unwrap_or_else(|| "…".to_string()),
+                    to.as_ref()
+                        .map(format_date)
+                        .unwrap_or_else(|| "…".to_string())
+                ),
+            )
+        }
+        DeleteTarget::Prompt => unreachable!(),
+    };
+
+    if candidates.is_empty() {
+        println!("❌ No entry found for {}", description);
+        return Ok(());
+    }
+
+    // Several entries on one night: let the user pick which ones go
+    let to_delete = if candidates.len() > 1 && matches!(target, DeleteTarget::Date(_)) && !yes {
+        MultiSelect::new(
+            "Several entries match. Which do you want to delete?",
+            candidates,
+        )
+        .prompt()?
+    } else {
+        candidates
+    };
+
+    if to_delete.is_empty() {
+        println!("Cancelled. No changes made.");
+        return Ok(());
+    }
+
+    println!("\n📋 Will delete {} entry(s):", to_delete.len());
+    for entry in &to_delete {
+        println!("   {}", entry);
+    }
+    println!();
+
+    let confirm = yes
+        || Confirm::new(&format!(
+            "Are you sure you want to delete {}?",
+            if to_delete.len() == 1 {
+                "this entry".to_string()
+            } else {
+                format!("these {} entries", to_delete.len())
+            }
+        ))
+        .with_default(false)
+        .prompt()?;
 
-    let _ = sqlx::migrate!().run(&pool).await;
+    if confirm {
+        storage::auto_backup(&storage, config, "delete").await?;
+        let ids: Vec<i64> = to_delete.iter().map(|entry| entry.id).collect();
+        storage.delete(&ids).await?;
+        println!(
+            "✓ Deleted {} entry(s) successfully!{}",
+            to_delete.len(),
+            // Only SQLite keeps the history to undo from
+            if storage.sqlite().is_some() {
+                " (undo with `slog undo`)"
+            } else {
+                ""
+            }
+        );
+    } else {
+        println!("Cancelled. No changes made.");
+    }
+
+    Ok(())
+}
+
+/// Where `record` takes its starting values from.
+enum Like {
+    /// The most recent entry before the chosen date
+    Last,
+    /// The entry recorded on a specific date
+    Date(NaiveDate),
+}
+
+/// The fields of a sleep entry, in the order they are prompted for.
+#[derive(Debug, Clone, Copy, PartialEq, EnumIter, strum_macros::Display)]
+enum Field {
+    #[strum(to_string = "Start time")]
+    StartTime,
+    #[strum(to_string = "Minutes to fall asleep")]
+    MinutesToFallAsleep,
+    #[strum(to_string = "Times woken up")]
+    AwakeCount,
+    #[strum(to_string = "Time awake")]
+    TimeAwake,
+    #[strum(to_string = "End time")]
+    EndTime,
+    #[strum(to_string = "Time in bed after waking")]
+    TimeInBedAfterWaking,
+    Quality,
+    Melatonin,
+    Benadryl,
+    Edible,
+    Exertion,
+    Notes,
+}
+
+/// A sleep session being recorded.
+#[derive(Debug, Clone)]
+struct SleepRecord {
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+    minutes_to_fall_asleep: i32,
+    awake_count: i16,
+    time_awake: i32,
+    time_in_bed_after_waking: i32,
+    quality: i8,
+    melatonin: f32,
+    benadryl: f32,
+    edible: f32,
+    exertion: i8,
+    notes: Option<String>,
+    profile_id: i64,
+}
+
+impl SleepRecord {
+    /// A new record of `profile` for the night starting on `date`, using the default times.
+    fn new(date: NaiveDate, defaults: &DefaultTimes, profile: i64) -> Self {
+        SleepRecord {
+            start: time_defaults::start_of_night(date, defaults.start, defaults.end),
+            end: next_day(date).and_time(defaults.end),
+            minutes_to_fall_asleep: 0,
+            awake_count: 0,
+            time_awake: 0,
+            time_in_bed_after_waking: 0,
+            quality: Quality::Okay.db_value(),
+            melatonin: 0.0,
+            benadryl: 0.0,
+            edible: 0.0,
+            exertion: Exertion::Normal.db_value(),
+            notes: None,
+            profile_id: profile,
+        }
+    }
+
+    /// A copy of an existing entry moved to the night starting on `date`.
+    /// Times keep their time of day and the duration in bed; notes are not copied.
+    fn from_row(
+        row: &Entry,
+        date: NaiveDate,
+        defaults: &DefaultTimes,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut record = Self::new(date, defaults, row.profile_id);
+        let row_start = NaiveDateTime::parse_from_str(&row.start, "%Y-%m-%d %H:%M:%S")?;
+        let row_end = match &row.end {
+            Some(end) => Some(NaiveDateTime::parse_from_str(end, "%Y-%m-%d %H:%M:%S")?),
+            None => None,
+        };
+
+        // Whole days from the template's night, so a start after midnight stays after midnight
+        let end_time = row_end.map_or(defaults.end, |end| end.time());
+        let shift = date - time_defaults::night_of(row_start, end_time);
+        record.start = row_start + shift;
+        if let Some(row_end) = row_end {
+            record.end = row_end + shift;
+        }
+
+        record.minutes_to_fall_asleep = row.minutes_to_fall_asleep.unwrap_or(0);
+        record.awake_count = row.awake_count.unwrap_or(0);
+        record.time_awake = row.time_awake.unwrap_or(0);
+        record.time_in_bed_after_waking = row.time_in_bed_after_waking.unwrap_or(0);
+        record.quality = row.quality.unwrap_or(0);
+        record.melatonin = row.melatonin.unwrap_or(0.0);
+        record.benadryl = row.benadryl.unwrap_or(0.0);
+        record.edible = row.edible.unwrap_or(0.0);
+        record.exertion = row.exertion.unwrap_or(0);
+        Ok(record)
+    }
+
+    /// The record as it would be stored, for validation.
+    fn to_entry(&self) -> Entry {
+        Entry {
+            id: 0,
+            start: self.start_str(),
+            end: Some(self.end_str()),
+            minutes_to_fall_asleep: Some(self.minutes_to_fall_asleep),
+            awake_count: Some(self.awake_count),
+            time_awake: Some(self.time_awake),
+            time_in_bed_after_waking: Some(self.time_in_bed_after_waking),
+            quality: Some(self.quality),
+            melatonin: Some(self.melatonin),
+            benadryl: Some(self.benadryl),
+            edible: Some(self.edible),
+            exertion: Some(self.exertion),
+            notes: self.notes.clone(),
+            source: None,
+            profile_id: self.profile_id,
+            uuid: None,
+            created_at: None,
+            updated_at: None,
+            origin: entry::MANUAL.to_string(),
+        }
+    }
+
+    fn start_str(&self) -> String {
+        self.start.format("%Y-%m-%d %H:%M:%S").to_string()
+    }
+
+    fn end_str(&self) -> String {
+        self.end.format("%Y-%m-%d %H:%M:%S").to_string()
+    }
+
+    /// Human readable value of a single field.
+    fn describe(&self, field: Field) -> String {
+        match field {
+            Field::StartTime => self.start.format("%Y-%m-%d %H:%M").to_string(),
+            Field::MinutesToFallAsleep => format!("{} min", self.minutes_to_fall_asleep),
+            Field::AwakeCount => self.awake_count.to_string(),
+            Field::TimeAwake => format!("{} min", self.time_awake),
+            Field::EndTime => self.end.format("%Y-%m-%d %H:%M").to_string(),
+            Field::TimeInBedAfterWaking => format!("{} min", self.time_in_bed_after_waking),
+            Field::Quality => Quality::from_db_value(self.quality)
+                .map(|q| q.to_string())
+                .unwrap_or_else(|| "Unknown".to_string()),
+            Field::Melatonin => format!("{} mg", self.melatonin),
+            Field::Benadryl => format!("{} mg", self.benadryl),
+            Field::Edible => format!("{} mg", self.edible),
+            Field::Exertion => Exertion::from_db_value(self.exertion)
+                .map(|e| e.to_string())
+                .unwrap_or_else(|| "Unknown".to_string()),
+            Field::Notes => self.notes.clone().unwrap_or_default(),
+        }
+    }
+}
+
+fn parse_hhmm(time: &str) -> Result<NaiveTime, chrono::ParseError> {
+    NaiveTime::parse_from_str(time, "%H:%M")
+}
+
+fn next_day(date: NaiveDate) -> NaiveDate {
+    date.checked_add_days(Days::new(1)).unwrap()
+}
+
+/// Parses `--since`: `14d`, `2w` or a date.
+fn parse_since(input: &str) -> Result<NaiveDate, String> {
+    if let Ok(date) = NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+        return Ok(date);
+    }
+
+    let invalid = || format!("Expected e.g. 14d, 2w or YYYY-MM-DD, got: {}", input);
+    let (number, unit) = input.split_at(input.len().saturating_sub(1));
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let days = match unit {
+        "d" => number,
+        "w" => number * 7,
+        _ => return Err(invalid()),
+    };
+    Local::now()
+        .date_naive()
+        .checked_sub_days(Days::new(days))
+        .ok_or_else(invalid)
+}
+
+// Prompts for a single field, using the record's current value as the default
+fn prompt_field(field: Field, record: &mut SleepRecord) -> InquireResult<()> {
+    match field {
+        Field::StartTime => {
+            let input: String = Text::new("Start Time (HH:MM)")
+                .with_default(&record.start.format("%H:%M").to_string())
+                .with_validator(|input: &str| validate_time_format(input))
+                .prompt()?;
+            let time = parse_hhmm(&input).expect("validated time");
+            // Same night, moved to the next day if the new start is after midnight
+            let end = record.end.time();
+            let night = time_defaults::night_of(record.start, end);
+            record.start = time_defaults::start_of_night(night, time, end);
+        }
+        Field::MinutesToFallAsleep => {
+            record.minutes_to_fall_asleep =
+                CustomType::<i32>::new("How many minutes did it take you to fall asleep?")
+                    .with_error_message("Please type a valid number")
+                    .with_help_message("Type a number")
+                    .with_default(record.minutes_to_fall_asleep)
+                    .prompt()?;
+        }
+        Field::AwakeCount => {
+            record.awake_count =
+                CustomType::<i16>::new("How many times did you wake up minus one?")
+                    .with_error_message("Please type a valid number")
+                    .with_help_message("Type a number")
+                    .with_default(record.awake_count)
+                    .prompt()?;
+        }
+        Field::TimeAwake => {
+            record.time_awake = CustomType::<i32>::new(
+                "How long were you awake if you add together the # of minutes awake?",
+            )
+            .with_error_message("Please type a valid number")
+            .with_help_message("Type a number in minutes")
+            .with_default(record.time_awake)
+            .prompt()?;
+        }
+        Field::EndTime => {
+            let input: String = Text::new("End Time (HH:MM)")
+                .with_default(&record.end.format("%H:%M").to_string())
+                .with_validator(|input: &str| validate_time_format(input))
+                .prompt()?;
+            let time = parse_hhmm(&input).expect("validated time");
+            // The first time it is `time` from the start on
+            let end = record.start.date().and_time(time);
+            record.end = if end < record.start {
+                next_day(end.date()).and_time(time)
+            } else {
+                end
+            };
+        }
+        Field::TimeInBedAfterWaking => {
+            record.time_in_bed_after_waking =
+                CustomType::<i32>::new("How long did you lie in bed after waking? (minutes)")
+                    .with_error_message("Please type a valid number")
+                    .with_help_message("Type a number in minutes")
+                    .with_default(record.time_in_bed_after_waking)
+                    .prompt()?;
+        }
+        Field::Quality => {
+            let quality_options: Vec<Quality> = Quality::iter().collect();
+            let quality = Select::new("Quality", quality_options)
+                .with_starting_cursor((record.quality.clamp(-2, 2) + 2) as usize)
+                .prompt()?;
+            record.quality = quality.db_value();
+        }
+        Field::Melatonin => {
+            record.melatonin = CustomType::<f32>::new("How much melatonin did you use? (mg)")
+                .with_error_message("Please type a valid number")
+                .with_help_message("Type a number")
+                .with_default(record.melatonin)
+                .prompt()?;
+        }
+        Field::Benadryl => {
+            record.benadryl = CustomType::<f32>::new("How much benadryl did you use? (mg)")
+                .with_error_message("Please type a valid number")
+                .with_help_message("Type a number")
+                .with_default(record.benadryl)
+                .prompt()?;
+        }
+        Field::Edible => {
+            record.edible = CustomType::<f32>::new("How much edible did you use? (mg)")
+                .with_error_message("Please type a valid number")
+                .with_help_message("Type a number")
+                .with_default(record.edible)
+                .prompt()?;
+        }
+        Field::Exertion => {
+            let exertion_options: Vec<Exertion> = Exertion::iter().collect();
+            let exertion = Select::new("Exertion", exertion_options)
+                .with_starting_cursor((record.exertion.clamp(-1, 1) + 1) as usize)
+                .prompt()?;
+            record.exertion = exertion.db_value();
+        }
+        Field::Notes => {
+            let notes: String = Text::new("Is there anything else you'd like to add?")
+                .with_default(record.notes.as_deref().unwrap_or(""))
+                .prompt()?;
+            record.notes = if notes.trim().is_empty() {
+                None
+            } else {
+                Some(notes.trim().to_string())
+            };
+        }
+    }
+
+    Ok(())
+}
+
+// Lets the user pick fields to change and prompts for each of them
+fn edit_fields(record: &mut SleepRecord) -> InquireResult<()> {
+    let to_edit = MultiSelect::new(
+        "Which fields do you want to change?",
+        Field::iter().collect(),
+    )
+    .prompt()?;
+    for field in to_edit {
+        prompt_field(field, record)?;
+    }
+    Ok(())
+}
+
+// Loads the entry a `--like`/`--like-last` record starts from
+async fn fetch_template(
+    storage: &impl Storage,
+    profile: i64,
+    like: &Like,
+    date: NaiveDate,
+) -> Result<Option<Entry>, StoreError> {
+    Ok(match like {
+        Like::Last => {
+            let date = date.format("%Y-%m-%d").to_string();
+            storage
+                .recent(profile, None)
+                .await?
+                .into_iter()
+                .find(|entry| entry.start.get(..10).unwrap_or(&entry.start) < date.as_str())
+        }
+        Like::Date(like_date) => storage.on_date(profile, *like_date).await?.pop(),
+    })
+}
+
+async fn record_sleep(
+    config: &Config,
+    like: Option<Like>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Display welcome message
+    println!("\n╔═══════════════════════════════════╗");
+    println!("║  🥱💤 SLOG (The Sleep Log) 💤🥱   ║");
+    println!("╚═══════════════════════════════════╝");
+    println!(
+        "                                v{}\n",
+        env!("CARGO_PKG_VERSION")
+    );
+
+    // Creates the database if it doesn't exist
+    let storage = storage::open(config).await?;
 
     let now = Local::now()
         .fixed_offset()
@@ -414,29 +2373,62 @@ async fn record_sleep() -> Result<(), Box<dyn std::error::Error>> {
                 .expect("Failed to get start date"),
         )
         .prompt()?;
-    let start_date_str = start_date.format("%Y-%m-%d").to_string();
-    let end_date = start_date.checked_add_days(Days::new(1)).unwrap();
-    let end_date_str = end_date.format("%Y-%m-%d").to_string();
-
-    let start_time_default = normalize_time_to_hhmm(&config.start_time_default);
-    let _start_time_input: String = Text::new("Start Time (HH:MM)")
-        .with_default(&start_time_default)
-        .with_validator(|input: &str| validate_time_format(input))
-        .prompt()?;
-    let _start_time = format!("{}:00", _start_time_input);
-    let start = format!("{start_date_str} {_start_time}");
 
-    // Check for existing entries on this date
-    let existing_query = "SELECT id, start, end FROM sleep WHERE DATE(start) = DATE(?)";
-    let existing: Option<(i64, String, Option<String>)> = sqlx::query_as(existing_query)
-        .bind(&start)
-        .fetch_optional(&pool)
-        .await?;
+    let profile = storage.active_profile(config).await?;
+    record_night(&storage, config, profile, start_date, like.as_ref()).await?;
+    Ok(())
+}
+
+/// Prompts for and saves the night of `profile` starting on `start_date`. Returns whether an
+/// entry was saved.
+async fn record_night(
+    storage: &impl Storage,
+    config: &Config,
+    profile: i64,
+    start_date: NaiveDate,
+    like: Option<&Like>,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let defaults = time_defaults::resolve(storage, profile, config, start_date).await?;
+
+    let mut record = match like {
+        Some(like) => match fetch_template(storage, profile, like, start_date).await? {
+            Some(row) => SleepRecord::from_row(&row, start_date, &defaults)?,
+            None => {
+                match like {
+                    Like::Last => println!("❌ No earlier entry found to copy from."),
+                    Like::Date(date) => {
+                        println!("❌ No entry found for date: {}", date.format("%Y-%m-%d"))
+                    }
+                }
+                return Ok(false);
+            }
+        },
+        None => {
+            println!(
+                "Default times {} → {} ({})",
+                defaults.start.format("%H:%M"),
+                defaults.end.format("%H:%M"),
+                defaults.strategy
+            );
+            let mut record = SleepRecord::new(start_date, &defaults, profile);
+            prompt_field(Field::StartTime, &mut record)?;
+            record
+        }
+    };
 
-    if let Some((existing_id, existing_start, existing_end)) = existing {
+    // Check for existing entries on this date
+    let existing = storage
+        .on_date(profile, record.start.date())
+        .await?
+        .into_iter()
+        .next();
+
+    // Only deleted together with saving the new entry, once all the prompts are done
+    let mut replace = None;
+    if let Some(existing) = existing {
         println!("\n⚠️  An entry already exists for this date:");
-        println!("   Start: {}", existing_start);
-        if let Some(end) = existing_end {
+        println!("   Start: {}", existing.start);
+        if let Some(end) = &existing.end {
             println!("   End: {}", end);
         }
 
@@ -446,132 +2438,121 @@ async fn record_sleep() -> Result<(), Box<dyn std::error::Error>> {
                 .prompt()?;
 
         if should_delete {
-            let delete_sql = "DELETE FROM sleep WHERE id = ?";
-            sqlx::query(delete_sql)
-                .bind(existing_id)
-                .execute(&pool)
-                .await?;
-            println!("✓ Existing entry deleted.");
+            replace = Some(existing.id);
         } else {
             println!("Cancelled. No changes made.");
-            return Ok(());
+            return Ok(false);
         }
     }
 
-    let minutes_to_fall_asleep =
-        CustomType::<i32>::new("How many minutes did it take you to fall asleep?")
-            .with_error_message("Please type a valid number")
-            .with_help_message("Type a number")
-            .with_default(0)
-            .prompt()
-            .expect("Failed");
-
-    let awake_count = CustomType::<i16>::new("How many times did you wake up minus one?")
-        .with_error_message("Please type a valid number")
-        .with_help_message("Type a number")
-        .with_default(0)
-        .prompt()
-        .expect("Failed");
-
-    let time_awake = CustomType::<i32>::new(
-        "How long were you awake if you add together the # of minutes awake?",
-    )
-    .with_error_message("Please type a valid number")
-    .with_help_message("Type a number in minutes")
-    .with_default(0)
-    .prompt()
-    .expect("Failed");
-
-    let end_time_default = normalize_time_to_hhmm(&config.end_time_default);
-    let _end_time_input: String = Text::new("End Time (HH:MM)")
-        .with_default(&end_time_default)
-        .with_validator(|input: &str| validate_time_format(input))
-        .prompt()?;
-    let _end_time = format!("{}:00", _end_time_input);
-    let end = format!("{end_date_str} {_end_time}");
-
-    let time_in_bed_after_waking =
-        CustomType::<i32>::new("How long did you lie in bed after waking? (minutes)")
-            .with_error_message("Please type a valid number")
-            .with_help_message("Type a number in minutes")
-            .with_default(0)
-            .prompt()
-            .expect("Failed");
-
-    let quality_options: Vec<Quality> = Quality::iter().collect();
-    let quality = Select::new("Quality", quality_options)
-        .with_starting_cursor(3)
-        .prompt()
-        .expect("failed to read Quality prompt.");
-
-    let melatonin = CustomType::<f32>::new("How much melatonin did you use? (mg)")
-        .with_error_message("Please type a valid number")
-        .with_help_message("Type a number")
-        .with_default(0.0)
-        .prompt()
-        .expect("Failed");
-
-    let benadryl = CustomType::<f32>::new("How much benadryl did you use? (mg)")
-        .with_error_message("Please type a valid number")
-        .with_help_message("Type a number")
-        .with_default(0.0)
-        .prompt()
-        .expect("Failed");
-
-    let edible = CustomType::<f32>::new("How much edible did you use? (mg)")
-        .with_error_message("Please type a valid number")
-        .with_help_message("Type a number")
-        .with_default(0.0)
-        .prompt()
-        .expect("Failed");
-
-    let exertion_options: Vec<Exertion> = Exertion::iter().collect();
-    let exertion: Exertion = Select::new("Exertion", exertion_options)
-        .with_starting_cursor(1)
-        .prompt()
-        .expect("failed to read Exertion prompt.");
-
-    let notes: String = Text::new("Is there anything else you'd like to add?")
-        .with_default("")
-        .prompt()?;
+    if like.is_some() {
+        println!("\n📋 Copied values:");
+        for field in Field::iter().filter(|field| *field != Field::Notes) {
+            println!("   {}: {}", field, record.describe(field));
+        }
+        println!();
 
-    let notes_value = if notes.trim().is_empty() {
-        None
+        edit_fields(&mut record)?;
     } else {
-        Some(notes.trim().to_string())
-    };
+        for field in Field::iter().skip(1) {
+            prompt_field(field, &mut record)?;
+        }
+    }
+
+    save_record(storage, config, record, replace).await
+}
+
+/// Validates and saves `record` in place of the entry with id `replace`, if any, offering to
+/// merge it with sessions it overlaps. Returns whether it was saved.
+async fn save_record(
+    storage: &impl Storage,
+    config: &Config,
+    mut record: SleepRecord,
+    replace: Option<i64>,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    // Same rules as `slog doctor`: impossible values have to be fixed, unlikely ones confirmed
+    loop {
+        let problems = record.to_entry().problems();
+        if problems.is_empty() {
+            break;
+        }
+
+        println!();
+        for problem in &problems {
+            println!("{}", problem);
+        }
 
-    let sql = "INSERT INTO sleep (start, minutes_to_fall_asleep, end, awake_count, time_awake, time_in_bed_after_waking, quality, melatonin, benadryl, edible, exertion, notes)
-        VALUES($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)";
-
-    let result = sqlx::query(sql)
-        .bind(&start)
-        .bind(minutes_to_fall_asleep)
-        .bind(&end)
-        .bind(awake_count)
-        .bind(time_awake)
-        .bind(time_in_bed_after_waking)
-        .bind(quality.db_value())
-        .bind(melatonin)
-        .bind(benadryl)
-        .bind(edible)
-        .bind(exertion.db_value())
-        .bind(&notes_value)
-        .execute(&pool)
-        .await;
+        let invalid = problems
+            .iter()
+            .any(|problem| problem.severity == Severity::Invalid);
+        if !invalid && Confirm::new("Save anyway?").with_default(false).prompt()? {
+            break;
+        }
+        edit_fields(&mut record)?;
+    }
+
+    let mut entry = record.to_entry();
+    if replace.is_some() {
+        storage::auto_backup(storage, config, "replace").await?;
+    }
+    let result = loop {
+        let saved = match replace {
+            Some(old) => storage
+                .save_night(&store::Save::Replace(entry.clone(), old), &[])
+                .await
+                .map(|_| ()),
+            None => storage.insert(&entry).await.map(|_| ()),
+        };
+        match saved {
+            Err(StoreError::Overlap(existing)) => {
+                println!("\n⚠️  This session overlaps:");
+                for other in &existing {
+                    println!("   {}", other);
+                }
+
+                let merged = store::merged(&entry, &existing);
+                let merge = format!(
+                    "Merge into one entry ({} → {})",
+                    merged.start,
+                    merged.end.as_deref().unwrap_or("N/A")
+                );
+                let edit = "Edit the times".to_string();
+                let cancel = "Cancel".to_string();
+                // A merge would keep the entry being replaced, so replacing only offers edits
+                let mut options = vec![edit.clone(), cancel];
+                if replace.is_none() {
+                    options.insert(0, merge.clone());
+                }
+                let choice = Select::new("What do you want to do?", options).prompt()?;
+
+                if choice == merge {
+                    storage::auto_backup(storage, config, "merge").await?;
+                    break storage.merge(&merged, &existing).await.map(|()| merged);
+                } else if choice == edit {
+                    edit_fields(&mut record)?;
+                    entry = record.to_entry();
+                } else {
+                    println!("Cancelled. No changes made.");
+                    return Ok(false);
+                }
+            }
+            result => break result.map(|_| entry),
+        }
+    };
 
     match result {
-        Ok(_) => {
+        Ok(saved) => {
             // Calculate total sleep time and efficiency
-            let start_dt = NaiveDateTime::parse_from_str(&start, "%Y-%m-%d %H:%M:%S")?;
-            let end_dt = NaiveDateTime::parse_from_str(&end, "%Y-%m-%d %H:%M:%S")?;
-            let total_time_in_bed = (end_dt - start_dt).num_minutes();
+            let total_time_in_bed = (record.end - record.start).num_minutes();
             let total_sleep_minutes = total_time_in_bed
-                - minutes_to_fall_asleep as i64
-                - time_awake as i64
-                - time_in_bed_after_waking as i64;
+                - record.minutes_to_fall_asleep as i64
+                - record.time_awake as i64
+                - record.time_in_bed_after_waking as i64;
             let sleep_efficiency = (total_sleep_minutes as f64 / total_time_in_bed as f64) * 100.0;
 
+            if replace.is_some() {
+                println!("✓ Existing entry replaced.");
+            }
             println!("Sleep data recorded successfully!");
             println!("\n=== Sleep Summary ===");
             println!(
@@ -580,28 +2561,188 @@ async fn record_sleep() -> Result<(), Box<dyn std::error::Error>> {
                 total_time_in_bed as f64 / 60.0
             );
             println!("Sleep efficiency: {:.1}%", sleep_efficiency);
+
+            print_goal_feedback(storage, config, &saved).await?;
         }
         Err(e) => {
-            eprintln!("Error executing SQL: {}", e);
-            eprintln!("SQL: {}", sql);
-            eprintln!(
-                "Parameters: start={}, minutes_to_fall_asleep={}, end={}, awake_count={}, time_awake={}, time_in_bed_after_waking={}, quality={}, melatonin={}, benadryl={}, edible={}, exertion={}, notes={:?}",
-                start,
-                minutes_to_fall_asleep,
-                end,
-                awake_count,
-                time_awake,
-                time_in_bed_after_waking,
-                quality.db_value(),
-                melatonin,
-                benadryl,
-                edible,
-                exertion.db_value(),
-                notes_value
-            );
-            return Err(Box::new(e));
+            eprintln!("Parameters: {:?}", record);
+            return Err(format!("Failed to save entry: {}", e).into());
         }
     }
 
-    Ok(())
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(dir: &Path, name: &str) -> Config {
+        Config {
+            db_file_path: dir.join(name).display().to_string(),
+            ..Config::default()
+        }
+    }
+
+    fn night(start: &str, end: &str) -> Entry {
+        Entry {
+            id: 0,
+            start: start.to_string(),
+            end: Some(end.to_string()),
+            minutes_to_fall_asleep: Some(10),
+            awake_count: None,
+            time_awake: None,
+            time_in_bed_after_waking: None,
+            quality: Some(1),
+            melatonin: None,
+            benadryl: None,
+            edible: None,
+            exertion: None,
+            notes: None,
+            source: None,
+            profile_id: 1,
+            uuid: None,
+            created_at: None,
+            updated_at: None,
+            origin: entry::MANUAL.to_string(),
+        }
+    }
+
+    #[test]
+    fn copies_keep_a_start_after_midnight_on_the_night() {
+        let defaults = DefaultTimes {
+            start: NaiveTime::from_hms_opt(23, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(7, 0, 0).unwrap(),
+            strategy: "static".to_string(),
+        };
+        let date = NaiveDate::from_ymd_opt(2026, 10, 18).unwrap();
+
+        let late = night("2026-10-11 00:30:00", "2026-10-11 07:30:00");
+        let record = SleepRecord::from_row(&late, date, &defaults).unwrap();
+        assert_eq!(record.start_str(), "2026-10-19 00:30:00");
+        assert_eq!(record.end_str(), "2026-10-19 07:30:00");
+
+        let early = night("2026-10-10 22:30:00", "2026-10-11 06:00:00");
+        let record = SleepRecord::from_row(&early, date, &defaults).unwrap();
+        assert_eq!(record.start_str(), "2026-10-18 22:30:00");
+        assert_eq!(record.end_str(), "2026-10-19 06:00:00");
+    }
+
+    /// Uuid, start and notes of the entries that aren't deleted.
+    async fn live(pool: &sqlx::SqlitePool) -> Vec<(String, String, Option<String>)> {
+        sync::changes(pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|change| change.deleted_at.is_none())
+            .map(|change| (change.uuid, change.start, change.notes))
+            .collect()
+    }
+
+    async fn sync(config: &Config, target: &Path) {
+        sync_with(config, target, sync::Resolution::Newest, false)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn sync_between_databases() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (scratch(dir.path(), "a.db"), scratch(dir.path(), "b.db"));
+        let (a_path, b_path) = (Path::new(&a.db_file_path), Path::new(&b.db_file_path));
+        let a_pool = db::connect(&a).await.unwrap();
+        let b_pool = db::connect(&b).await.unwrap();
+        store::insert(
+            &a_pool,
+            &night("2026-01-10 22:00:00", "2026-01-11 06:00:00"),
+        )
+        .await
+        .unwrap();
+        store::insert(
+            &b_pool,
+            &night("2026-01-11 22:30:00", "2026-01-12 06:30:00"),
+        )
+        .await
+        .unwrap();
+
+        sync(&a, b_path).await;
+        assert_eq!(live(&a_pool).await.len(), 2);
+        assert_eq!(live(&a_pool).await, live(&b_pool).await);
+
+        // b knows what was synced, so its own delete is no conflict when it syncs back
+        let (id,): (i64,) = sqlx::query_as("SELECT id FROM sleep WHERE start LIKE '2026-01-10%'")
+            .fetch_one(&b_pool)
+            .await
+            .unwrap();
+        history::soft_delete(&b_pool, id).await.unwrap();
+        let base = sync::base(&b_pool, &a_path.canonicalize().unwrap())
+            .await
+            .unwrap();
+        let plan = sync::plan(
+            sync::changes(&b_pool).await.unwrap(),
+            sync::changes(&a_pool).await.unwrap(),
+            &base,
+            true,
+            |conflict| panic!("unexpected conflict: {}", conflict.describe()),
+        )
+        .unwrap();
+        assert_eq!(plan.push.len(), 1);
+        assert!(plan.pull.is_empty());
+
+        sync(&b, a_path).await;
+        assert_eq!(live(&a_pool).await.len(), 1);
+        assert_eq!(live(&a_pool).await, live(&b_pool).await);
+
+        // Nothing left to exchange, from either side
+        let base = sync::base(&a_pool, &b_path.canonicalize().unwrap())
+            .await
+            .unwrap();
+        let plan = sync::plan(
+            sync::changes(&a_pool).await.unwrap(),
+            sync::changes(&b_pool).await.unwrap(),
+            &base,
+            true,
+            |conflict| panic!("unexpected conflict: {}", conflict.describe()),
+        )
+        .unwrap();
+        assert!(plan.pull.is_empty() && plan.push.is_empty());
+    }
+
+    #[tokio::test]
+    async fn sync_through_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (scratch(dir.path(), "a.db"), scratch(dir.path(), "b.db"));
+        let changes = dir.path().join("changes");
+        let a_pool = db::connect(&a).await.unwrap();
+        let b_pool = db::connect(&b).await.unwrap();
+        store::insert(
+            &a_pool,
+            &night("2026-01-10 22:00:00", "2026-01-11 06:00:00"),
+        )
+        .await
+        .unwrap();
+
+        sync(&a, &changes).await;
+        sync(&b, &changes).await;
+        assert_eq!(live(&b_pool).await.len(), 1);
+        assert_eq!(live(&a_pool).await, live(&b_pool).await);
+
+        let entry = store::recent(&b_pool, 1, None).await.unwrap().remove(0);
+        store::update(
+            &b_pool,
+            &Entry {
+                notes: Some("edited on b".to_string()),
+                ..entry
+            },
+        )
+        .await
+        .unwrap();
+        sync(&b, &changes).await;
+        sync(&a, &changes).await;
+        assert_eq!(live(&a_pool).await[0].2.as_deref(), Some("edited on b"));
+        assert_eq!(live(&a_pool).await, live(&b_pool).await);
+
+        let files = std::fs::read_dir(&changes).unwrap().count();
+        assert_eq!(files, 2, "one change file per database");
+    }
 }
@@ -0,0 +1,126 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::NaiveDate;
+use sqlx::{SqliteExecutor, SqlitePool};
+
+use crate::storage::Storage;
+use crate::store::StoreError;
+
+/// Why a night has no entry.
+#[derive(Debug, Clone, Copy, PartialEq, strum_macros::Display)]
+pub enum MarkerKind {
+    /// Slept as usual, but the details are forgotten
+    #[strum(to_string = "unknown")]
+    Unknown,
+    /// Didn't sleep here (travelling, night shift, ...)
+    #[strum(to_string = "didn't sleep here")]
+    Away,
+}
+
+impl MarkerKind {
+    pub fn db_value(&self) -> &'static str {
+        match self {
+            MarkerKind::Unknown => "unknown",
+            MarkerKind::Away => "away",
+        }
+    }
+
+    pub fn from_db_value(value: &str) -> Option<Self> {
+        match value {
+            "unknown" => Some(MarkerKind::Unknown),
+            "away" => Some(MarkerKind::Away),
+            _ => None,
+        }
+    }
+}
+
+/// Marks the night starting on `date` for `profile`, replacing an earlier marker.
+pub async fn set(
+    pool: &SqlitePool,
+    profile: i64,
+    date: NaiveDate,
+    kind: MarkerKind,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT OR REPLACE INTO night_markers (profile_id, date, kind) VALUES (?, ?, ?)")
+        .bind(profile)
+        .bind(date.format("%Y-%m-%d").to_string())
+        .bind(kind.db_value())
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Removes the marker for `date`, e.g. once a real entry has been recorded for it.
+pub async fn clear(
+    executor: impl SqliteExecutor<'_>,
+    profile: i64,
+    date: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM night_markers WHERE profile_id = ? AND date = ?")
+        .bind(profile)
+        .bind(date)
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
+/// Markers for the nights from `from` to `to`, both included.
+pub async fn between(
+    pool: &SqlitePool,
+    profile: i64,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<BTreeMap<NaiveDate, MarkerKind>, sqlx::Error> {
+    let rows: Vec<(String, String)> = sqlx::query_as(
+        "SELECT date, kind FROM night_markers
+         WHERE profile_id = ? AND date >= ? AND date <= ?
+         ORDER BY date",
+    )
+    .bind(profile)
+    .bind(from.format("%Y-%m-%d").to_string())
+    .bind(to.format("%Y-%m-%d").to_string())
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|(date, kind)| {
+            Some((
+                NaiveDate::parse_from_str(&date, "%Y-%m-%d").ok()?,
+                MarkerKind::from_db_value(&kind)?,
+            ))
+        })
+        .collect())
+}
+
+/// Nights from `from` to `to` (both included) with neither an entry starting on them nor a marker.
+pub async fn unlogged_nights(
+    storage: &impl Storage,
+    profile: i64,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<NaiveDate>, StoreError> {
+    let logged: BTreeSet<NaiveDate> = storage
+        .between(profile, from, to)
+        .await?
+        .iter()
+        .filter_map(|entry| entry.start_datetime().ok())
+        .map(|start| start.date())
+        .collect();
+    let marked = storage.markers(profile, from, to).await?;
+
+    Ok(from
+        .iter_days()
+        .take_while(|date| *date <= to)
+        .filter(|date| !logged.contains(date) && !marked.contains_key(date))
+        .collect())
+}
+
+/// Whether the night of `profile` starting on `date` has a marker.
+pub async fn is_marked(
+    storage: &impl Storage,
+    profile: i64,
+    date: NaiveDate,
+) -> Result<bool, StoreError> {
+    Ok(!storage.markers(profile, date, date).await?.is_empty())
+}
@@ -0,0 +1,458 @@
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+use sqlx::migrate::Migrator;
+use sqlx::postgres::{PgPool, PgRow};
+use sqlx::{PgExecutor, Row};
+
+use crate::entry::{DATETIME_FORMAT, Entry};
+use crate::markers::MarkerKind;
+use crate::profiles::{self, Profile};
+use crate::stages::{self, Span};
+use crate::storage::Storage;
+use crate::store::{self, Save, StoreError};
+
+/// The migrations in `./migrations/postgres`, embedded at compile time.
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations/postgres");
+
+/// `entry::COLUMNS` for Postgres, with the times in the format SQLite stores them in.
+const COLUMNS: &str = "id, to_char(start, 'YYYY-MM-DD HH24:MI:SS') AS start,
+    to_char(\"end\", 'YYYY-MM-DD HH24:MI:SS') AS \"end\", minutes_to_fall_asleep, awake_count,
+    time_awake, time_in_bed_after_waking, quality, melatonin, benadryl, edible, exertion, notes,
+    source, profile_id, uuid::text AS uuid,
+    to_char(created_at, 'YYYY-MM-DD HH24:MI:SS') AS created_at,
+    to_char(updated_at, 'YYYY-MM-DD HH24:MI:SS') AS updated_at, origin";
+
+/// A Postgres database shared by several people or machines. Deleting removes the entry for
+/// good; there is no history to undo from.
+pub struct PostgresStorage {
+    pool: PgPool,
+}
+
+impl PostgresStorage {
+    /// Connects to `url` and applies pending migrations.
+    pub async fn connect(url: &str) -> Result<Self, String> {
+        // The URL may contain a password, so it stays out of the messages
+        let pool = PgPool::connect(url)
+            .await
+            .map_err(|e| format!("Failed to connect to Postgres: {}", e))?;
+        MIGRATOR
+            .run(&pool)
+            .await
+            .map_err(|e| format!("Failed to apply Postgres migrations: {}", e))?;
+        Ok(PostgresStorage { pool })
+    }
+}
+
+/// Reads a row selected with `COLUMNS`; Postgres has no one-byte integers for `quality` and
+/// `exertion`.
+fn entry(row: PgRow) -> Result<Entry, sqlx::Error> {
+    Ok(Entry {
+        id: row.try_get("id")?,
+        start: row.try_get("start")?,
+        end: row.try_get("end")?,
+        minutes_to_fall_asleep: row.try_get("minutes_to_fall_asleep")?,
+        awake_count: row.try_get("awake_count")?,
+        time_awake: row.try_get("time_awake")?,
+        time_in_bed_after_waking: row.try_get("time_in_bed_after_waking")?,
+        quality: row
+            .try_get::<Option<i16>, _>("quality")?
+            .map(|quality| quality as i8),
+        melatonin: row.try_get("melatonin")?,
+        benadryl: row.try_get("benadryl")?,
+        edible: row.try_get("edible")?,
+        exertion: row
+            .try_get::<Option<i16>, _>("exertion")?
+            .map(|exertion| exertion as i8),
+        notes: row.try_get("notes")?,
+        source: row.try_get("source")?,
+        profile_id: row.try_get("profile_id")?,
+        uuid: row.try_get("uuid")?,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+        origin: row.try_get("origin")?,
+    })
+}
+
+/// Same as `store::overlapping`.
+async fn overlapping(
+    executor: impl PgExecutor<'_>,
+    entry: &Entry,
+    exclude: &[i64],
+) -> Result<Vec<Entry>, sqlx::Error> {
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM sleep
+         WHERE profile_id = $3
+           AND (start < $2::timestamp AND COALESCE(\"end\", start) > $1::timestamp
+                OR start = $1::timestamp)
+         ORDER BY start",
+        COLUMNS
+    ))
+    .bind(&entry.start)
+    .bind(entry.end.as_deref().unwrap_or(&entry.start))
+    .bind(entry.profile_id)
+    .fetch_all(executor)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(self::entry)
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .filter(|entry| !exclude.contains(&entry.id))
+        .collect())
+}
+
+/// Overwrites the entry with `entry.id`, refusing overlaps with anything but `exclude`.
+async fn update_in(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    entry: &Entry,
+    exclude: &[i64],
+) -> Result<(), StoreError> {
+    store::validate(entry)?;
+    let overlaps = overlapping(&mut **tx, entry, exclude).await?;
+    if !overlaps.is_empty() {
+        return Err(StoreError::Overlap(overlaps));
+    }
+
+    sqlx::query(
+        "UPDATE sleep SET start = $1::timestamp, minutes_to_fall_asleep = $2,
+             \"end\" = $3::timestamp, awake_count = $4, time_awake = $5,
+             time_in_bed_after_waking = $6, quality = $7, melatonin = $8, benadryl = $9,
+             edible = $10, exertion = $11, notes = $12, source = $13, updated_at = $14::timestamp
+         WHERE id = $15",
+    )
+    .bind(&entry.start)
+    .bind(entry.minutes_to_fall_asleep)
+    .bind(&entry.end)
+    .bind(entry.awake_count)
+    .bind(entry.time_awake)
+    .bind(entry.time_in_bed_after_waking)
+    .bind(entry.quality.map(i16::from))
+    .bind(entry.melatonin)
+    .bind(entry.benadryl)
+    .bind(entry.edible)
+    .bind(entry.exertion.map(i16::from))
+    .bind(&entry.notes)
+    .bind(&entry.source)
+    .bind(now())
+    .bind(entry.id)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Inserts `entry`, refusing overlaps, and drops the marker for its night. Returns the new id.
+async fn insert_in(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    entry: &Entry,
+) -> Result<i64, StoreError> {
+    store::validate(entry)?;
+    let overlaps = overlapping(&mut **tx, entry, &[]).await?;
+    if !overlaps.is_empty() {
+        return Err(StoreError::Overlap(overlaps));
+    }
+
+    let (id,): (i64,) = sqlx::query_as(
+        "INSERT INTO sleep (start, minutes_to_fall_asleep, \"end\", awake_count, time_awake,
+             time_in_bed_after_waking, quality, melatonin, benadryl, edible, exertion, notes,
+             source, profile_id, origin, created_at, updated_at)
+         VALUES ($1::timestamp, $2, $3::timestamp, $4, $5, $6, $7, $8, $9, $10, $11, $12,
+             $13, $14, $15, $16::timestamp, $16::timestamp)
+         RETURNING id",
+    )
+    .bind(&entry.start)
+    .bind(entry.minutes_to_fall_asleep)
+    .bind(&entry.end)
+    .bind(entry.awake_count)
+    .bind(entry.time_awake)
+    .bind(entry.time_in_bed_after_waking)
+    .bind(entry.quality.map(i16::from))
+    .bind(entry.melatonin)
+    .bind(entry.benadryl)
+    .bind(entry.edible)
+    .bind(entry.exertion.map(i16::from))
+    .bind(&entry.notes)
+    .bind(&entry.source)
+    .bind(entry.profile_id)
+    .bind(&entry.origin)
+    .bind(now())
+    .fetch_one(&mut **tx)
+    .await?;
+
+    // A real entry supersedes "unknown" or "didn't sleep here"
+    if let Some(date) = entry.start.get(..10) {
+        sqlx::query("DELETE FROM night_markers WHERE profile_id = $1 AND date = $2::date")
+            .bind(entry.profile_id)
+            .bind(date)
+            .execute(&mut **tx)
+            .await?;
+    }
+    Ok(id)
+}
+
+/// Overwrites the earliest of `existing` with `merged` and deletes the others.
+async fn merge_in(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    merged: &Entry,
+    existing: &[Entry],
+) -> Result<(), StoreError> {
+    let ids: Vec<i64> = existing.iter().map(|entry| entry.id).collect();
+    let others: Vec<i64> = ids.iter().copied().filter(|id| *id != merged.id).collect();
+    sqlx::query("DELETE FROM sleep WHERE id = ANY($1)")
+        .bind(&others)
+        .execute(&mut **tx)
+        .await?;
+    update_in(tx, merged, &ids).await
+}
+
+/// The time of a change: local, like the entries, rather than the server's clock.
+fn now() -> String {
+    chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// Starts a transaction in which no one else can change entries, so overlap checks hold until
+/// the change is committed.
+async fn lock(pool: &PgPool) -> Result<sqlx::Transaction<'_, sqlx::Postgres>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("LOCK TABLE sleep IN SHARE ROW EXCLUSIVE MODE")
+        .execute(&mut *tx)
+        .await?;
+    Ok(tx)
+}
+
+impl Storage for PostgresStorage {
+    fn kind(&self) -> &'static str {
+        "Postgres"
+    }
+
+    async fn profiles(&self) -> Result<Vec<Profile>, StoreError> {
+        Ok(sqlx::query_as(
+            "SELECT p.name, to_char(p.created_at, 'YYYY-MM-DD HH24:MI:SS') AS created_at,
+                    (SELECT COUNT(*) FROM sleep s WHERE s.profile_id = p.id) AS entries
+             FROM profiles p
+             ORDER BY p.id",
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn profile_id(&self, name: &str) -> Result<Option<i64>, StoreError> {
+        let row: Option<(i64,)> = sqlx::query_as("SELECT id FROM profiles WHERE name = $1")
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|(id,)| id))
+    }
+
+    async fn add_profile(&self, name: &str) -> Result<i64, StoreError> {
+        let name = profiles::check_name(name)?;
+        if self.profile_id(name).await?.is_some() {
+            return Err(profiles::exists(name).into());
+        }
+        let (id,): (i64,) = sqlx::query_as(
+            "INSERT INTO profiles (name, created_at) VALUES ($1, $2::timestamp) RETURNING id",
+        )
+        .bind(name)
+        .bind(now())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(id)
+    }
+
+    async fn rename_profile(&self, name: &str, new_name: &str) -> Result<(), StoreError> {
+        let new_name = profiles::check_name(new_name)?;
+        if self.profile_id(new_name).await?.is_some() {
+            return Err(profiles::exists(new_name).into());
+        }
+        let result = sqlx::query("UPDATE profiles SET name = $1 WHERE name = $2")
+            .bind(new_name)
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(format!("No profile named {}", name).into());
+        }
+        Ok(())
+    }
+
+    async fn remove_profile(&self, name: &str) -> Result<(), StoreError> {
+        let id = self
+            .profile_id(name)
+            .await?
+            .ok_or_else(|| format!("No profile named {}", name))?;
+        let (entries,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM sleep WHERE profile_id = $1")
+            .bind(id)
+            .fetch_one(&self.pool)
+            .await?;
+        if entries > 0 {
+            return Err(profiles::not_empty(name, entries).into());
+        }
+        let mut tx = self.pool.begin().await?;
+        for query in [
+            "DELETE FROM night_markers WHERE profile_id = $1",
+            "DELETE FROM profiles WHERE id = $1",
+        ] {
+            sqlx::query(query).bind(id).execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn recent(&self, profile: i64, limit: Option<usize>) -> Result<Vec<Entry>, StoreError> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM sleep WHERE profile_id = $1 ORDER BY start DESC LIMIT $2",
+            COLUMNS
+        ))
+        .bind(profile)
+        // LIMIT NULL is no limit
+        .bind(limit.map(|limit| limit as i64))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(entry).collect::<Result<_, _>>()?)
+    }
+
+    async fn get(&self, profile: i64, id: i64) -> Result<Option<Entry>, StoreError> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM sleep WHERE id = $1 AND profile_id = $2",
+            COLUMNS
+        ))
+        .bind(id)
+        .bind(profile)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.map(entry).transpose()?)
+    }
+
+    async fn insert(&self, entry: &Entry) -> Result<i64, StoreError> {
+        let mut tx = lock(&self.pool).await?;
+        let id = insert_in(&mut tx, entry).await?;
+        tx.commit().await?;
+        Ok(id)
+    }
+
+    async fn update(&self, entry: &Entry) -> Result<(), StoreError> {
+        let mut tx = lock(&self.pool).await?;
+        update_in(&mut tx, entry, &[entry.id]).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn merge(&self, merged: &Entry, existing: &[Entry]) -> Result<(), StoreError> {
+        let mut tx = lock(&self.pool).await?;
+        merge_in(&mut tx, merged, existing).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn delete(&self, ids: &[i64]) -> Result<(), StoreError> {
+        sqlx::query("DELETE FROM sleep WHERE id = ANY($1)")
+            .bind(ids)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn markers(
+        &self,
+        profile: i64,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<BTreeMap<NaiveDate, MarkerKind>, StoreError> {
+        let rows: Vec<(String, String)> = sqlx::query_as(
+            "SELECT to_char(date, 'YYYY-MM-DD'), kind FROM night_markers
+             WHERE profile_id = $1 AND date >= $2::date AND date <= $3::date
+             ORDER BY date",
+        )
+        .bind(profile)
+        .bind(from.format("%Y-%m-%d").to_string())
+        .bind(to.format("%Y-%m-%d").to_string())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|(date, kind)| {
+                Some((
+                    NaiveDate::parse_from_str(&date, "%Y-%m-%d").ok()?,
+                    MarkerKind::from_db_value(&kind)?,
+                ))
+            })
+            .collect())
+    }
+
+    async fn set_marker(
+        &self,
+        profile: i64,
+        date: NaiveDate,
+        kind: MarkerKind,
+    ) -> Result<(), StoreError> {
+        sqlx::query(
+            "INSERT INTO night_markers (profile_id, date, kind, created_at)
+             VALUES ($1, $2::date, $3, $4::timestamp)
+             ON CONFLICT (profile_id, date) DO UPDATE SET kind = EXCLUDED.kind",
+        )
+        .bind(profile)
+        .bind(date.format("%Y-%m-%d").to_string())
+        .bind(kind.db_value())
+        .bind(now())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn stages(&self, id: i64) -> Result<Vec<Span>, StoreError> {
+        let rows: Vec<(String, String, String)> = sqlx::query_as(
+            "SELECT stage, to_char(start, 'YYYY-MM-DD HH24:MI:SS'),
+                    to_char(\"end\", 'YYYY-MM-DD HH24:MI:SS')
+             FROM sleep_stages WHERE sleep_id = $1 ORDER BY start",
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .iter()
+            .filter_map(|(stage, start, end)| stages::span(stage, start, end))
+            .collect())
+    }
+
+    async fn save_night(&self, save: &Save, spans: &[Span]) -> Result<i64, StoreError> {
+        let mut tx = lock(&self.pool).await?;
+        let id = match save {
+            Save::Insert(entry) => insert_in(&mut tx, entry).await?,
+            Save::Update(entry) => {
+                update_in(&mut tx, entry, &[entry.id]).await?;
+                entry.id
+            }
+            Save::Merge(merged, existing) => {
+                merge_in(&mut tx, merged, existing).await?;
+                merged.id
+            }
+            Save::Replace(entry, old) => {
+                sqlx::query("DELETE FROM sleep WHERE id = $1")
+                    .bind(old)
+                    .execute(&mut *tx)
+                    .await?;
+                insert_in(&mut tx, entry).await?
+            }
+        };
+        if !spans.is_empty() {
+            sqlx::query("DELETE FROM sleep_stages WHERE sleep_id = $1")
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            for span in spans {
+                sqlx::query(
+                    "INSERT INTO sleep_stages (sleep_id, stage, start, \"end\")
+                     VALUES ($1, $2, $3::timestamp, $4::timestamp)",
+                )
+                .bind(id)
+                .bind(span.stage.db_value())
+                .bind(span.start.format(DATETIME_FORMAT).to_string())
+                .bind(span.end.format(DATETIME_FORMAT).to_string())
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+        tx.commit().await?;
+        Ok(id)
+    }
+}
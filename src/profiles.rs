@@ -0,0 +1,140 @@
+use sqlx::SqlitePool;
+
+use crate::config::Config;
+
+/// Profile every database starts with; entries from before profiles existed belong to it.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// A row of the `profiles` table, with the number of entries it has.
+#[derive(Debug, sqlx::FromRow)]
+pub struct Profile {
+    pub name: String,
+    pub created_at: String,
+    pub entries: i64,
+}
+
+/// Profiles in the order they were created.
+pub async fn list(pool: &SqlitePool) -> Result<Vec<Profile>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT p.name, p.created_at,
+                (SELECT COUNT(*) FROM sleep s
+                 WHERE s.profile_id = p.id AND s.deleted_at IS NULL) AS entries
+         FROM profiles p
+         ORDER BY p.id",
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Id of the profile called `name`, if there is one.
+pub async fn find(pool: &SqlitePool, name: &str) -> Result<Option<i64>, sqlx::Error> {
+    let row: Option<(i64,)> = sqlx::query_as("SELECT id FROM profiles WHERE name = ?")
+        .bind(name)
+        .fetch_optional(pool)
+        .await?;
+    Ok(row.map(|(id,)| id))
+}
+
+/// Id of the profile commands work on: `--profile`, or `default_profile` from the config.
+pub async fn active(pool: &SqlitePool, config: &Config) -> Result<i64, String> {
+    let name = &config.default_profile;
+    find(pool, name)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| missing(name))
+}
+
+/// Error for a profile that doesn't exist.
+pub fn missing(name: &str) -> String {
+    format!(
+        "No profile named {}. Create it with `slog profile add {}`",
+        name, name
+    )
+}
+
+/// Creates a profile and returns its id.
+pub async fn add(pool: &SqlitePool, name: &str) -> Result<i64, String> {
+    let name = check_name(name)?;
+    if find(pool, name).await.map_err(|e| e.to_string())?.is_some() {
+        return Err(exists(name));
+    }
+    let result = sqlx::query("INSERT INTO profiles (name) VALUES (?)")
+        .bind(name)
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(result.last_insert_rowid())
+}
+
+pub async fn rename(pool: &SqlitePool, name: &str, new_name: &str) -> Result<(), String> {
+    let new_name = check_name(new_name)?;
+    if find(pool, new_name)
+        .await
+        .map_err(|e| e.to_string())?
+        .is_some()
+    {
+        return Err(exists(new_name));
+    }
+    let result = sqlx::query("UPDATE profiles SET name = ? WHERE name = ?")
+        .bind(new_name)
+        .bind(name)
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    if result.rows_affected() == 0 {
+        return Err(format!("No profile named {}", name));
+    }
+    Ok(())
+}
+
+/// Removes a profile that has no entries left. Its deleted entries are purged with it.
+pub async fn remove(pool: &SqlitePool, name: &str) -> Result<(), String> {
+    let id = find(pool, name)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("No profile named {}", name))?;
+    let (entries,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM sleep WHERE profile_id = ? AND deleted_at IS NULL")
+            .bind(id)
+            .fetch_one(pool)
+            .await
+            .map_err(|e| e.to_string())?;
+    if entries > 0 {
+        return Err(not_empty(name, entries));
+    }
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+    for query in [
+        "DELETE FROM sleep WHERE profile_id = ?",
+        "DELETE FROM night_markers WHERE profile_id = ?",
+        "DELETE FROM profiles WHERE id = ?",
+    ] {
+        sqlx::query(query)
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    tx.commit().await.map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// `name` without surrounding whitespace, refusing empty names.
+pub fn check_name(name: &str) -> Result<&str, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Profile name must not be empty".to_string());
+    }
+    Ok(name)
+}
+
+pub fn exists(name: &str) -> String {
+    format!("Profile {} already exists", name)
+}
+
+pub fn not_empty(name: &str, entries: i64) -> String {
+    format!(
+        "Profile {} still has {} entry(s); delete them first",
+        name, entries
+    )
+}
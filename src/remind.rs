@@ -0,0 +1,190 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{Days, NaiveDate};
+
+use crate::config::{self, Config};
+use crate::markers;
+use crate::storage::Storage;
+use crate::store::StoreError;
+
+/// Name of the systemd units written by `slog remind install`.
+const UNIT_NAME: &str = "slog-remind";
+
+/// Whether `profile` has an entry or a marker for the night before `today`. A night is the date
+/// it started on, as for `record`'s duplicate check, `doctor`, `backfill` and `digest`.
+pub async fn has_entry_for_last_night(
+    storage: &impl Storage,
+    profile: i64,
+    today: NaiveDate,
+) -> Result<bool, StoreError> {
+    let yesterday = today - Days::new(1);
+    Ok(!storage.on_date(profile, yesterday).await?.is_empty()
+        || markers::is_marked(storage, profile, yesterday).await?)
+}
+
+/// Shows a desktop notification through `notify-send`.
+pub fn notify(summary: &str, body: &str) -> Result<(), String> {
+    let status = std::process::Command::new("notify-send")
+        .args(["--app-name=slog", "--icon=appointment-soon", summary, body])
+        .status()
+        .map_err(|e| format!("Failed to run notify-send: {}", e))?;
+    if !status.success() {
+        return Err(format!("notify-send exited with {}", status));
+    }
+    Ok(())
+}
+
+/// `$XDG_CONFIG_HOME/systemd/user`, where systemd looks for user units.
+pub fn unit_dir() -> Result<PathBuf, String> {
+    config::config_dir()
+        .map(|dir| dir.join("systemd").join("user"))
+        .ok_or_else(|| {
+            "Could not determine the config directory. Set XDG_CONFIG_HOME or HOME".into()
+        })
+}
+
+/// Writes `slog-remind.service` and `slog-remind.timer`, firing daily at `reminder_time`.
+/// The service runs this executable with the same config (and database, if overridden).
+/// Returns the files written.
+pub fn install(
+    config: &Config,
+    config_path: &Path,
+    db: Option<&Path>,
+    profile: Option<&str>,
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let exe = std::env::current_exe()
+        .map_err(|e| format!("Failed to find the slog executable: {}", e))?;
+
+    let mut command = format!(
+        "{} --config {}",
+        quote(&exe),
+        quote(&std::path::absolute(config_path)?)
+    );
+    if let Some(db) = db {
+        command.push_str(&format!(" --db {}", quote(&std::path::absolute(db)?)));
+    }
+    if let Some(profile) = profile {
+        command.push_str(&format!(" --profile {}", quote(Path::new(profile))));
+    }
+    command.push_str(" remind");
+
+    let service = format!(
+        "[Unit]\n\
+         Description=Remind to log last night's sleep\n\
+         \n\
+         [Service]\n\
+         Type=oneshot\n\
+         ExecStart={}\n",
+        command
+    );
+    let timer = format!(
+        "[Unit]\n\
+         Description=Daily reminder to log last night's sleep\n\
+         \n\
+         [Timer]\n\
+         OnCalendar=*-*-* {}:00\n\
+         Persistent=true\n\
+         \n\
+         [Install]\n\
+         WantedBy=timers.target\n",
+        config.reminder_time
+    );
+
+    let dir = unit_dir()?;
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+
+    let mut written = Vec::new();
+    for (extension, contents) in [("service", service), ("timer", timer)] {
+        let path = dir.join(format!("{}.{}", UNIT_NAME, extension));
+        std::fs::write(&path, contents)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// Name of the timer unit to enable after `install`.
+pub fn timer_name() -> String {
+    format!("{}.timer", UNIT_NAME)
+}
+
+/// Quotes a path for a systemd `ExecStart=` line.
+fn quote(path: &Path) -> String {
+    format!(
+        "\"{}\"",
+        path.to_string_lossy()
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::{self, Entry};
+    use crate::files::FileStorage;
+    use crate::markers::MarkerKind;
+
+    fn night(start: &str, end: &str) -> Entry {
+        Entry {
+            id: 0,
+            start: start.to_string(),
+            end: Some(end.to_string()),
+            minutes_to_fall_asleep: None,
+            awake_count: None,
+            time_awake: None,
+            time_in_bed_after_waking: None,
+            quality: None,
+            melatonin: None,
+            benadryl: None,
+            edible: None,
+            exertion: None,
+            notes: None,
+            source: None,
+            profile_id: 1,
+            uuid: None,
+            created_at: None,
+            updated_at: None,
+            origin: entry::MANUAL.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn last_night_is_the_night_that_started_yesterday() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = FileStorage::open(dir.path()).unwrap();
+        let date = |day| NaiveDate::from_ymd_opt(2026, 10, day).unwrap();
+
+        // Started after midnight: that is the night of the 12th
+        storage
+            .insert(&night("2026-10-12 00:30:00", "2026-10-12 07:00:00"))
+            .await
+            .unwrap();
+        assert!(
+            !has_entry_for_last_night(&storage, 1, date(12))
+                .await
+                .unwrap()
+        );
+        assert!(
+            has_entry_for_last_night(&storage, 1, date(13))
+                .await
+                .unwrap()
+        );
+
+        storage
+            .set_marker(1, date(13), MarkerKind::Away)
+            .await
+            .unwrap();
+        assert!(
+            has_entry_for_last_night(&storage, 1, date(14))
+                .await
+                .unwrap()
+        );
+        assert!(
+            !has_entry_for_last_night(&storage, 1, date(15))
+                .await
+                .unwrap()
+        );
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::BTreeMap;
+
+use chrono::{Days, NaiveDate};
+use serde::Serialize;
+use strum::IntoEnumIterator;
+
+use crate::digest::{self, Averages};
+use crate::entry::Entry;
+use crate::stages::{self, Stage};
+use crate::storage::Storage;
+use crate::store::StoreError;
+
+/// Nights before the shown one that its numbers are compared to.
+pub const AVERAGE_NIGHTS: u64 = 30;
+
+/// Which night(s) `slog show` prints.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    /// The most recent entry
+    Last,
+    Id(i64),
+    /// Every entry starting on this date
+    Date(NaiveDate),
+}
+
+/// Parses `last`, an entry id or a date (YYYY-MM-DD).
+pub fn parse_target(input: &str) -> Result<Target, String> {
+    if input.eq_ignore_ascii_case("last") {
+        return Ok(Target::Last);
+    }
+    if let Ok(id) = input.parse::<i64>() {
+        return Ok(Target::Id(id));
+    }
+    NaiveDate::parse_from_str(input, "%Y-%m-%d")
+        .map(Target::Date)
+        .map_err(|_| {
+            format!(
+                "expected last, an entry id or a date (YYYY-MM-DD), got {}",
+                input
+            )
+        })
+}
+
+/// Everything known about one entry, as printed by `slog show`.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub entry: Entry,
+    pub quality_name: String,
+    pub exertion_name: String,
+    pub time_in_bed_minutes: Option<i64>,
+    pub total_sleep_minutes: Option<i64>,
+    pub efficiency: Option<f64>,
+    /// Minutes per stage, empty without stage data
+    pub stages: BTreeMap<String, i64>,
+    #[serde(skip)]
+    pub hypnogram: Vec<String>,
+    pub problems: Vec<String>,
+    /// Averages over the `AVERAGE_NIGHTS` nights before this one
+    pub average: Averages,
+}
+
+/// The entries of `profile` that `target` refers to, oldest first; empty when there are none.
+pub async fn find(
+    storage: &impl Storage,
+    profile: i64,
+    target: Target,
+) -> Result<Vec<Entry>, StoreError> {
+    match target {
+        Target::Last => storage.recent(profile, Some(1)).await,
+        Target::Id(id) => Ok(storage.get(profile, id).await?.into_iter().collect()),
+        Target::Date(date) => storage.on_date(profile, date).await,
+    }
+}
+
+/// Builds the report for `entry`.
+pub async fn build(storage: &impl Storage, entry: Entry) -> Result<Report, StoreError> {
+    let spans = storage.stages(entry.id).await?;
+
+    let average = match entry.start_datetime() {
+        Ok(start) => {
+            let date = start.date();
+            let before = storage
+                .between(
+                    entry.profile_id,
+                    date - Days::new(AVERAGE_NIGHTS),
+                    date - Days::new(1),
+                )
+                .await?;
+            digest::averages(&before)
+        }
+        Err(_) => Averages::default(),
+    };
+
+    Ok(Report {
+        quality_name: entry.quality_name(),
+        exertion_name: entry.exertion_name(),
+        time_in_bed_minutes: entry.time_in_bed_minutes(),
+        total_sleep_minutes: entry.total_sleep_minutes(),
+        efficiency: entry.efficiency(),
+        stages: stages::totals(&spans)
+            .into_iter()
+            .map(|(stage, minutes)| (stage.to_string(), minutes))
+            .collect(),
+        hypnogram: stages::hypnogram(&spans, 60),
+        problems: entry
+            .problems()
+            .iter()
+            .map(|problem| problem.to_string())
+            .collect(),
+        average,
+        entry,
+    })
+}
+
+fn duration(minutes: i64) -> String {
+    format!("{}h {:02}m", minutes / 60, minutes % 60)
+}
+
+/// `(+12m vs 7h 28m)` against the average, empty without one.
+fn compared(value: Option<f64>, average: Option<f64>, percent: bool) -> String {
+    match (value, average) {
+        (Some(value), Some(average)) if percent => {
+            format!("({:+.1}% vs {:.1}%)", value - average, average)
+        }
+        (Some(value), Some(average)) => format!(
+            "({:+}m vs {})",
+            (value - average).round() as i64,
+            duration(average.round() as i64)
+        ),
+        _ => String::new(),
+    }
+}
+
+fn or_na(value: Option<&str>) -> String {
+    value.unwrap_or("N/A").to_string()
+}
+
+/// Labelled lines for the terminal.
+pub fn render_text(report: &Report) -> String {
+    let entry = &report.entry;
+    let minutes = |value: Option<i32>| value.map_or("N/A".to_string(), |m| format!("{} min", m));
+    let dose = |value: Option<f32>| value.map_or("N/A".to_string(), |mg| format!("{} mg", mg));
+
+    let mut rows: Vec<(&str, String)> = vec![
+        ("Entry", format!("#{}", entry.id)),
+        ("Went to bed", entry.start.clone()),
+        ("Got up", entry.end.clone().unwrap_or_else(|| "N/A".into())),
+        ("Time to fall asleep", minutes(entry.minutes_to_fall_asleep)),
+        (
+            "Times woken up",
+            entry
+                .awake_count
+                .map_or("N/A".to_string(), |count| count.to_string()),
+        ),
+        ("Time awake", minutes(entry.time_awake)),
+        (
+            "In bed after waking",
+            minutes(entry.time_in_bed_after_waking),
+        ),
+        ("Quality", report.quality_name.clone()),
+        ("Melatonin", dose(entry.melatonin)),
+        ("Benadryl", dose(entry.benadryl)),
+        ("Edible", dose(entry.edible)),
+        ("Exertion", report.exertion_name.clone()),
+        ("Notes", entry.notes.clone().unwrap_or_default()),
+        ("Source", entry.source_description()),
+        ("Created", or_na(entry.created_at.as_deref())),
+        ("Last changed", or_na(entry.updated_at.as_deref())),
+        ("UUID", or_na(entry.uuid.as_deref())),
+    ];
+    rows.push(("", String::new()));
+    rows.push((
+        "Time in bed",
+        format!(
+            "{} {}",
+            report.time_in_bed_minutes.map_or("N/A".into(), duration),
+            compared(
+                report.time_in_bed_minutes.map(|m| m as f64),
+                report.average.time_in_bed_minutes,
+                false
+            )
+        ),
+    ));
+    rows.push((
+        "Asleep",
+        format!(
+            "{} {}",
+            report.total_sleep_minutes.map_or("N/A".into(), duration),
+            compared(
+                report.total_sleep_minutes.map(|m| m as f64),
+                report.average.total_sleep_minutes,
+                false
+            )
+        ),
+    ));
+    rows.push((
+        "Efficiency",
+        format!(
+            "{} {}",
+            report
+                .efficiency
+                .map_or("N/A".into(), |efficiency| format!("{:.1}%", efficiency)),
+            compared(report.efficiency, report.average.efficiency, true)
+        ),
+    ));
+
+    let mut out = String::new();
+    for (label, value) in rows {
+        if label.is_empty() {
+            out.push('\n');
+        } else {
+            out.push_str(&format!("{:<21}{}\n", label, value.trim_end()));
+        }
+    }
+    if report.average.nights_logged > 0 {
+        out.push_str(&format!(
+            "(compared to the average of {} night(s) in the {} days before)\n",
+            report.average.nights_logged, AVERAGE_NIGHTS
+        ));
+    }
+
+    if !report.stages.is_empty() {
+        let staged: i64 = report.stages.values().sum();
+        out.push_str("\nSleep stages\n");
+        for (stage, minutes) in
+            Stage::iter().filter_map(|stage| Some((stage, report.stages.get(&stage.to_string())?)))
+        {
+            out.push_str(&format!(
+                "  {:<6} {:>8} ({:.0}%)\n",
+                stage,
+                duration(*minutes),
+                *minutes as f64 / staged.max(1) as f64 * 100.0
+            ));
+        }
+        out.push('\n');
+        for line in &report.hypnogram {
+            out.push_str(&format!("  {}\n", line.trim_end()));
+        }
+    }
+
+    if !report.problems.is_empty() {
+        out.push('\n');
+        for problem in &report.problems {
+            out.push_str(&format!("{}\n", problem));
+        }
+    }
+    out
+}
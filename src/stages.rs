@@ -0,0 +1,165 @@
+use std::collections::BTreeMap;
+
+use chrono::NaiveDateTime;
+use sqlx::SqliteExecutor;
+use strum::IntoEnumIterator;
+
+use crate::entry::DATETIME_FORMAT;
+
+/// A sleep stage as reported by trackers. The order is the hypnogram's, top to bottom.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    strum_macros::EnumIter,
+    strum_macros::Display,
+)]
+pub enum Stage {
+    Awake,
+    #[strum(to_string = "REM")]
+    Rem,
+    Light,
+    Deep,
+}
+
+impl Stage {
+    pub fn db_value(&self) -> &'static str {
+        match self {
+            Stage::Awake => "awake",
+            Stage::Rem => "rem",
+            Stage::Light => "light",
+            Stage::Deep => "deep",
+        }
+    }
+
+    fn from_db_value(value: &str) -> Option<Self> {
+        match value {
+            "awake" => Some(Stage::Awake),
+            "rem" => Some(Stage::Rem),
+            "light" => Some(Stage::Light),
+            "deep" => Some(Stage::Deep),
+            _ => None,
+        }
+    }
+}
+
+/// A continuous stretch of one stage.
+#[derive(Debug, Clone)]
+pub struct Span {
+    pub stage: Stage,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+}
+
+impl Span {
+    pub fn minutes(&self) -> i64 {
+        (self.end - self.start).num_minutes()
+    }
+}
+
+/// The stages of entry `sleep_id`, in order.
+pub async fn for_entry(
+    executor: impl SqliteExecutor<'_>,
+    sleep_id: i64,
+) -> Result<Vec<Span>, sqlx::Error> {
+    let rows: Vec<(String, String, String)> = sqlx::query_as(
+        "SELECT stage, start, end FROM sleep_stages WHERE sleep_id = ? ORDER BY start",
+    )
+    .bind(sleep_id)
+    .fetch_all(executor)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .filter_map(|(stage, start, end)| span(&stage, &start, &end))
+        .collect())
+}
+
+/// A span as stored: the stage's `db_value` and times in `DATETIME_FORMAT`.
+pub fn span(stage: &str, start: &str, end: &str) -> Option<Span> {
+    Some(Span {
+        stage: Stage::from_db_value(stage)?,
+        start: NaiveDateTime::parse_from_str(start, DATETIME_FORMAT).ok()?,
+        end: NaiveDateTime::parse_from_str(end, DATETIME_FORMAT).ok()?,
+    })
+}
+
+/// Replaces the stages of entry `sleep_id` with `spans`, as part of the transaction that saves
+/// the entry.
+pub async fn replace(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    sleep_id: i64,
+    spans: &[Span],
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM sleep_stages WHERE sleep_id = ?")
+        .bind(sleep_id)
+        .execute(&mut **tx)
+        .await?;
+    for span in spans {
+        sqlx::query("INSERT INTO sleep_stages (sleep_id, stage, start, end) VALUES (?, ?, ?, ?)")
+            .bind(sleep_id)
+            .bind(span.stage.db_value())
+            .bind(span.start.format(DATETIME_FORMAT).to_string())
+            .bind(span.end.format(DATETIME_FORMAT).to_string())
+            .execute(&mut **tx)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Minutes spent in each stage; stages that never occur are left out.
+pub fn totals(spans: &[Span]) -> BTreeMap<Stage, i64> {
+    let mut totals = BTreeMap::new();
+    for span in spans {
+        *totals.entry(span.stage).or_insert(0) += span.minutes();
+    }
+    totals
+}
+
+/// A text hypnogram `width` columns wide: one row per stage, a block wherever the night was in
+/// that stage, and the start and end times underneath.
+pub fn hypnogram(spans: &[Span], width: usize) -> Vec<String> {
+    let (Some(first), Some(last)) = (
+        spans.iter().map(|span| span.start).min(),
+        spans.iter().map(|span| span.end).max(),
+    ) else {
+        return Vec::new();
+    };
+    let seconds = (last - first).num_seconds().max(1) as f64;
+    let width = width.max(10);
+
+    // The stage at the middle of each column
+    let columns: Vec<Option<Stage>> = (0..width)
+        .map(|column| {
+            let offset = seconds * (column as f64 + 0.5) / width as f64;
+            let time = first + chrono::TimeDelta::seconds(offset as i64);
+            spans
+                .iter()
+                .find(|span| span.start <= time && time < span.end)
+                .map(|span| span.stage)
+        })
+        .collect();
+
+    let mut lines: Vec<String> = Stage::iter()
+        .map(|stage| {
+            let row: String = columns
+                .iter()
+                .map(|column| if *column == Some(stage) { '█' } else { ' ' })
+                .collect();
+            format!("{:<6}│{}", stage.to_string(), row)
+        })
+        .collect();
+    lines.push(format!("      └{}", "─".repeat(width)));
+    let start = first.format("%H:%M").to_string();
+    let end = last.format("%H:%M").to_string();
+    lines.push(format!(
+        "       {}{:>pad$}",
+        start,
+        end,
+        pad = width.saturating_sub(start.len())
+    ));
+    lines
+}
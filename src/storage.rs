@@ -0,0 +1,1085 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use sqlx::SqlitePool;
+
+use crate::config::Config;
+use crate::entry::Entry;
+use crate::files::FileStorage;
+use crate::markers::{self, MarkerKind};
+use crate::postgres::PostgresStorage;
+use crate::profiles::{self, Profile};
+use crate::stages::{self, Span};
+use crate::store::{self, Save, StoreError};
+use crate::{backup, db, history};
+
+/// Where entries, profiles, night markers and sleep stages are kept. SQLite is the default and
+/// the only storage with history, backups and sync, which work on its file.
+pub trait Storage {
+    /// Name of the storage for messages, e.g. `SQLite`.
+    // Only the conformance tests name the storage so far
+    #[cfg_attr(not(test), allow(dead_code))]
+    fn kind(&self) -> &'static str;
+
+    /// The SQLite database behind this storage, for the commands only SQLite supports.
+    fn sqlite(&self) -> Option<&SqlitePool> {
+        None
+    }
+
+    /// Profiles in the order they were created, with their number of entries.
+    async fn profiles(&self) -> Result<Vec<Profile>, StoreError>;
+
+    /// Id of the profile called `name`, if there is one.
+    async fn profile_id(&self, name: &str) -> Result<Option<i64>, StoreError>;
+
+    /// Creates a profile and returns its id.
+    async fn add_profile(&self, name: &str) -> Result<i64, StoreError>;
+
+    async fn rename_profile(&self, name: &str, new_name: &str) -> Result<(), StoreError>;
+
+    /// Removes a profile that has no entries left.
+    async fn remove_profile(&self, name: &str) -> Result<(), StoreError>;
+
+    /// Entries of `profile`, newest first; all of them when `limit` is `None`.
+    async fn recent(&self, profile: i64, limit: Option<usize>) -> Result<Vec<Entry>, StoreError>;
+
+    /// The entry of `profile` with this id.
+    async fn get(&self, profile: i64, id: i64) -> Result<Option<Entry>, StoreError>;
+
+    /// Inserts a new entry, refusing if it overlaps another of the same profile.
+    /// Returns the new id.
+    async fn insert(&self, entry: &Entry) -> Result<i64, StoreError>;
+
+    /// Overwrites the entry with `entry.id`, refusing if its new time range overlaps another.
+    async fn update(&self, entry: &Entry) -> Result<(), StoreError>;
+
+    /// Saves the result of `store::merged`: the earliest existing entry is overwritten and the
+    /// other existing entries are deleted.
+    async fn merge(&self, merged: &Entry, existing: &[Entry]) -> Result<(), StoreError>;
+
+    /// Deletes the entries with these ids, all of them or none.
+    async fn delete(&self, ids: &[i64]) -> Result<(), StoreError>;
+
+    /// Entries of `profile` starting on `date`, in order.
+    async fn on_date(&self, profile: i64, date: NaiveDate) -> Result<Vec<Entry>, StoreError> {
+        let date = date.format("%Y-%m-%d").to_string();
+        let mut entries: Vec<Entry> = self
+            .recent(profile, None)
+            .await?
+            .into_iter()
+            .filter(|entry| entry.start.starts_with(&date))
+            .collect();
+        entries.reverse();
+        Ok(entries)
+    }
+
+    /// Entries of `profile` starting from `from` to `to` (both included), in order.
+    async fn between(
+        &self,
+        profile: i64,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<Entry>, StoreError> {
+        let (from, to) = (
+            from.format("%Y-%m-%d").to_string(),
+            to.format("%Y-%m-%d").to_string(),
+        );
+        let mut entries: Vec<Entry> = self
+            .recent(profile, None)
+            .await?
+            .into_iter()
+            .filter(|entry| {
+                let date = entry.start.get(..10).unwrap_or(&entry.start);
+                from.as_str() <= date && date <= to.as_str()
+            })
+            .collect();
+        entries.reverse();
+        Ok(entries)
+    }
+
+    /// Entries of the same profile whose time range overlaps `entry`'s, by the rules of
+    /// `store::overlapping`, in order.
+    async fn overlapping(&self, entry: &Entry) -> Result<Vec<Entry>, StoreError> {
+        let mut entries: Vec<Entry> = self
+            .recent(entry.profile_id, None)
+            .await?
+            .into_iter()
+            .filter(|other| store::overlaps(other, &entry.start, entry.end.as_deref()))
+            .collect();
+        entries.reverse();
+        Ok(entries)
+    }
+
+    /// Markers of `profile` for the nights from `from` to `to`, both included.
+    async fn markers(
+        &self,
+        profile: i64,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<BTreeMap<NaiveDate, MarkerKind>, StoreError>;
+
+    /// Marks the night of `profile` starting on `date`, replacing an earlier marker. Inserting an
+    /// entry for the night removes the marker again.
+    async fn set_marker(
+        &self,
+        profile: i64,
+        date: NaiveDate,
+        kind: MarkerKind,
+    ) -> Result<(), StoreError>;
+
+    /// Stages of the entry with this id, in order; empty without stage data.
+    async fn stages(&self, id: i64) -> Result<Vec<Span>, StoreError>;
+
+    /// Makes the change `save` and replaces the saved entry's stages with `spans`, all or
+    /// nothing; without `spans` the entry keeps the stages it has. Returns the entry's id.
+    async fn save_night(&self, save: &Save, spans: &[Span]) -> Result<i64, StoreError>;
+
+    /// Id of the profile commands work on, like `profiles::active`.
+    async fn active_profile(&self, config: &Config) -> Result<i64, StoreError> {
+        let name = &config.default_profile;
+        self.profile_id(name)
+            .await?
+            .ok_or_else(|| StoreError::Other(profiles::missing(name)))
+    }
+}
+
+/// Storage selected by `db_url`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Location {
+    Postgres(String),
+    /// Directory with one JSON file per month
+    Files(PathBuf),
+}
+
+impl Location {
+    /// Parses `postgres://…`, `postgresql://…` or `file:DIR`.
+    pub fn parse(url: &str) -> Result<Self, String> {
+        let url = url.trim();
+        if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+            Ok(Location::Postgres(url.to_string()))
+        } else if let Some(dir) = url.strip_prefix("file:") {
+            let dir = dir.strip_prefix("//").unwrap_or(dir);
+            if dir.is_empty() {
+                return Err("file: needs a directory, e.g. file:/home/me/sleep".to_string());
+            }
+            Ok(Location::Files(PathBuf::from(dir)))
+        } else if url.starts_with("sqlite:") {
+            Err("SQLite is the default; set db_file_path instead of db_url".to_string())
+        } else {
+            Err("Expected postgres://… or file:DIR".to_string())
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Location::Postgres(_) => "Postgres",
+            Location::Files(_) => "flat-file",
+        }
+    }
+}
+
+/// The storage configured by `db_url`, or the SQLite database at `db_file_path`.
+pub enum Backend {
+    Sqlite(SqliteStorage),
+    Postgres(PostgresStorage),
+    Files(FileStorage),
+}
+
+/// Opens the configured storage, applying pending migrations.
+pub async fn open(config: &Config) -> Result<Backend, Box<dyn std::error::Error>> {
+    Ok(match &config.db_url {
+        None => Backend::Sqlite(SqliteStorage::new(db::connect(config).await?)),
+        Some(url) => match Location::parse(url)? {
+            Location::Postgres(url) => Backend::Postgres(PostgresStorage::connect(&url).await?),
+            Location::Files(dir) => Backend::Files(FileStorage::open(&dir)?),
+        },
+    })
+}
+
+/// Makes an automatic backup before a destructive change. Only SQLite databases are backed up
+/// by slog; Postgres and flat files have their own tools for that.
+pub async fn auto_backup(
+    storage: &impl Storage,
+    config: &Config,
+    reason: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(pool) = storage.sqlite() {
+        backup::auto_backup(pool, config, reason).await?;
+    }
+    Ok(())
+}
+
+macro_rules! dispatch {
+    ($backend:expr, $storage:ident => $call:expr) => {
+        match $backend {
+            Backend::Sqlite($storage) => $call,
+            Backend::Postgres($storage) => $call,
+            Backend::Files($storage) => $call,
+        }
+    };
+}
+
+impl Storage for Backend {
+    fn kind(&self) -> &'static str {
+        dispatch!(self, storage => storage.kind())
+    }
+
+    fn sqlite(&self) -> Option<&SqlitePool> {
+        dispatch!(self, storage => storage.sqlite())
+    }
+
+    async fn profiles(&self) -> Result<Vec<Profile>, StoreError> {
+        dispatch!(self, storage => storage.profiles().await)
+    }
+
+    async fn profile_id(&self, name: &str) -> Result<Option<i64>, StoreError> {
+        dispatch!(self, storage => storage.profile_id(name).await)
+    }
+
+    async fn add_profile(&self, name: &str) -> Result<i64, StoreError> {
+        dispatch!(self, storage => storage.add_profile(name).await)
+    }
+
+    async fn rename_profile(&self, name: &str, new_name: &str) -> Result<(), StoreError> {
+        dispatch!(self, storage => storage.rename_profile(name, new_name).await)
+    }
+
+    async fn remove_profile(&self, name: &str) -> Result<(), StoreError> {
+        dispatch!(self, storage => storage.remove_profile(name).await)
+    }
+
+    async fn recent(&self, profile: i64, limit: Option<usize>) -> Result<Vec<Entry>, StoreError> {
+        dispatch!(self, storage => storage.recent(profile, limit).await)
+    }
+
+    async fn get(&self, profile: i64, id: i64) -> Result<Option<Entry>, StoreError> {
+        dispatch!(self, storage => storage.get(profile, id).await)
+    }
+
+    async fn insert(&self, entry: &Entry) -> Result<i64, StoreError> {
+        dispatch!(self, storage => storage.insert(entry).await)
+    }
+
+    async fn update(&self, entry: &Entry) -> Result<(), StoreError> {
+        dispatch!(self, storage => storage.update(entry).await)
+    }
+
+    async fn merge(&self, merged: &Entry, existing: &[Entry]) -> Result<(), StoreError> {
+        dispatch!(self, storage => storage.merge(merged, existing).await)
+    }
+
+    async fn delete(&self, ids: &[i64]) -> Result<(), StoreError> {
+        dispatch!(self, storage => storage.delete(ids).await)
+    }
+
+    async fn on_date(&self, profile: i64, date: NaiveDate) -> Result<Vec<Entry>, StoreError> {
+        dispatch!(self, storage => storage.on_date(profile, date).await)
+    }
+
+    async fn between(
+        &self,
+        profile: i64,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<Entry>, StoreError> {
+        dispatch!(self, storage => storage.between(profile, from, to).await)
+    }
+
+    async fn overlapping(&self, entry: &Entry) -> Result<Vec<Entry>, StoreError> {
+        dispatch!(self, storage => storage.overlapping(entry).await)
+    }
+
+    async fn markers(
+        &self,
+        profile: i64,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<BTreeMap<NaiveDate, MarkerKind>, StoreError> {
+        dispatch!(self, storage => storage.markers(profile, from, to).await)
+    }
+
+    async fn set_marker(
+        &self,
+        profile: i64,
+        date: NaiveDate,
+        kind: MarkerKind,
+    ) -> Result<(), StoreError> {
+        dispatch!(self, storage => storage.set_marker(profile, date, kind).await)
+    }
+
+    async fn stages(&self, id: i64) -> Result<Vec<Span>, StoreError> {
+        dispatch!(self, storage => storage.stages(id).await)
+    }
+
+    async fn save_night(&self, save: &Save, spans: &[Span]) -> Result<i64, StoreError> {
+        dispatch!(self, storage => storage.save_night(save, spans).await)
+    }
+}
+
+/// The default storage: `store`, `profiles`, `markers`, `stages` and `history` on a SQLite
+/// database. Deleting is a soft delete that `slog undo` can revert.
+pub struct SqliteStorage {
+    pool: SqlitePool,
+}
+
+impl SqliteStorage {
+    pub fn new(pool: SqlitePool) -> Self {
+        SqliteStorage { pool }
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn kind(&self) -> &'static str {
+        "SQLite"
+    }
+
+    fn sqlite(&self) -> Option<&SqlitePool> {
+        Some(&self.pool)
+    }
+
+    async fn profiles(&self) -> Result<Vec<Profile>, StoreError> {
+        Ok(profiles::list(&self.pool).await?)
+    }
+
+    async fn profile_id(&self, name: &str) -> Result<Option<i64>, StoreError> {
+        Ok(profiles::find(&self.pool, name).await?)
+    }
+
+    async fn add_profile(&self, name: &str) -> Result<i64, StoreError> {
+        Ok(profiles::add(&self.pool, name).await?)
+    }
+
+    async fn rename_profile(&self, name: &str, new_name: &str) -> Result<(), StoreError> {
+        Ok(profiles::rename(&self.pool, name, new_name).await?)
+    }
+
+    async fn remove_profile(&self, name: &str) -> Result<(), StoreError> {
+        Ok(profiles::remove(&self.pool, name).await?)
+    }
+
+    async fn recent(&self, profile: i64, limit: Option<usize>) -> Result<Vec<Entry>, StoreError> {
+        Ok(store::recent(&self.pool, profile, limit).await?)
+    }
+
+    async fn get(&self, profile: i64, id: i64) -> Result<Option<Entry>, StoreError> {
+        Ok(store::get(&self.pool, profile, id).await?)
+    }
+
+    async fn insert(&self, entry: &Entry) -> Result<i64, StoreError> {
+        store::insert(&self.pool, entry).await
+    }
+
+    async fn update(&self, entry: &Entry) -> Result<(), StoreError> {
+        store::update(&self.pool, entry).await
+    }
+
+    async fn merge(&self, merged: &Entry, existing: &[Entry]) -> Result<(), StoreError> {
+        store::merge(&self.pool, merged, existing).await
+    }
+
+    async fn delete(&self, ids: &[i64]) -> Result<(), StoreError> {
+        Ok(history::soft_delete_all(&self.pool, ids).await?)
+    }
+
+    async fn on_date(&self, profile: i64, date: NaiveDate) -> Result<Vec<Entry>, StoreError> {
+        Ok(store::on_date(&self.pool, profile, date).await?)
+    }
+
+    async fn between(
+        &self,
+        profile: i64,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<Entry>, StoreError> {
+        Ok(store::between(&self.pool, profile, from, to).await?)
+    }
+
+    async fn overlapping(&self, entry: &Entry) -> Result<Vec<Entry>, StoreError> {
+        Ok(store::overlapping(
+            &self.pool,
+            entry.profile_id,
+            &entry.start,
+            entry.end.as_deref(),
+            &[],
+        )
+        .await?)
+    }
+
+    async fn markers(
+        &self,
+        profile: i64,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<BTreeMap<NaiveDate, MarkerKind>, StoreError> {
+        Ok(markers::between(&self.pool, profile, from, to).await?)
+    }
+
+    async fn set_marker(
+        &self,
+        profile: i64,
+        date: NaiveDate,
+        kind: MarkerKind,
+    ) -> Result<(), StoreError> {
+        Ok(markers::set(&self.pool, profile, date, kind).await?)
+    }
+
+    async fn stages(&self, id: i64) -> Result<Vec<Span>, StoreError> {
+        Ok(stages::for_entry(&self.pool, id).await?)
+    }
+
+    async fn save_night(&self, save: &Save, spans: &[Span]) -> Result<i64, StoreError> {
+        store::save(&self.pool, save, spans).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry;
+    use crate::stages::Stage;
+
+    /// Runs the checks every storage has to pass against `storage`, in profiles of their own that
+    /// are removed again afterwards, also when a check fails. Returns each check with its outcome;
+    /// the first failure ends the run, since later checks build on earlier ones.
+    async fn conformance(storage: &impl Storage) -> Vec<(&'static str, Result<(), String>)> {
+        let mut results = Vec::new();
+        let mut checks = Conformance {
+            storage,
+            profile: 0,
+            other: 0,
+            name: format!(
+                "conformance-{}",
+                chrono::Local::now().format("%Y%m%d%H%M%S")
+            ),
+        };
+        run_checks(&mut checks, &mut results).await;
+        if let Err(e) = checks.teardown().await {
+            results.push(("cleans up after itself", Err(e)));
+        }
+        results
+    }
+
+    async fn run_checks<S: Storage>(
+        checks: &mut Conformance<'_, S>,
+        results: &mut Vec<(&'static str, Result<(), String>)>,
+    ) {
+        macro_rules! check {
+            ($name:expr, $check:ident) => {
+                let result = checks.$check().await;
+                let failed = result.is_err();
+                results.push(($name, result));
+                if failed {
+                    return;
+                }
+            };
+        }
+        check!("creates and finds profiles", profiles);
+        check!("reads back an entry unchanged", round_trip);
+        check!("refuses overlapping entries", overlap);
+        check!("refuses invalid entries", invalid);
+        check!("allows entries that only touch", touching);
+        check!("lists newest first and honours the limit", order);
+        check!("finds entries by date", by_date);
+        check!("keeps profiles apart", isolation);
+        check!("updates entries, keeping their uuid", updates);
+        check!("merges entries", merges);
+        check!("keeps night markers", markers);
+        check!("saves stages with the night", stages);
+        check!("replaces a night, all or nothing", replaces);
+        check!("deletes entries", deletes);
+        check!("renames and removes profiles", cleanup);
+    }
+
+    struct Conformance<'a, S> {
+        storage: &'a S,
+        /// Profile the checks write to
+        profile: i64,
+        /// Second profile, for `isolation`
+        other: i64,
+        name: String,
+    }
+
+    /// Fails a conformance check with `message` unless `condition` holds.
+    fn ensure(condition: bool, message: impl FnOnce() -> String) -> Result<(), String> {
+        if condition { Ok(()) } else { Err(message()) }
+    }
+
+    impl<S: Storage> Conformance<'_, S> {
+        fn night(&self, start: &str, end: &str) -> Entry {
+            Entry {
+                id: 0,
+                start: start.to_string(),
+                end: Some(end.to_string()),
+                minutes_to_fall_asleep: None,
+                awake_count: None,
+                time_awake: None,
+                time_in_bed_after_waking: None,
+                quality: None,
+                melatonin: None,
+                benadryl: None,
+                edible: None,
+                exertion: None,
+                notes: None,
+                source: None,
+                profile_id: self.profile,
+                uuid: None,
+                created_at: None,
+                updated_at: None,
+                origin: entry::MANUAL.to_string(),
+            }
+        }
+
+        async fn all(&self) -> Result<Vec<Entry>, String> {
+            self.storage
+                .recent(self.profile, None)
+                .await
+                .map_err(|e| e.to_string())
+        }
+
+        async fn profiles(&mut self) -> Result<(), String> {
+            let storage = self.storage;
+            self.profile = storage
+                .add_profile(&self.name)
+                .await
+                .map_err(|e| e.to_string())?;
+            self.other = storage
+                .add_profile(&format!("{}-other", self.name))
+                .await
+                .map_err(|e| e.to_string())?;
+            let found = storage
+                .profile_id(&self.name)
+                .await
+                .map_err(|e| e.to_string())?;
+            ensure(found == Some(self.profile), || {
+                format!("found {:?} instead of {}", found, self.profile)
+            })?;
+            ensure(storage.add_profile(&self.name).await.is_err(), || {
+                "added the same profile twice".to_string()
+            })?;
+            let listed = storage.profiles().await.map_err(|e| e.to_string())?;
+            ensure(
+                listed.iter().any(|profile| profile.name == self.name),
+                || "new profile not listed".to_string(),
+            )
+        }
+
+        async fn round_trip(&mut self) -> Result<(), String> {
+            let entry = Entry {
+                minutes_to_fall_asleep: Some(15),
+                awake_count: Some(2),
+                time_awake: Some(20),
+                time_in_bed_after_waking: Some(5),
+                quality: Some(-2),
+                melatonin: Some(0.5),
+                benadryl: Some(25.0),
+                edible: Some(2.5),
+                exertion: Some(1),
+                notes: Some("ünïcode, \"quotes\"; and\nnew lines".to_string()),
+                source: Some("oura".to_string()),
+                origin: entry::IMPORT.to_string(),
+                ..self.night("2026-01-10 22:30:00", "2026-01-11 06:45:00")
+            };
+            let id = self
+                .storage
+                .insert(&entry)
+                .await
+                .map_err(|e| e.to_string())?;
+            let stored = self
+                .storage
+                .get(self.profile, id)
+                .await
+                .map_err(|e| e.to_string())?
+                .ok_or("inserted entry not found")?;
+            ensure(
+                stored.uuid.as_ref().is_some_and(|uuid| uuid.len() == 36)
+                    && stored.created_at.is_some()
+                    && stored.updated_at.is_some(),
+                || format!("no uuid or save times: {:?}", stored),
+            )?;
+            let expected = Entry {
+                id,
+                uuid: stored.uuid.clone(),
+                created_at: stored.created_at.clone(),
+                updated_at: stored.updated_at.clone(),
+                ..entry
+            };
+            ensure(format!("{:?}", stored) == format!("{:?}", expected), || {
+                format!("stored {:?}, expected {:?}", stored, expected)
+            })
+        }
+
+        async fn overlap(&mut self) -> Result<(), String> {
+            for (start, end) in [
+                ("2026-01-11 06:00:00", "2026-01-11 07:00:00"),
+                ("2026-01-10 22:30:00", "2026-01-10 23:00:00"),
+                ("2026-01-10 20:00:00", "2026-01-11 09:00:00"),
+            ] {
+                match self.storage.insert(&self.night(start, end)).await {
+                    Err(StoreError::Overlap(existing)) if existing.len() == 1 => {}
+                    other => return Err(format!("{} → {}: {:?}", start, end, other)),
+                }
+            }
+            Ok(())
+        }
+
+        async fn invalid(&mut self) -> Result<(), String> {
+            let before = self.all().await?;
+            let backwards = self.night("2026-02-10 22:00:00", "2026-02-10 21:00:00");
+            match self.storage.insert(&backwards).await {
+                Err(StoreError::Invalid(_)) => {}
+                other => return Err(format!("inserting an end before the start: {:?}", other)),
+            }
+            let out_of_range = Entry {
+                quality: Some(9),
+                ..before[0].clone()
+            };
+            match self.storage.update(&out_of_range).await {
+                Err(StoreError::Invalid(_)) => {}
+                other => return Err(format!("updating quality to 9: {:?}", other)),
+            }
+            let summary = |entries: &[Entry]| -> Vec<(i64, String, Option<String>, Option<i8>)> {
+                entries
+                    .iter()
+                    .map(|entry| {
+                        (
+                            entry.id,
+                            entry.start.clone(),
+                            entry.end.clone(),
+                            entry.quality,
+                        )
+                    })
+                    .collect()
+            };
+            let after = self.all().await?;
+            ensure(summary(&after) == summary(&before), || {
+                format!("stored anyway: {:?}", after)
+            })
+        }
+
+        async fn touching(&mut self) -> Result<(), String> {
+            self.storage
+                .insert(&self.night("2026-01-11 06:45:00", "2026-01-11 08:00:00"))
+                .await
+                .map_err(|e| e.to_string())?;
+            self.storage
+                .insert(&self.night("2026-01-12 23:00:00", "2026-01-13 07:00:00"))
+                .await
+                .map_err(|e| e.to_string())?;
+            Ok(())
+        }
+
+        async fn order(&mut self) -> Result<(), String> {
+            let starts: Vec<String> = self.all().await?.into_iter().map(|e| e.start).collect();
+            ensure(
+                starts
+                    == [
+                        "2026-01-12 23:00:00",
+                        "2026-01-11 06:45:00",
+                        "2026-01-10 22:30:00",
+                    ],
+                || format!("listed {:?}", starts),
+            )?;
+            let limited = self
+                .storage
+                .recent(self.profile, Some(1))
+                .await
+                .map_err(|e| e.to_string())?;
+            ensure(limited.len() == 1 && limited[0].start == starts[0], || {
+                format!("limit 1 gave {:?}", limited)
+            })
+        }
+
+        async fn by_date(&mut self) -> Result<(), String> {
+            let date = NaiveDate::from_ymd_opt(2026, 1, 11).expect("valid date");
+            let found = self
+                .storage
+                .on_date(self.profile, date)
+                .await
+                .map_err(|e| e.to_string())?;
+            ensure(
+                found.len() == 1 && found[0].start == "2026-01-11 06:45:00",
+                || format!("found {:?}", found),
+            )?;
+            let found = self
+                .storage
+                .between(self.profile, date - chrono::Days::new(1), date)
+                .await
+                .map_err(|e| e.to_string())?;
+            let starts: Vec<&str> = found.iter().map(|entry| entry.start.as_str()).collect();
+            ensure(
+                starts == ["2026-01-10 22:30:00", "2026-01-11 06:45:00"],
+                || format!("between found {:?}", starts),
+            )
+        }
+
+        async fn isolation(&mut self) -> Result<(), String> {
+            let entry = Entry {
+                profile_id: self.other,
+                ..self.night("2026-01-10 22:30:00", "2026-01-11 06:45:00")
+            };
+            let id = self
+                .storage
+                .insert(&entry)
+                .await
+                .map_err(|e| format!("same night in another profile: {}", e))?;
+            ensure(self.all().await?.len() == 3, || {
+                "another profile's entry listed".to_string()
+            })?;
+            let found = self
+                .storage
+                .get(self.profile, id)
+                .await
+                .map_err(|e| e.to_string())?;
+            ensure(found.is_none(), || {
+                "another profile's entry found by id".to_string()
+            })?;
+            self.storage.delete(&[id]).await.map_err(|e| e.to_string())
+        }
+
+        async fn updates(&mut self) -> Result<(), String> {
+            let before = self.all().await?.remove(0);
+            let mut entry = Entry {
+                start: "2026-01-12 22:00:00".to_string(),
+                notes: Some("moved".to_string()),
+                ..before.clone()
+            };
+            self.storage
+                .update(&entry)
+                .await
+                .map_err(|e| e.to_string())?;
+            let stored = self
+                .storage
+                .get(self.profile, entry.id)
+                .await
+                .map_err(|e| e.to_string())?;
+            ensure(
+                stored
+                    .as_ref()
+                    .map(|e| (e.start.as_str(), e.notes.as_deref()))
+                    == Some(("2026-01-12 22:00:00", Some("moved"))),
+                || format!("stored {:?}", stored),
+            )?;
+            ensure(
+                stored.as_ref().is_some_and(|stored| {
+                    (&stored.uuid, &stored.created_at, &stored.origin)
+                        == (&before.uuid, &before.created_at, &before.origin)
+                        && stored.updated_at >= before.updated_at
+                }),
+                || format!("uuid, creation time or origin changed: {:?}", stored),
+            )?;
+
+            entry.start = "2026-01-11 07:00:00".to_string();
+            match self.storage.update(&entry).await {
+                Err(StoreError::Overlap(_)) => Ok(()),
+                other => Err(format!("overlapping update gave {:?}", other)),
+            }
+        }
+
+        async fn merges(&mut self) -> Result<(), String> {
+            let existing: Vec<Entry> = self
+                .all()
+                .await?
+                .into_iter()
+                .filter(|entry| entry.start.as_str() < "2026-01-12")
+                .rev()
+                .collect();
+            ensure(existing.len() == 2, || {
+                format!("expected 2 entries, got {:?}", existing)
+            })?;
+            let entry = self.night("2026-01-10 22:00:00", "2026-01-11 07:30:00");
+            let merged = store::merged(&entry, &existing);
+            self.storage
+                .merge(&merged, &existing)
+                .await
+                .map_err(|e| e.to_string())?;
+
+            let starts: Vec<String> = self.all().await?.into_iter().map(|e| e.start).collect();
+            ensure(
+                starts == ["2026-01-12 22:00:00", "2026-01-10 22:00:00"],
+                || format!("after merging: {:?}", starts),
+            )
+        }
+
+        async fn markers(&mut self) -> Result<(), String> {
+            let storage = self.storage;
+            let date = |day| NaiveDate::from_ymd_opt(2026, 1, day).expect("valid date");
+            for (day, kind) in [
+                (14, MarkerKind::Unknown),
+                (15, MarkerKind::Unknown),
+                (14, MarkerKind::Away),
+            ] {
+                storage
+                    .set_marker(self.profile, date(day), kind)
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+            let marked = storage
+                .markers(self.profile, date(13), date(16))
+                .await
+                .map_err(|e| e.to_string())?;
+            ensure(
+                marked
+                    == BTreeMap::from([
+                        (date(14), MarkerKind::Away),
+                        (date(15), MarkerKind::Unknown),
+                    ]),
+                || format!("marked {:?}", marked),
+            )?;
+            let other = storage
+                .markers(self.other, date(13), date(16))
+                .await
+                .map_err(|e| e.to_string())?;
+            ensure(other.is_empty(), || {
+                format!("another profile's markers found: {:?}", other)
+            })?;
+
+            // A real entry for the night replaces its marker
+            storage
+                .insert(&self.night("2026-01-15 23:00:00", "2026-01-16 07:00:00"))
+                .await
+                .map_err(|e| e.to_string())?;
+            let marked = storage
+                .markers(self.profile, date(13), date(16))
+                .await
+                .map_err(|e| e.to_string())?;
+            ensure(
+                marked == BTreeMap::from([(date(14), MarkerKind::Away)]),
+                || format!("marked after inserting {:?}", marked),
+            )
+        }
+
+        async fn stages(&mut self) -> Result<(), String> {
+            let time = |value| {
+                chrono::NaiveDateTime::parse_from_str(value, entry::DATETIME_FORMAT)
+                    .expect("valid time")
+            };
+            let spans = vec![
+                Span {
+                    stage: Stage::Light,
+                    start: time("2026-01-16 23:00:00"),
+                    end: time("2026-01-17 01:00:00"),
+                },
+                Span {
+                    stage: Stage::Deep,
+                    start: time("2026-01-17 01:00:00"),
+                    end: time("2026-01-17 06:30:00"),
+                },
+            ];
+            let summary = |spans: &[Span]| -> Vec<(Stage, i64)> {
+                spans
+                    .iter()
+                    .map(|span| (span.stage, span.minutes()))
+                    .collect()
+            };
+
+            let night = self.night("2026-01-16 23:00:00", "2026-01-17 06:30:00");
+            let id = self
+                .storage
+                .save_night(&Save::Insert(night), &spans)
+                .await
+                .map_err(|e| e.to_string())?;
+            let stored = self.storage.stages(id).await.map_err(|e| e.to_string())?;
+            ensure(summary(&stored) == summary(&spans), || {
+                format!("stored {:?}", stored)
+            })?;
+
+            // Saving the entry again without stages keeps the ones it has
+            let entry = self
+                .storage
+                .get(self.profile, id)
+                .await
+                .map_err(|e| e.to_string())?
+                .ok_or("night with stages not found")?;
+            self.storage
+                .save_night(
+                    &Save::Update(Entry {
+                        quality: Some(1),
+                        ..entry
+                    }),
+                    &[],
+                )
+                .await
+                .map_err(|e| e.to_string())?;
+            let stored = self.storage.stages(id).await.map_err(|e| e.to_string())?;
+            ensure(summary(&stored) == summary(&spans), || {
+                format!("after updating: {:?}", stored)
+            })?;
+
+            // Nothing is saved when the entry is refused
+            let overlapping = self.night("2026-01-17 06:00:00", "2026-01-17 08:00:00");
+            match self
+                .storage
+                .save_night(&Save::Insert(overlapping), &spans)
+                .await
+            {
+                Err(StoreError::Overlap(_)) => Ok(()),
+                other => Err(format!("overlapping night with stages gave {:?}", other)),
+            }
+        }
+
+        async fn replaces(&mut self) -> Result<(), String> {
+            let old = self.night("2026-01-20 23:00:00", "2026-01-21 06:30:00");
+            let old = self.storage.insert(&old).await.map_err(|e| e.to_string())?;
+
+            // A refused replacement leaves the old night in place
+            let invalid = self.night("2026-01-20 23:30:00", "2026-01-20 22:00:00");
+            if self
+                .storage
+                .save_night(&Save::Replace(invalid, old), &[])
+                .await
+                .is_ok()
+            {
+                return Err("saved an invalid replacement".to_string());
+            }
+            let kept = self.storage.get(self.profile, old).await;
+            ensure(matches!(kept, Ok(Some(_))), || {
+                format!("after a refused replacement: {:?}", kept)
+            })?;
+
+            // The new night may overlap the one it replaces
+            let new = self.night("2026-01-20 23:30:00", "2026-01-21 07:00:00");
+            let id = self
+                .storage
+                .save_night(&Save::Replace(new, old), &[])
+                .await
+                .map_err(|e| e.to_string())?;
+            let date = NaiveDate::from_ymd_opt(2026, 1, 20).unwrap();
+            let found = self
+                .storage
+                .on_date(self.profile, date)
+                .await
+                .map_err(|e| e.to_string())?;
+            ensure(
+                found.len() == 1 && found[0].id == id && found[0].start == "2026-01-20 23:30:00",
+                || format!("found {:?}", found),
+            )
+        }
+
+        async fn deletes(&mut self) -> Result<(), String> {
+            let ids: Vec<i64> = self.all().await?.iter().map(|entry| entry.id).collect();
+            ensure(ids.len() > 1, || {
+                format!("only {} entries to delete", ids.len())
+            })?;
+            self.storage.delete(&ids).await.map_err(|e| e.to_string())?;
+            let left = self.all().await?;
+            ensure(left.is_empty(), || format!("still listed: {:?}", left))
+        }
+
+        async fn cleanup(&mut self) -> Result<(), String> {
+            let storage = self.storage;
+            let renamed = format!("{}-renamed", self.name);
+            storage
+                .rename_profile(&self.name, &renamed)
+                .await
+                .map_err(|e| e.to_string())?;
+            let found = storage
+                .profile_id(&renamed)
+                .await
+                .map_err(|e| e.to_string())?;
+            ensure(found == Some(self.profile), || {
+                format!("renamed profile found as {:?}", found)
+            })?;
+            for name in [renamed, format!("{}-other", self.name)] {
+                storage
+                    .remove_profile(&name)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                let found = storage.profile_id(&name).await.map_err(|e| e.to_string())?;
+                ensure(found.is_none(), || format!("{} still there", name))?;
+            }
+            Ok(())
+        }
+
+        /// Deletes whatever the checks left behind: the profiles with their entries and, on SQLite,
+        /// the history of those entries.
+        async fn teardown(&self) -> Result<(), String> {
+            let storage = self.storage;
+            let profiles = [self.profile, self.other];
+
+            for name in [
+                self.name.clone(),
+                format!("{}-renamed", self.name),
+                format!("{}-other", self.name),
+            ] {
+                let Some(profile) = storage.profile_id(&name).await.map_err(|e| e.to_string())?
+                else {
+                    continue;
+                };
+                // Only profiles the checks made, not one a failed check found by mistake
+                if !profiles.contains(&profile) {
+                    continue;
+                }
+                let ids: Vec<i64> = storage
+                    .recent(profile, None)
+                    .await
+                    .map_err(|e| e.to_string())?
+                    .iter()
+                    .map(|entry| entry.id)
+                    .collect();
+                storage.delete(&ids).await.map_err(|e| e.to_string())?;
+                storage
+                    .remove_profile(&name)
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+
+            // Removing a profile logs its entries as purged, so every entry has an event naming
+            // the profile; that finds the events without one too (inserts), by entry id
+            if let Some(pool) = storage.sqlite() {
+                sqlx::query(
+                    "DELETE FROM sleep_history WHERE entry_id IN (
+                     SELECT entry_id FROM sleep_history
+                     WHERE json_extract(previous, '$.profile_id') IN (?, ?))",
+                )
+                .bind(self.profile)
+                .bind(self.other)
+                .execute(pool)
+                .await
+                .map_err(|e| e.to_string())?;
+            }
+            Ok(())
+        }
+    }
+
+    /// Runs every check against `storage`, failing with the checks that didn't pass.
+    async fn assert_conforms(storage: &impl Storage) {
+        let failed: Vec<String> = conformance(storage)
+            .await
+            .into_iter()
+            .filter_map(|(check, result)| result.err().map(|e| format!("{}: {}", check, e)))
+            .collect();
+        assert!(
+            failed.is_empty(),
+            "{} storage failed:\n{}",
+            storage.kind(),
+            failed.join("\n")
+        );
+    }
+
+    #[tokio::test]
+    async fn sqlite_conforms() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            db_file_path: dir.path().join("slog.db").display().to_string(),
+            ..Config::default()
+        };
+        let pool = db::connect(&config).await.unwrap();
+        assert_conforms(&SqliteStorage::new(pool)).await;
+    }
+
+    #[tokio::test]
+    async fn files_conform() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_conforms(&FileStorage::open(dir.path()).unwrap()).await;
+    }
+
+    /// Needs a database it may write to, e.g.
+    /// `SLOG_TEST_PG_URL=postgres://localhost/slog_test cargo test -- --ignored`
+    #[tokio::test]
+    #[ignore = "needs SLOG_TEST_PG_URL"]
+    async fn postgres_conforms() {
+        let url = std::env::var("SLOG_TEST_PG_URL").expect("SLOG_TEST_PG_URL is not set");
+        assert_conforms(&PostgresStorage::connect(&url).await.unwrap()).await;
+    }
+}
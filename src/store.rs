@@ -0,0 +1,423 @@
+use chrono::NaiveDate;
+use sqlx::{SqliteExecutor, SqlitePool};
+
+use crate::entry::{self, Entry, Problem, Severity};
+use crate::history;
+use crate::markers;
+use crate::stages::{self, Span};
+
+/// SQL expression for a new random (version 4) UUID, identifying an entry across databases.
+pub const NEW_UUID: &str = "lower(hex(randomblob(4)) || '-' || hex(randomblob(2)) || '-4'
+    || substr(hex(randomblob(2)), 2) || '-' || substr('89ab', abs(random()) % 4 + 1, 1)
+    || substr(hex(randomblob(2)), 2) || '-' || hex(randomblob(6)))";
+
+/// Why an entry couldn't be saved.
+#[derive(Debug)]
+pub enum StoreError {
+    /// The entry's time range overlaps these entries
+    Overlap(Vec<Entry>),
+    /// The entry has impossible values, e.g. it ends before it starts
+    Invalid(Vec<Problem>),
+    Database(sqlx::Error),
+    /// Anything else, e.g. an unreadable file of the flat-file storage
+    Other(String),
+}
+
+impl std::fmt::Display for StoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StoreError::Overlap(entries) => {
+                write!(f, "overlaps ")?;
+                for (i, entry) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(
+                        f,
+                        "entry #{} ({} → {})",
+                        entry.id,
+                        entry.start,
+                        entry.end.as_deref().unwrap_or("N/A")
+                    )?;
+                }
+                Ok(())
+            }
+            StoreError::Invalid(problems) => {
+                let messages: Vec<&str> = problems
+                    .iter()
+                    .map(|problem| problem.message.as_str())
+                    .collect();
+                write!(f, "is invalid: {}", messages.join("; "))
+            }
+            StoreError::Database(e) => write!(f, "{}", e),
+            StoreError::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<sqlx::Error> for StoreError {
+    fn from(e: sqlx::Error) -> Self {
+        StoreError::Database(e)
+    }
+}
+
+impl From<String> for StoreError {
+    fn from(message: String) -> Self {
+        StoreError::Other(message)
+    }
+}
+
+/// Whether `entry` overlaps `start`..`end`, by the same rules as `overlapping`.
+/// For storages that can't ask SQLite.
+pub fn overlaps(entry: &Entry, start: &str, end: Option<&str>) -> bool {
+    let end = end.unwrap_or(start);
+    let entry_end = entry.end.as_deref().unwrap_or(&entry.start);
+    entry.start.as_str() < end && entry_end > start || entry.start == start
+}
+
+/// Entries of `profile` (not deleted, other than `exclude`) whose time range overlaps
+/// `start`..`end`.
+/// Touching ranges, where one ends exactly when the other starts, don't overlap.
+/// An entry without an end only covers its start; entries with the same start always overlap.
+pub async fn overlapping(
+    executor: impl SqliteExecutor<'_>,
+    profile: i64,
+    start: &str,
+    end: Option<&str>,
+    exclude: &[i64],
+) -> Result<Vec<Entry>, sqlx::Error> {
+    let end = end.unwrap_or(start);
+    let entries: Vec<Entry> = sqlx::query_as(&format!(
+        "SELECT {} FROM sleep
+         WHERE deleted_at IS NULL AND profile_id = ?3
+           AND (start < ?2 AND COALESCE(end, start) > ?1 OR start = ?1)
+         ORDER BY start",
+        entry::COLUMNS
+    ))
+    .bind(start)
+    .bind(end)
+    .bind(profile)
+    .fetch_all(executor)
+    .await?;
+    Ok(entries
+        .into_iter()
+        .filter(|entry| !exclude.contains(&entry.id))
+        .collect())
+}
+
+/// Entries of `profile` that aren't deleted, newest first; all of them when `limit` is `None`.
+pub async fn recent(
+    pool: &SqlitePool,
+    profile: i64,
+    limit: Option<usize>,
+) -> Result<Vec<Entry>, sqlx::Error> {
+    sqlx::query_as(&format!(
+        "SELECT {} FROM sleep
+         WHERE deleted_at IS NULL AND profile_id = ?
+         ORDER BY start DESC LIMIT ?",
+        entry::COLUMNS
+    ))
+    .bind(profile)
+    // SQLite treats a negative limit as no limit
+    .bind(limit.map_or(-1, |limit| limit as i64))
+    .fetch_all(pool)
+    .await
+}
+
+/// The entry of `profile` with this id, unless it is deleted.
+pub async fn get(pool: &SqlitePool, profile: i64, id: i64) -> Result<Option<Entry>, sqlx::Error> {
+    sqlx::query_as(&format!(
+        "SELECT {} FROM sleep WHERE id = ? AND profile_id = ? AND deleted_at IS NULL",
+        entry::COLUMNS
+    ))
+    .bind(id)
+    .bind(profile)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Entries of `profile` that aren't deleted starting on `date`, in order.
+pub async fn on_date(
+    pool: &SqlitePool,
+    profile: i64,
+    date: NaiveDate,
+) -> Result<Vec<Entry>, sqlx::Error> {
+    sqlx::query_as(&format!(
+        "SELECT {} FROM sleep
+         WHERE deleted_at IS NULL AND profile_id = ? AND DATE(start) = ?
+         ORDER BY start",
+        entry::COLUMNS
+    ))
+    .bind(profile)
+    .bind(date.format("%Y-%m-%d").to_string())
+    .fetch_all(pool)
+    .await
+}
+
+/// Entries of `profile` that aren't deleted starting from `from` to `to` (both included), in
+/// order.
+pub async fn between(
+    pool: &SqlitePool,
+    profile: i64,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<Entry>, sqlx::Error> {
+    sqlx::query_as(&format!(
+        "SELECT {} FROM sleep
+         WHERE deleted_at IS NULL AND profile_id = ? AND DATE(start) >= ? AND DATE(start) <= ?
+         ORDER BY start",
+        entry::COLUMNS
+    ))
+    .bind(profile)
+    .bind(from.format("%Y-%m-%d").to_string())
+    .bind(to.format("%Y-%m-%d").to_string())
+    .fetch_all(pool)
+    .await
+}
+
+/// Refuses an entry with `Severity::Invalid` problems. Every storage checks this before saving,
+/// so no command can store one.
+pub fn validate(entry: &Entry) -> Result<(), StoreError> {
+    let invalid: Vec<Problem> = entry
+        .problems()
+        .into_iter()
+        .filter(|problem| problem.severity == Severity::Invalid)
+        .collect();
+    if invalid.is_empty() {
+        Ok(())
+    } else {
+        Err(StoreError::Invalid(invalid))
+    }
+}
+
+/// A change `Storage::save_night` makes together with the night's stages.
+pub enum Save {
+    Insert(Entry),
+    Update(Entry),
+    /// The result of `merged` and the existing entries it replaces
+    Merge(Entry, Vec<Entry>),
+    /// A new entry and the id of the entry it replaces, deleted in the same change (and the
+    /// same `slog undo` batch)
+    Replace(Entry, i64),
+}
+
+/// Makes the change `save` and replaces the saved entry's stages with `spans`, all or nothing;
+/// without `spans` the entry keeps the stages it has. Returns the id of the saved entry.
+pub async fn save(pool: &SqlitePool, save: &Save, spans: &[Span]) -> Result<i64, StoreError> {
+    let mut tx = pool.begin().await?;
+    let id = match save {
+        Save::Insert(entry) => insert_in(&mut tx, entry).await?,
+        Save::Update(entry) => {
+            update_in(&mut tx, entry, &[entry.id]).await?;
+            entry.id
+        }
+        Save::Merge(merged, existing) => {
+            merge_in(&mut tx, merged, existing).await?;
+            merged.id
+        }
+        Save::Replace(entry, old) => {
+            let last_before = history::last_event(&mut tx).await?;
+            history::soft_delete(&mut *tx, *old).await?;
+            let id = insert_in(&mut tx, entry).await?;
+            history::batch_since(&mut tx, last_before).await?;
+            id
+        }
+    };
+    if !spans.is_empty() {
+        stages::replace(&mut tx, id, spans).await?;
+    }
+    tx.commit().await?;
+    Ok(id)
+}
+
+/// Inserts a new entry, refusing if it is invalid or overlaps another of the same profile, and
+/// drops the marker for its night.
+/// Returns the new id.
+pub async fn insert(pool: &SqlitePool, entry: &Entry) -> Result<i64, StoreError> {
+    let mut tx = pool.begin().await?;
+    let id = insert_in(&mut tx, entry).await?;
+    tx.commit().await?;
+    Ok(id)
+}
+
+/// `insert` as part of the transaction `tx`, e.g. together with the entry's stages.
+pub async fn insert_in(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    entry: &Entry,
+) -> Result<i64, StoreError> {
+    validate(entry)?;
+    let overlaps = overlapping(
+        &mut **tx,
+        entry.profile_id,
+        &entry.start,
+        entry.end.as_deref(),
+        &[],
+    )
+    .await?;
+    if !overlaps.is_empty() {
+        return Err(StoreError::Overlap(overlaps));
+    }
+
+    let result = sqlx::query(&format!(
+        "INSERT INTO sleep (start, minutes_to_fall_asleep, end, awake_count, time_awake,
+             time_in_bed_after_waking, quality, melatonin, benadryl, edible, exertion, notes,
+             source, profile_id, origin, uuid, created_at, updated_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, {},
+             datetime('now', 'localtime'), datetime('now', 'localtime'))",
+        NEW_UUID
+    ))
+    .bind(&entry.start)
+    .bind(entry.minutes_to_fall_asleep)
+    .bind(&entry.end)
+    .bind(entry.awake_count)
+    .bind(entry.time_awake)
+    .bind(entry.time_in_bed_after_waking)
+    .bind(entry.quality)
+    .bind(entry.melatonin)
+    .bind(entry.benadryl)
+    .bind(entry.edible)
+    .bind(entry.exertion)
+    .bind(&entry.notes)
+    .bind(&entry.source)
+    .bind(entry.profile_id)
+    .bind(&entry.origin)
+    .execute(&mut **tx)
+    .await?;
+
+    // A real entry supersedes "unknown" or "didn't sleep here"
+    if let Some(date) = entry.start.get(..10) {
+        markers::clear(&mut **tx, entry.profile_id, date).await?;
+    }
+
+    Ok(result.last_insert_rowid())
+}
+
+/// Overwrites the entry with `entry.id`, refusing if it is invalid or its new time range
+/// overlaps another.
+pub async fn update(pool: &SqlitePool, entry: &Entry) -> Result<(), StoreError> {
+    let mut tx = pool.begin().await?;
+    update_in(&mut tx, entry, &[entry.id]).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// `update` as part of the transaction `tx`, refusing overlaps with anything but `exclude`.
+pub async fn update_in(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    entry: &Entry,
+    exclude: &[i64],
+) -> Result<(), StoreError> {
+    validate(entry)?;
+    let overlaps = overlapping(
+        &mut **tx,
+        entry.profile_id,
+        &entry.start,
+        entry.end.as_deref(),
+        exclude,
+    )
+    .await?;
+    if !overlaps.is_empty() {
+        return Err(StoreError::Overlap(overlaps));
+    }
+
+    sqlx::query(
+        "UPDATE sleep SET start = ?, minutes_to_fall_asleep = ?, end = ?, awake_count = ?,
+             time_awake = ?, time_in_bed_after_waking = ?, quality = ?, melatonin = ?,
+             benadryl = ?, edible = ?, exertion = ?, notes = ?, source = ?,
+             updated_at = datetime('now', 'localtime')
+         WHERE id = ?",
+    )
+    .bind(&entry.start)
+    .bind(entry.minutes_to_fall_asleep)
+    .bind(&entry.end)
+    .bind(entry.awake_count)
+    .bind(entry.time_awake)
+    .bind(entry.time_in_bed_after_waking)
+    .bind(entry.quality)
+    .bind(entry.melatonin)
+    .bind(entry.benadryl)
+    .bind(entry.edible)
+    .bind(entry.exertion)
+    .bind(&entry.notes)
+    .bind(&entry.source)
+    .bind(entry.id)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Combines `entry` with the entries it overlaps: the result spans from the earliest start to
+/// the latest end, takes every other value from `entry` where set and from the existing entries
+/// otherwise, and joins the notes. It keeps the id, uuid, creation time and origin of the
+/// earliest existing entry.
+pub fn merged(entry: &Entry, existing: &[Entry]) -> Entry {
+    let mut merged = entry.clone();
+    let Some(first) = existing.first() else {
+        return merged;
+    };
+    merged.id = first.id;
+    merged.uuid = first.uuid.clone();
+    merged.created_at = first.created_at.clone();
+    merged.updated_at = first.updated_at.clone();
+    merged.origin = first.origin.clone();
+
+    for other in existing {
+        if other.start < merged.start {
+            merged.start = other.start.clone();
+        }
+        if other.end > merged.end {
+            merged.end = other.end.clone();
+        }
+        merged.minutes_to_fall_asleep = merged
+            .minutes_to_fall_asleep
+            .or(other.minutes_to_fall_asleep);
+        merged.awake_count = merged.awake_count.or(other.awake_count);
+        merged.time_awake = merged.time_awake.or(other.time_awake);
+        merged.time_in_bed_after_waking = merged
+            .time_in_bed_after_waking
+            .or(other.time_in_bed_after_waking);
+        merged.quality = merged.quality.or(other.quality);
+        merged.melatonin = merged.melatonin.or(other.melatonin);
+        merged.benadryl = merged.benadryl.or(other.benadryl);
+        merged.edible = merged.edible.or(other.edible);
+        merged.exertion = merged.exertion.or(other.exertion);
+        merged.source = merged.source.or(other.source.clone());
+        merged.notes = match (merged.notes.take(), &other.notes) {
+            (Some(notes), Some(other_notes)) if !notes.contains(other_notes.as_str()) => {
+                Some(format!("{}; {}", other_notes, notes))
+            }
+            (notes, other_notes) => notes.or(other_notes.clone()),
+        };
+    }
+
+    merged
+}
+
+/// Saves the result of `merged`: the earliest existing entry is overwritten and the other
+/// existing entries are deleted (so `slog undo` can bring them back).
+/// Refuses if the combined range overlaps yet another entry.
+pub async fn merge(
+    pool: &SqlitePool,
+    merged: &Entry,
+    existing: &[Entry],
+) -> Result<(), StoreError> {
+    let mut tx = pool.begin().await?;
+    merge_in(&mut tx, merged, existing).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// `merge` as part of the transaction `tx`.
+pub async fn merge_in(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    merged: &Entry,
+    existing: &[Entry],
+) -> Result<(), StoreError> {
+    let ids: Vec<i64> = existing.iter().map(|entry| entry.id).collect();
+    for id in ids.iter().filter(|id| **id != merged.id) {
+        history::soft_delete(&mut **tx, *id).await?;
+    }
+    update_in(tx, merged, &ids).await
+}
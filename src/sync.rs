@@ -0,0 +1,907 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::config::Config;
+use crate::entry;
+use crate::markers;
+use crate::profiles;
+use crate::store;
+
+/// An entry as exchanged by `slog sync`: identified by its uuid and its profile's name, since
+/// ids differ between databases. Deleted entries are exchanged too, so deletes propagate.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow, Serialize, Deserialize)]
+pub struct Change {
+    pub uuid: String,
+    pub profile: String,
+    pub start: String,
+    pub end: Option<String>,
+    pub minutes_to_fall_asleep: Option<i32>,
+    pub awake_count: Option<i16>,
+    pub time_awake: Option<i32>,
+    pub time_in_bed_after_waking: Option<i32>,
+    pub quality: Option<i8>,
+    pub melatonin: Option<f32>,
+    pub benadryl: Option<f32>,
+    pub edible: Option<f32>,
+    pub exertion: Option<i8>,
+    pub notes: Option<String>,
+    pub source: Option<String>,
+    /// Missing from change files written before it was exchanged
+    #[serde(default)]
+    pub created_at: Option<String>,
+    pub updated_at: String,
+    #[serde(default = "entry::default_origin")]
+    pub origin: String,
+    pub deleted_at: Option<String>,
+}
+
+impl Change {
+    /// Same entry with the same values, however long ago either side saved it.
+    fn same_as(&self, other: &Change) -> bool {
+        *self
+            == Change {
+                updated_at: self.updated_at.clone(),
+                ..other.clone()
+            }
+    }
+
+    /// Same night with the same values, recorded separately on both sides (or copied before
+    /// uuids existed).
+    fn duplicate_of(&self, other: &Change) -> bool {
+        self.same_as(&Change {
+            uuid: self.uuid.clone(),
+            created_at: self.created_at.clone(),
+            deleted_at: self.deleted_at.clone(),
+            ..other.clone()
+        })
+    }
+
+    fn live(&self) -> bool {
+        self.deleted_at.is_none()
+    }
+
+    fn overlaps(&self, other: &Change) -> bool {
+        let end = self.end.as_deref().unwrap_or(&self.start);
+        let other_end = other.end.as_deref().unwrap_or(&other.start);
+        self.profile == other.profile
+            && (self.start.as_str() < other_end && end > other.start.as_str()
+                || self.start == other.start)
+    }
+}
+
+/// One line description, e.g. `2026-10-10 22:00:00 → 2026-10-11 06:00:00 (default)`.
+pub fn describe(change: &Change) -> String {
+    format!(
+        "{} → {} ({}){}",
+        change.start,
+        change.end.as_deref().unwrap_or("N/A"),
+        change.profile,
+        if change.live() { "" } else { ", deleted" }
+    )
+}
+
+/// Values of a change for choosing between two versions.
+pub fn summary(change: &Change) -> String {
+    if !change.live() {
+        return format!("deleted (changed {})", change.updated_at);
+    }
+    let mut parts = vec![format!(
+        "{} → {}",
+        change.start,
+        change.end.as_deref().unwrap_or("N/A")
+    )];
+    if let Some(quality) = change.quality {
+        parts.push(format!("quality {}", quality));
+    }
+    for (name, dose) in [
+        ("melatonin", change.melatonin),
+        ("benadryl", change.benadryl),
+        ("edible", change.edible),
+    ] {
+        if let Some(dose) = dose.filter(|dose| *dose > 0.0) {
+            parts.push(format!("{} {}mg", name, dose));
+        }
+    }
+    if let Some(notes) = &change.notes {
+        parts.push(format!("\"{}\"", notes));
+    }
+    parts.push(format!("changed {}", change.updated_at));
+    parts.join(", ")
+}
+
+/// How conflicts are settled: both sides changed an entry since they last synced, or entries
+/// from both sides cover the same night.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Resolution {
+    /// Keep the version changed most recently
+    Newest,
+    /// Ask for each conflict
+    Ask,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    Local,
+    Remote,
+}
+
+pub enum Conflict<'a> {
+    /// Both sides changed the same entry since they last synced
+    Edited {
+        local: &'a Change,
+        remote: &'a Change,
+    },
+    /// Different entries for the same night; the one not kept is deleted on both sides
+    Overlap {
+        local: &'a Change,
+        remote: &'a Change,
+    },
+}
+
+impl Conflict<'_> {
+    fn sides(&self) -> (&Change, &Change) {
+        match self {
+            Conflict::Edited { local, remote } | Conflict::Overlap { local, remote } => {
+                (local, remote)
+            }
+        }
+    }
+
+    /// The side changed most recently, this database's on a tie.
+    pub fn newest(&self) -> Side {
+        let (local, remote) = self.sides();
+        if remote.updated_at > local.updated_at {
+            Side::Remote
+        } else {
+            Side::Local
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Conflict::Edited { local, .. } => {
+                format!("{} changed on both sides", describe(local))
+            }
+            Conflict::Overlap { local, remote } => {
+                format!("{} overlaps {}", describe(local), describe(remote))
+            }
+        }
+    }
+}
+
+/// What a sync does on each side.
+pub struct Plan {
+    /// Every entry as both sides have it afterwards, the base for the next sync
+    pub merged: Vec<Change>,
+    /// Uuids this database takes over from identical entries on the target, old to new
+    pub rename_local: Vec<(String, String)>,
+    /// Same for the target
+    pub rename_remote: Vec<(String, String)>,
+    /// Changes to apply to this database
+    pub pull: Vec<Change>,
+    /// Changes to send to the target
+    pub push: Vec<Change>,
+    /// Conflicts and the side that was kept
+    pub conflicts: Vec<(String, Side)>,
+}
+
+/// Works out the entries both sides should end up with. `base` has the entries as they were
+/// after the last sync with the target: an entry changed on only one side since then takes
+/// that side's version, and `resolve` picks one when both changed (or they never synced).
+/// `remote_renames` says whether the target can change an entry's uuid.
+pub fn plan(
+    local: Vec<Change>,
+    remote: Vec<Change>,
+    base: &BTreeMap<String, Change>,
+    remote_renames: bool,
+    mut resolve: impl FnMut(&Conflict) -> Result<Side, Box<dyn std::error::Error>>,
+) -> Result<Plan, Box<dyn std::error::Error>> {
+    let local: BTreeMap<String, Change> = local
+        .into_iter()
+        .map(|change| (change.uuid.clone(), change))
+        .collect();
+    let remote: BTreeMap<String, Change> = remote
+        .into_iter()
+        .map(|change| (change.uuid.clone(), change))
+        .collect();
+    let changed_since = |change: &Change| {
+        base.get(&change.uuid)
+            .is_none_or(|synced| !synced.same_as(change))
+    };
+
+    let mut conflicts = Vec::new();
+    let mut merged: BTreeMap<String, Change> = BTreeMap::new();
+    let uuids: BTreeSet<&String> = local.keys().chain(remote.keys()).collect();
+    for uuid in uuids {
+        let winner = match (local.get(uuid), remote.get(uuid)) {
+            (Some(change), None) | (None, Some(change)) => change,
+            (Some(ours), Some(theirs)) if ours.same_as(theirs) => ours,
+            (Some(ours), Some(theirs)) => match (changed_since(ours), changed_since(theirs)) {
+                (true, false) => ours,
+                (false, true) => theirs,
+                _ => {
+                    let conflict = Conflict::Edited {
+                        local: ours,
+                        remote: theirs,
+                    };
+                    let side = resolve(&conflict)?;
+                    conflicts.push((conflict.describe(), side));
+                    match side {
+                        Side::Local => ours,
+                        Side::Remote => theirs,
+                    }
+                }
+            },
+            (None, None) => unreachable!(),
+        };
+        merged.insert(uuid.clone(), winner.clone());
+    }
+
+    // Entries for the same night that were apart until now; overlaps that already exist on
+    // one side are left for `slog doctor`
+    let live_in = |side: &BTreeMap<String, Change>, uuid: &str| {
+        side.get(uuid).is_some_and(|change| change.live())
+    };
+    let mut nights: Vec<&Change> = merged.values().filter(|change| change.live()).collect();
+    nights.sort_by(|a, b| (&a.profile, &a.start).cmp(&(&b.profile, &b.start)));
+    let mut losers: Vec<String> = Vec::new();
+    let mut renames: Vec<(Side, String, String)> = Vec::new();
+    for (i, a) in nights.iter().enumerate() {
+        // Sorted by start, so nothing after an entry starting past this one's end overlaps it
+        let last = a.end.as_deref().unwrap_or(&a.start).max(&a.start);
+        for b in &nights[i + 1..] {
+            if b.profile != a.profile || b.start.as_str() > last {
+                break;
+            }
+            if !a.overlaps(b)
+                || losers.contains(&a.uuid)
+                || losers.contains(&b.uuid)
+                || live_in(&local, &a.uuid) && live_in(&local, &b.uuid)
+                || live_in(&remote, &a.uuid) && live_in(&remote, &b.uuid)
+            {
+                continue;
+            }
+            let (ours, theirs) = if live_in(&local, &a.uuid) {
+                (*a, *b)
+            } else {
+                (*b, *a)
+            };
+            let loser = if ours.duplicate_of(theirs) {
+                // Both sides pick the same uuid, so repeated syncs agree. A database takes it
+                // over, keeping the entry's id and stages; a directory gets a delete
+                let (kept, dropped) = if ours.uuid < theirs.uuid {
+                    (ours, theirs)
+                } else {
+                    (theirs, ours)
+                };
+                let side = if dropped.uuid == ours.uuid {
+                    Side::Local
+                } else {
+                    Side::Remote
+                };
+                let renamed = match side {
+                    Side::Local => !remote.contains_key(&dropped.uuid),
+                    Side::Remote => remote_renames && !local.contains_key(&dropped.uuid),
+                };
+                if renamed {
+                    renames.push((side, dropped.uuid.clone(), kept.uuid.clone()));
+                }
+                dropped.uuid.clone()
+            } else {
+                let conflict = Conflict::Overlap {
+                    local: ours,
+                    remote: theirs,
+                };
+                let side = resolve(&conflict)?;
+                conflicts.push((conflict.describe(), side));
+                match side {
+                    Side::Local => theirs.uuid.clone(),
+                    Side::Remote => ours.uuid.clone(),
+                }
+            };
+            losers.push(loser);
+        }
+    }
+    let (mut local, mut remote) = (local, remote);
+    for (side, old, new) in &renames {
+        let changes = match side {
+            Side::Local => &mut local,
+            Side::Remote => &mut remote,
+        };
+        if let Some(change) = changes.remove(old) {
+            changes.insert(
+                new.clone(),
+                Change {
+                    uuid: new.clone(),
+                    ..change
+                },
+            );
+        }
+        merged.remove(old);
+        losers.retain(|uuid| uuid != old);
+    }
+    let now = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    for uuid in &losers {
+        if let Some(change) = merged.get_mut(uuid) {
+            change.deleted_at = Some(now.clone());
+            change.updated_at = now.clone();
+        }
+    }
+
+    let differs = |side: &BTreeMap<String, Change>, change: &Change| {
+        side.get(&change.uuid)
+            .is_none_or(|other| !other.same_as(change))
+    };
+    let renamed = |side: Side| -> Vec<(String, String)> {
+        renames
+            .iter()
+            .filter(|(renamed, _, _)| *renamed == side)
+            .map(|(_, old, new)| (old.clone(), new.clone()))
+            .collect()
+    };
+    Ok(Plan {
+        merged: merged.values().cloned().collect(),
+        rename_local: renamed(Side::Local),
+        rename_remote: renamed(Side::Remote),
+        pull: merged
+            .values()
+            .filter(|change| differs(&local, change))
+            .cloned()
+            .collect(),
+        push: merged
+            .values()
+            .filter(|change| differs(&remote, change))
+            .cloned()
+            .collect(),
+        conflicts,
+    })
+}
+
+/// Every entry of every profile in a slog database, deleted ones included.
+pub async fn changes(pool: &SqlitePool) -> Result<Vec<Change>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT s.uuid, p.name AS profile, s.start, s.end, s.minutes_to_fall_asleep,
+                s.awake_count, s.time_awake, s.time_in_bed_after_waking, s.quality, s.melatonin,
+                s.benadryl, s.edible, s.exertion, s.notes, s.source, s.created_at,
+                COALESCE(s.updated_at, s.start) AS updated_at, s.origin, s.deleted_at
+         FROM sleep s
+         JOIN profiles p ON p.id = s.profile_id
+         ORDER BY s.start",
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Writes `changes` to a slog database after giving entries their `renames`, creating missing
+/// profiles. Deletes go first so that a night can change hands.
+pub async fn apply(
+    pool: &SqlitePool,
+    renames: &[(String, String)],
+    changes: &[Change],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut profile_ids: HashMap<&str, i64> = HashMap::new();
+    for change in changes {
+        if profile_ids.contains_key(change.profile.as_str()) {
+            continue;
+        }
+        let id = match profiles::find(pool, &change.profile).await? {
+            Some(id) => id,
+            None => profiles::add(pool, &change.profile).await?,
+        };
+        profile_ids.insert(&change.profile, id);
+    }
+
+    let mut ordered: Vec<&Change> = changes.iter().collect();
+    ordered.sort_by_key(|change| change.live());
+
+    let mut tx = pool.begin().await?;
+    for (old, new) in renames {
+        sqlx::query("UPDATE sleep SET uuid = ? WHERE uuid = ?")
+            .bind(new)
+            .bind(old)
+            .execute(&mut *tx)
+            .await?;
+    }
+    for change in ordered {
+        let profile = profile_ids[change.profile.as_str()];
+        let (exists,): (bool,) =
+            sqlx::query_as("SELECT EXISTS (SELECT 1 FROM sleep WHERE uuid = ?)")
+                .bind(&change.uuid)
+                .fetch_one(&mut *tx)
+                .await?;
+        let sql = if exists {
+            "UPDATE sleep SET start = ?, end = ?, minutes_to_fall_asleep = ?, awake_count = ?,
+                 time_awake = ?, time_in_bed_after_waking = ?, quality = ?, melatonin = ?,
+                 benadryl = ?, edible = ?, exertion = ?, notes = ?, source = ?, profile_id = ?,
+                 created_at = COALESCE(?, created_at), updated_at = ?, origin = ?, deleted_at = ?
+             WHERE uuid = ?"
+        } else {
+            "INSERT INTO sleep (start, end, minutes_to_fall_asleep, awake_count, time_awake,
+                 time_in_bed_after_waking, quality, melatonin, benadryl, edible, exertion, notes,
+                 source, profile_id, created_at, updated_at, origin, deleted_at, uuid)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        };
+        // Entries from change files written before it was exchanged keep their creation time,
+        // or are taken as created when last changed
+        let created_at = if exists {
+            change.created_at.as_deref()
+        } else {
+            Some(change.created_at.as_deref().unwrap_or(&change.updated_at))
+        };
+        sqlx::query(sql)
+            .bind(&change.start)
+            .bind(&change.end)
+            .bind(change.minutes_to_fall_asleep)
+            .bind(change.awake_count)
+            .bind(change.time_awake)
+            .bind(change.time_in_bed_after_waking)
+            .bind(change.quality)
+            .bind(change.melatonin)
+            .bind(change.benadryl)
+            .bind(change.edible)
+            .bind(change.exertion)
+            .bind(&change.notes)
+            .bind(&change.source)
+            .bind(profile)
+            .bind(created_at)
+            .bind(&change.updated_at)
+            .bind(&change.origin)
+            .bind(&change.deleted_at)
+            .bind(&change.uuid)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| match e {
+                sqlx::Error::Database(db) if db.is_unique_violation() => format!(
+                    "Cannot sync {}: another entry has the same start and end",
+                    describe(change)
+                ),
+                e => e.to_string(),
+            })?;
+
+        // Same as recording the night here
+        if change.live()
+            && let Some(date) = change.start.get(..10)
+        {
+            markers::clear(&mut *tx, profile, date).await?;
+        }
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Id of this database in sync directories, created on first use.
+pub async fn device_id(pool: &SqlitePool) -> Result<String, sqlx::Error> {
+    sqlx::query(&format!(
+        "INSERT OR IGNORE INTO sync_state (key, value) VALUES ('device_id', {})",
+        store::NEW_UUID
+    ))
+    .execute(pool)
+    .await?;
+    let (id,): (String,) = sqlx::query_as("SELECT value FROM sync_state WHERE key = 'device_id'")
+        .fetch_one(pool)
+        .await?;
+    Ok(id)
+}
+
+/// Entries as they were after the last sync with `target`, by uuid.
+pub async fn base(
+    pool: &SqlitePool,
+    target: &Path,
+) -> Result<BTreeMap<String, Change>, Box<dyn std::error::Error>> {
+    let rows: Vec<(String,)> = sqlx::query_as("SELECT change FROM sync_base WHERE target = ?")
+        .bind(target.display().to_string())
+        .fetch_all(pool)
+        .await?;
+    let mut base = BTreeMap::new();
+    for (json,) in rows {
+        let change: Change = serde_json::from_str(&json)?;
+        base.insert(change.uuid.clone(), change);
+    }
+    Ok(base)
+}
+
+/// Remembers `merged` as the entries both sides have after syncing with `target`.
+pub async fn save_base(
+    pool: &SqlitePool,
+    target: &Path,
+    merged: &[Change],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let target = target.display().to_string();
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM sync_base WHERE target = ?")
+        .bind(&target)
+        .execute(&mut *tx)
+        .await?;
+    for change in merged {
+        sqlx::query("INSERT INTO sync_base (target, uuid, change) VALUES (?, ?, ?)")
+            .bind(&target)
+            .bind(&change.uuid)
+            .bind(serde_json::to_string(change)?)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// What `slog sync` exchanges entries with.
+pub enum Target {
+    /// Another slog database, with the config to back it up with
+    Database(SqlitePool, Box<Config>),
+    Directory(Directory),
+}
+
+/// A directory of NDJSON change files, one per database syncing through it. Each database only
+/// appends to its own file, so a git repository or a Syncthing folder never has to merge one.
+pub struct Directory {
+    /// File this database appends to
+    own: PathBuf,
+    /// The current version of each entry
+    current: BTreeMap<String, Change>,
+}
+
+/// A line of a change file.
+#[derive(Serialize, Deserialize)]
+struct Line {
+    #[serde(flatten)]
+    change: Change,
+    /// `updated_at` of the version this one was written over, so the order of versions doesn't
+    /// depend on the clocks of the machines that wrote them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    replaces: Option<String>,
+}
+
+impl Directory {
+    /// Reads every change file in `dir`.
+    pub fn open(dir: &Path, device_id: &str) -> Result<Self, String> {
+        let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+            .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?
+            .filter_map(|file| file.ok().map(|file| file.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "ndjson")
+            })
+            .collect();
+        files.sort();
+
+        let mut versions: BTreeMap<String, Vec<Line>> = BTreeMap::new();
+        for path in files {
+            let contents = std::fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            for (number, line) in contents.lines().enumerate() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let line: Line = serde_json::from_str(line).map_err(|e| {
+                    format!(
+                        "Invalid change on line {} of {}: {}",
+                        number + 1,
+                        path.display(),
+                        e
+                    )
+                })?;
+                versions
+                    .entry(line.change.uuid.clone())
+                    .or_default()
+                    .push(line);
+            }
+        }
+
+        // The current version is one nothing was written over. There are several when two
+        // databases changed an entry without seeing each other's change; the newest wins then
+        let current = versions
+            .into_iter()
+            .filter_map(|(uuid, lines)| {
+                // A version written over one from the same second replaces it all the same,
+                // though the times can't tell them apart
+                let rewrite = |line: &Line| line.replaces.as_ref() == Some(&line.change.updated_at);
+                let replaced: BTreeSet<&str> = lines
+                    .iter()
+                    .filter(|line| !rewrite(line))
+                    .filter_map(|line| line.replaces.as_deref())
+                    .collect();
+                let change = lines
+                    .iter()
+                    .filter(|line| !replaced.contains(line.change.updated_at.as_str()))
+                    .max_by_key(|line| (&line.change.updated_at, rewrite(line)))
+                    .or(lines.last())?
+                    .change
+                    .clone();
+                Some((uuid, change))
+            })
+            .collect();
+
+        Ok(Directory {
+            own: dir.join(format!("{}.ndjson", device_id)),
+            current,
+        })
+    }
+
+    pub fn changes(&self) -> Vec<Change> {
+        self.current.values().cloned().collect()
+    }
+
+    /// Appends `changes` to this database's file.
+    pub fn append(&self, changes: &[Change]) -> Result<(), String> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let mut lines = String::new();
+        for change in changes {
+            let line = Line {
+                change: change.clone(),
+                replaces: self
+                    .current
+                    .get(&change.uuid)
+                    .map(|current| current.updated_at.clone()),
+            };
+            lines.push_str(&serde_json::to_string(&line).map_err(|e| e.to_string())?);
+            lines.push('\n');
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.own)
+            .map_err(|e| format!("Failed to open {}: {}", self.own.display(), e))?;
+        file.write_all(lines.as_bytes())
+            .map_err(|e| format!("Failed to write {}: {}", self.own.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn night(uuid: &str, start: &str, end: &str, updated_at: &str) -> Change {
+        Change {
+            uuid: uuid.to_string(),
+            profile: "default".to_string(),
+            start: start.to_string(),
+            end: Some(end.to_string()),
+            minutes_to_fall_asleep: Some(10),
+            awake_count: None,
+            time_awake: None,
+            time_in_bed_after_waking: None,
+            quality: Some(1),
+            melatonin: None,
+            benadryl: None,
+            edible: None,
+            exertion: None,
+            notes: None,
+            source: None,
+            created_at: Some("2026-01-11 07:00:00".to_string()),
+            updated_at: updated_at.to_string(),
+            origin: entry::MANUAL.to_string(),
+            deleted_at: None,
+        }
+    }
+
+    fn synced() -> Change {
+        night(
+            "a",
+            "2026-01-10 22:00:00",
+            "2026-01-11 06:00:00",
+            "2026-01-11 07:00:00",
+        )
+    }
+
+    fn edited(notes: &str, updated_at: &str) -> Change {
+        Change {
+            notes: Some(notes.to_string()),
+            updated_at: updated_at.to_string(),
+            ..synced()
+        }
+    }
+
+    fn base(changes: &[Change]) -> BTreeMap<String, Change> {
+        changes
+            .iter()
+            .map(|change| (change.uuid.clone(), change.clone()))
+            .collect()
+    }
+
+    fn no_conflicts(conflict: &Conflict) -> Result<Side, Box<dyn std::error::Error>> {
+        panic!("unexpected conflict: {}", conflict.describe())
+    }
+
+    fn newest(conflict: &Conflict) -> Result<Side, Box<dyn std::error::Error>> {
+        Ok(conflict.newest())
+    }
+
+    #[test]
+    fn edit_on_one_side_wins_whatever_the_clocks() {
+        // The remote edit is older by the clock, but only the remote changed since the last sync
+        let remote = edited("remote", "2026-01-11 06:59:00");
+        let plan = super::plan(
+            vec![synced()],
+            vec![remote.clone()],
+            &base(&[synced()]),
+            true,
+            no_conflicts,
+        )
+        .unwrap();
+        assert_eq!(plan.pull, vec![remote.clone()]);
+        assert!(plan.push.is_empty());
+        assert_eq!(plan.merged, vec![remote]);
+
+        let local = edited("local", "2026-01-11 08:00:00");
+        let plan = super::plan(
+            vec![local.clone()],
+            vec![synced()],
+            &base(&[synced()]),
+            true,
+            no_conflicts,
+        )
+        .unwrap();
+        assert!(plan.pull.is_empty());
+        assert_eq!(plan.push, vec![local]);
+    }
+
+    #[test]
+    fn edit_on_both_sides_is_a_conflict() {
+        let local = edited("local", "2026-01-11 08:00:00");
+        let remote = edited("remote", "2026-01-11 09:00:00");
+        let plan = super::plan(
+            vec![local],
+            vec![remote.clone()],
+            &base(&[synced()]),
+            true,
+            newest,
+        )
+        .unwrap();
+        assert_eq!(plan.conflicts.len(), 1);
+        assert_eq!(plan.conflicts[0].1, Side::Remote);
+        assert_eq!(plan.pull, vec![remote]);
+        assert!(plan.push.is_empty());
+    }
+
+    #[test]
+    fn deletes_propagate() {
+        let deleted = Change {
+            deleted_at: Some("2026-01-12 08:00:00".to_string()),
+            updated_at: "2026-01-12 08:00:00".to_string(),
+            ..synced()
+        };
+        let plan = super::plan(
+            vec![synced()],
+            vec![deleted.clone()],
+            &base(&[synced()]),
+            true,
+            no_conflicts,
+        )
+        .unwrap();
+        assert_eq!(plan.pull, vec![deleted]);
+        assert!(plan.push.is_empty());
+    }
+
+    #[test]
+    fn duplicate_night_takes_over_the_smaller_uuid() {
+        // Recorded separately on both sides, so only created_at and updated_at differ
+        let ours = Change {
+            uuid: "b".to_string(),
+            created_at: Some("2026-01-11 07:30:00".to_string()),
+            updated_at: "2026-01-11 07:30:00".to_string(),
+            ..synced()
+        };
+        let plan = super::plan(
+            vec![ours.clone()],
+            vec![synced()],
+            &BTreeMap::new(),
+            true,
+            no_conflicts,
+        )
+        .unwrap();
+        assert_eq!(plan.rename_local, vec![("b".to_string(), "a".to_string())]);
+        assert!(plan.rename_remote.is_empty());
+        assert!(plan.push.is_empty());
+        assert_eq!(plan.pull, vec![synced()]);
+        assert_eq!(plan.merged, vec![synced()]);
+
+        // The other way around the target renames, unless it can't (a directory)
+        let plan = super::plan(
+            vec![synced()],
+            vec![ours.clone()],
+            &BTreeMap::new(),
+            true,
+            no_conflicts,
+        )
+        .unwrap();
+        assert_eq!(plan.rename_remote, vec![("b".to_string(), "a".to_string())]);
+        assert!(plan.pull.is_empty());
+        // Only to give the renamed entry the kept one's creation time
+        assert_eq!(plan.push, vec![synced()]);
+
+        let plan = super::plan(
+            vec![synced()],
+            vec![ours],
+            &BTreeMap::new(),
+            false,
+            no_conflicts,
+        )
+        .unwrap();
+        assert!(plan.rename_remote.is_empty());
+        let pushed: Vec<(&str, bool)> = plan
+            .push
+            .iter()
+            .map(|change| (change.uuid.as_str(), change.live()))
+            .collect();
+        assert_eq!(pushed, [("a", true), ("b", false)]);
+    }
+
+    #[test]
+    fn overlapping_nights_keep_one() {
+        let ours = synced();
+        let theirs = night(
+            "c",
+            "2026-01-10 23:00:00",
+            "2026-01-11 07:00:00",
+            "2026-01-11 08:00:00",
+        );
+        let plan = super::plan(
+            vec![ours],
+            vec![theirs.clone()],
+            &BTreeMap::new(),
+            true,
+            newest,
+        )
+        .unwrap();
+        assert_eq!(plan.conflicts.len(), 1);
+        assert_eq!(plan.conflicts[0].1, Side::Remote);
+        let live: Vec<&str> = plan
+            .merged
+            .iter()
+            .filter(|change| change.live())
+            .map(|change| change.uuid.as_str())
+            .collect();
+        assert_eq!(live, ["c"]);
+        // This database gets the other night and deletes its own; the target learns of the delete
+        assert!(plan.pull.contains(&theirs));
+        assert!(
+            plan.pull
+                .iter()
+                .any(|change| change.uuid == "a" && !change.live())
+        );
+        assert!(
+            plan.push
+                .iter()
+                .all(|change| change.uuid == "a" && !change.live())
+        );
+    }
+
+    #[test]
+    fn repeated_sync_changes_nothing() {
+        let changes = vec![edited("both", "2026-01-11 08:00:00")];
+        let plan = super::plan(
+            changes.clone(),
+            changes.clone(),
+            &base(&changes),
+            true,
+            no_conflicts,
+        )
+        .unwrap();
+        assert!(plan.pull.is_empty() && plan.push.is_empty() && plan.conflicts.is_empty());
+    }
+
+    #[test]
+    fn rewrite_in_the_same_second_is_current() {
+        // Both files name the same time, and whichever is read last must not win by order
+        let dir = tempfile::tempdir().unwrap();
+        let first = synced();
+        Directory::open(dir.path(), "z")
+            .unwrap()
+            .append(&[first])
+            .unwrap();
+        let rewrite = edited("rewritten", "2026-01-11 07:00:00");
+        Directory::open(dir.path(), "a")
+            .unwrap()
+            .append(std::slice::from_ref(&rewrite))
+            .unwrap();
+
+        let changes = Directory::open(dir.path(), "a").unwrap().changes();
+        assert_eq!(changes, vec![rewrite]);
+    }
+}
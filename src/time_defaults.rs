@@ -0,0 +1,163 @@
+use chrono::{Datelike, Days, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+
+use crate::config::{Config, TimeDefaults};
+use crate::storage::Storage;
+
+const MINUTES_PER_DAY: i64 = 24 * 60;
+
+/// Default start and end times offered by `record`, and where they came from.
+pub struct DefaultTimes {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub strategy: String,
+}
+
+/// Resolves the default times for the night starting on `date` according to `config.time_defaults`.
+/// Falls back to the static defaults when there is no history to learn from.
+pub async fn resolve(
+    storage: &impl Storage,
+    profile: i64,
+    config: &Config,
+    date: NaiveDate,
+) -> Result<DefaultTimes, Box<dyn std::error::Error>> {
+    let static_start =
+        crate::parse_hhmm(&crate::normalize_time_to_hhmm(&config.start_time_default))?;
+    let static_end = crate::parse_hhmm(&crate::normalize_time_to_hhmm(&config.end_time_default))?;
+
+    let static_defaults = |strategy: &str| DefaultTimes {
+        start: static_start,
+        end: static_end,
+        strategy: strategy.to_string(),
+    };
+
+    let nights = config.time_defaults_nights as usize;
+    let description = match config.time_defaults {
+        TimeDefaults::Static => return Ok(static_defaults("static config defaults")),
+        TimeDefaults::RollingMedian => format!("median of the last {} nights", nights),
+        TimeDefaults::WeekdayMedian => {
+            format!("median of the last {} {}s", nights, date.format("%A"))
+        }
+    };
+
+    // Newest first, so the nights closest to `date` are taken
+    let mut starts = Vec::new();
+    let mut ends = Vec::new();
+    for entry in storage.recent(profile, None).await? {
+        let (Ok(start), Ok(Some(end))) = (entry.start_datetime(), entry.end_datetime()) else {
+            continue;
+        };
+        let night = night_of(start, end.time());
+        if night >= date
+            || config.time_defaults == TimeDefaults::WeekdayMedian
+                && night.weekday() != date.weekday()
+        {
+            continue;
+        }
+        starts.push(start.time());
+        ends.push(end.time());
+        if starts.len() == nights {
+            break;
+        }
+    }
+
+    match (
+        median_time(&starts, static_start),
+        median_time(&ends, static_end),
+    ) {
+        (Some(start), Some(end)) => Ok(DefaultTimes {
+            start,
+            end,
+            strategy: format!("{} ({} found)", description, starts.len()),
+        }),
+        _ => Ok(static_defaults("static config defaults, no history yet")),
+    }
+}
+
+/// The night a sleep starting at `start` and ending at the time of day `end` belongs to. A start
+/// before the end's time of day is after midnight, so the night began the day before.
+pub fn night_of(start: NaiveDateTime, end: NaiveTime) -> NaiveDate {
+    if start.time() < end {
+        start.date() - Days::new(1)
+    } else {
+        start.date()
+    }
+}
+
+/// When the night of `date` starts if it starts at `start` and ends at `end`: on `date` itself,
+/// or on the next day for a start after midnight. The reverse of `night_of`.
+pub fn start_of_night(date: NaiveDate, start: NaiveTime, end: NaiveTime) -> NaiveDateTime {
+    if start < end {
+        (date + Days::new(1)).and_time(start)
+    } else {
+        date.and_time(start)
+    }
+}
+
+/// Median time of day, computed on a 24h window centred on `around` so that
+/// nights either side of midnight (23:50, 00:10) are treated as close together.
+fn median_time(times: &[NaiveTime], around: NaiveTime) -> Option<NaiveTime> {
+    if times.is_empty() {
+        return None;
+    }
+
+    let minutes = |time: &NaiveTime| (time.hour() * 60 + time.minute()) as i64;
+    let window_start = minutes(&around) - MINUTES_PER_DAY / 2;
+
+    let mut offsets: Vec<i64> = times
+        .iter()
+        .map(|time| (minutes(time) - window_start).rem_euclid(MINUTES_PER_DAY))
+        .collect();
+    offsets.sort_unstable();
+
+    let mid = offsets.len() / 2;
+    let median = if offsets.len().is_multiple_of(2) {
+        (offsets[mid - 1] + offsets[mid]) / 2
+    } else {
+        offsets[mid]
+    };
+
+    let minute_of_day = (window_start + median).rem_euclid(MINUTES_PER_DAY) as u32;
+    NaiveTime::from_hms_opt(minute_of_day / 60, minute_of_day % 60, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(value: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(value, "%H:%M").unwrap()
+    }
+
+    fn times(values: &[&str]) -> Vec<NaiveTime> {
+        values.iter().map(|value| time(value)).collect()
+    }
+
+    #[test]
+    fn median_across_midnight() {
+        let starts = times(&["23:50", "00:10", "00:20"]);
+        assert_eq!(median_time(&starts, time("22:30")), Some(time("00:10")));
+        let starts = times(&["00:20", "23:40", "23:50"]);
+        assert_eq!(median_time(&starts, time("22:30")), Some(time("23:50")));
+    }
+
+    #[test]
+    fn median_of_an_even_count_is_halfway_between() {
+        let starts = times(&["22:40", "23:00", "23:20", "23:50"]);
+        assert_eq!(median_time(&starts, time("22:30")), Some(time("23:10")));
+        let starts = times(&["23:40", "00:20"]);
+        assert_eq!(median_time(&starts, time("22:30")), Some(time("00:00")));
+        assert_eq!(median_time(&[], time("22:30")), None);
+    }
+
+    #[test]
+    fn starts_after_midnight_belong_to_the_night_before() {
+        let date = NaiveDate::from_ymd_opt(2026, 10, 10).unwrap();
+        let start = start_of_night(date, time("00:10"), time("07:00"));
+        assert_eq!(start.to_string(), "2026-10-11 00:10:00");
+        assert_eq!(night_of(start, time("07:00")), date);
+
+        let start = start_of_night(date, time("23:10"), time("07:00"));
+        assert_eq!(start.to_string(), "2026-10-10 23:10:00");
+        assert_eq!(night_of(start, time("07:00")), date);
+    }
+}
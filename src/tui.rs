@@ -0,0 +1,627 @@
+use ratatui::DefaultTerminal;
+use ratatui::Frame;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{
+    Bar, BarChart, BarGroup, Block, Cell, Paragraph, Row, Table, TableState, Wrap,
+};
+use strum::IntoEnumIterator;
+
+use crate::config::Config;
+use crate::entry::{DATETIME_FORMAT, Entry, Severity};
+use crate::storage::{self, Storage};
+use crate::store::StoreError;
+use crate::{Exertion, Quality};
+
+/// Fields shown in the detail pane, in the order they can be edited.
+const FIELDS: [&str; 12] = [
+    "Start",
+    "End",
+    "Minutes to fall asleep",
+    "Times woken up",
+    "Time awake",
+    "Time in bed after waking",
+    "Quality",
+    "Melatonin (mg)",
+    "Benadryl (mg)",
+    "Edible (mg)",
+    "Exertion",
+    "Notes",
+];
+
+fn field_value(entry: &Entry, field: usize) -> String {
+    fn or_empty<T: ToString>(value: Option<T>) -> String {
+        value.map(|value| value.to_string()).unwrap_or_default()
+    }
+    match field {
+        0 => entry.start.clone(),
+        1 => or_empty(entry.end.as_ref()),
+        2 => or_empty(entry.minutes_to_fall_asleep),
+        3 => or_empty(entry.awake_count),
+        4 => or_empty(entry.time_awake),
+        5 => or_empty(entry.time_in_bed_after_waking),
+        6 => entry.quality_name(),
+        7 => or_empty(entry.melatonin),
+        8 => or_empty(entry.benadryl),
+        9 => or_empty(entry.edible),
+        10 => exertion_name(entry),
+        _ => or_empty(entry.notes.as_ref()),
+    }
+}
+
+fn exertion_name(entry: &Entry) -> String {
+    entry
+        .exertion
+        .and_then(Exertion::from_db_value)
+        .map(|exertion| exertion.to_string())
+        .unwrap_or_else(|| "Unknown".to_string())
+}
+
+/// Parses `input` into `field` of `entry`. An empty input clears optional fields.
+fn set_field(entry: &mut Entry, field: usize, input: &str) -> Result<(), String> {
+    let input = input.trim();
+    fn number<T: std::str::FromStr>(input: &str) -> Result<Option<T>, String> {
+        if input.is_empty() {
+            return Ok(None);
+        }
+        input
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("Not a number: {}", input))
+    }
+    // Seconds are optional when typing a time
+    let datetime = |input: &str| -> Result<String, String> {
+        chrono::NaiveDateTime::parse_from_str(input, DATETIME_FORMAT)
+            .or_else(|_| chrono::NaiveDateTime::parse_from_str(input, "%Y-%m-%d %H:%M"))
+            .map(|datetime| datetime.format(DATETIME_FORMAT).to_string())
+            .map_err(|_| format!("Expected YYYY-MM-DD HH:MM, got: {}", input))
+    };
+
+    match field {
+        0 => entry.start = datetime(input)?,
+        1 => entry.end = (!input.is_empty()).then(|| datetime(input)).transpose()?,
+        2 => entry.minutes_to_fall_asleep = number(input)?,
+        3 => entry.awake_count = number(input)?,
+        4 => entry.time_awake = number(input)?,
+        5 => entry.time_in_bed_after_waking = number(input)?,
+        6 => {
+            entry.quality = match Quality::iter()
+                .find(|quality| quality.to_string().eq_ignore_ascii_case(input))
+            {
+                Some(quality) => Some(quality.db_value()),
+                None => number(input)?,
+            }
+        }
+        7 => entry.melatonin = number(input)?,
+        8 => entry.benadryl = number(input)?,
+        9 => entry.edible = number(input)?,
+        10 => {
+            entry.exertion = match Exertion::iter()
+                .find(|exertion| exertion.to_string().eq_ignore_ascii_case(input))
+            {
+                Some(exertion) => Some(exertion.db_value()),
+                None => number(input)?,
+            }
+        }
+        _ => entry.notes = (!input.is_empty()).then(|| input.to_string()),
+    }
+    Ok(())
+}
+
+fn duration(minutes: Option<i64>) -> String {
+    match minutes {
+        Some(minutes) => format!("{}h {:02}m", minutes / 60, minutes % 60),
+        None => "N/A".to_string(),
+    }
+}
+
+enum Mode {
+    Browse,
+    /// Typing a text filter
+    Filter,
+    /// Picking a field of the selected entry in the detail pane
+    Edit {
+        field: usize,
+    },
+    /// Typing a new value for a field
+    Input {
+        field: usize,
+        buffer: String,
+    },
+    ConfirmDelete,
+}
+
+struct App {
+    /// Profile whose entries are shown
+    profile: i64,
+    /// Every entry, newest first
+    entries: Vec<Entry>,
+    /// Indices into `entries` that pass the filters
+    visible: Vec<usize>,
+    table: TableState,
+    mode: Mode,
+    filter: String,
+    /// Only show nights with this quality
+    quality_filter: Option<i8>,
+    show_chart: bool,
+    message: Option<String>,
+    quit: bool,
+}
+
+impl App {
+    async fn reload(&mut self, storage: &impl Storage) -> Result<(), StoreError> {
+        let selected_id = self.selected().map(|entry| entry.id);
+        self.entries = storage.recent(self.profile, None).await?;
+        self.apply_filters();
+        if let Some(position) = selected_id.and_then(|id| {
+            self.visible
+                .iter()
+                .position(|index| self.entries[*index].id == id)
+        }) {
+            self.table.select(Some(position));
+        }
+        Ok(())
+    }
+
+    fn apply_filters(&mut self) {
+        let filter = self.filter.to_lowercase();
+        self.visible = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| {
+                self.quality_filter.is_none() || entry.quality == self.quality_filter
+            })
+            .filter(|(_, entry)| {
+                filter.is_empty()
+                    || entry.start.contains(&filter)
+                    || entry.quality_name().to_lowercase().contains(&filter)
+                    || entry
+                        .notes
+                        .as_ref()
+                        .is_some_and(|notes| notes.to_lowercase().contains(&filter))
+            })
+            .map(|(index, _)| index)
+            .collect();
+
+        let selected = self.table.selected().unwrap_or(0);
+        self.table.select(if self.visible.is_empty() {
+            None
+        } else {
+            Some(selected.min(self.visible.len() - 1))
+        });
+    }
+
+    fn selected(&self) -> Option<&Entry> {
+        self.table
+            .selected()
+            .and_then(|position| self.visible.get(position))
+            .map(|index| &self.entries[*index])
+    }
+
+    fn move_selection(&mut self, by: isize) {
+        if self.visible.is_empty() {
+            return;
+        }
+        let current = self.table.selected().unwrap_or(0) as isize;
+        let last = self.visible.len() as isize - 1;
+        self.table
+            .select(Some((current + by).clamp(0, last) as usize));
+    }
+
+    async fn handle_key(
+        &mut self,
+        key: KeyEvent,
+        storage: &impl Storage,
+        config: &Config,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // Messages stay up until the next key
+        if matches!(self.mode, Mode::Browse | Mode::Edit { .. }) {
+            self.message = None;
+        }
+        match &mut self.mode {
+            Mode::Browse => {
+                match key.code {
+                    KeyCode::Char('q') => self.quit = true,
+                    KeyCode::Esc if self.filter.is_empty() && self.quality_filter.is_none() => {
+                        self.quit = true
+                    }
+                    KeyCode::Esc => {
+                        self.filter.clear();
+                        self.quality_filter = None;
+                        self.apply_filters();
+                    }
+                    KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+                    KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+                    KeyCode::PageDown => self.move_selection(20),
+                    KeyCode::PageUp => self.move_selection(-20),
+                    KeyCode::Home | KeyCode::Char('g') => self.move_selection(isize::MIN / 2),
+                    KeyCode::End | KeyCode::Char('G') => self.move_selection(isize::MAX / 2),
+                    KeyCode::Char('/') => self.mode = Mode::Filter,
+                    KeyCode::Char('f') => {
+                        // All, then each quality from worst to best
+                        let qualities: Vec<i8> = Quality::iter().map(|q| q.db_value()).collect();
+                        self.quality_filter = match self.quality_filter {
+                            None => qualities.first().copied(),
+                            Some(current) => qualities
+                                .iter()
+                                .skip_while(|quality| **quality != current)
+                                .nth(1)
+                                .copied(),
+                        };
+                        self.apply_filters();
+                    }
+                    KeyCode::Char('c') => self.show_chart = !self.show_chart,
+                    KeyCode::Char('r') => self.reload(storage).await?,
+                    KeyCode::Char('e') | KeyCode::Enter if self.selected().is_some() => {
+                        self.mode = Mode::Edit { field: 0 }
+                    }
+                    KeyCode::Char('d') | KeyCode::Delete if self.selected().is_some() => {
+                        self.mode = Mode::ConfirmDelete
+                    }
+                    _ => {}
+                }
+            }
+            Mode::Filter => match key.code {
+                KeyCode::Enter => self.mode = Mode::Browse,
+                KeyCode::Esc => {
+                    self.filter.clear();
+                    self.apply_filters();
+                    self.mode = Mode::Browse;
+                }
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                    self.apply_filters();
+                }
+                KeyCode::Char(c) => {
+                    self.filter.push(c);
+                    self.apply_filters();
+                }
+                _ => {}
+            },
+            Mode::Edit { field } => match key.code {
+                KeyCode::Esc | KeyCode::Char('q') => self.mode = Mode::Browse,
+                KeyCode::Down | KeyCode::Char('j') => *field = (*field + 1).min(FIELDS.len() - 1),
+                KeyCode::Up | KeyCode::Char('k') => *field = field.saturating_sub(1),
+                KeyCode::Enter => {
+                    let field = *field;
+                    let buffer = self
+                        .selected()
+                        .map(|entry| field_value(entry, field))
+                        .unwrap_or_default();
+                    self.mode = Mode::Input { field, buffer };
+                }
+                _ => {}
+            },
+            Mode::Input { field, buffer } => match key.code {
+                KeyCode::Esc => self.mode = Mode::Edit { field: *field },
+                KeyCode::Backspace => {
+                    buffer.pop();
+                }
+                KeyCode::Char(c) => buffer.push(c),
+                KeyCode::Enter => {
+                    let (field, input) = (*field, buffer.clone());
+                    self.mode = Mode::Edit { field };
+                    self.save_field(storage, field, &input).await?;
+                }
+                _ => {}
+            },
+            Mode::ConfirmDelete => {
+                self.mode = Mode::Browse;
+                if let (KeyCode::Char('y'), Some(entry)) = (key.code, self.selected()) {
+                    let id = entry.id;
+                    // Same as `slog delete`: on SQLite a backup, then a soft delete `slog undo`
+                    // can revert
+                    storage::auto_backup(storage, config, "delete").await?;
+                    storage.delete(&[id]).await?;
+                    self.reload(storage).await?;
+                    self.message = Some(format!(
+                        "✓ Deleted entry #{}{}",
+                        id,
+                        if storage.sqlite().is_some() {
+                            " (undo with `slog undo`)"
+                        } else {
+                            ""
+                        }
+                    ));
+                } else {
+                    self.message = Some("Cancelled".to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies an edit with the same checks as recording: invalid values and overlaps are refused.
+    async fn save_field(
+        &mut self,
+        storage: &impl Storage,
+        field: usize,
+        input: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(mut entry) = self.selected().cloned() else {
+            return Ok(());
+        };
+        if let Err(e) = set_field(&mut entry, field, input) {
+            self.message = Some(format!("❌ {}", e));
+            return Ok(());
+        }
+        if let Some(problem) = entry
+            .problems()
+            .into_iter()
+            .find(|problem| problem.severity == Severity::Invalid)
+        {
+            self.message = Some(problem.to_string());
+            return Ok(());
+        }
+
+        match storage.update(&entry).await {
+            Ok(()) => {
+                self.message = Some(format!("✓ Saved {} of #{}", FIELDS[field], entry.id));
+                self.reload(storage).await?;
+            }
+            Err(e @ (StoreError::Overlap(_) | StoreError::Invalid(_))) => {
+                self.message = Some(format!("❌ Not saved: {}", e))
+            }
+            Err(e) => return Err(e.into()),
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let chart_height = if self.show_chart { 12 } else { 0 };
+        let [header, main, chart, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(6),
+            Constraint::Length(chart_height),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [table_area, detail_area] =
+            Layout::horizontal([Constraint::Percentage(65), Constraint::Percentage(35)])
+                .areas(main);
+
+        let mut title = format!(
+            " 💤 slog – {} of {} nights",
+            self.visible.len(),
+            self.entries.len()
+        );
+        if !self.filter.is_empty() {
+            title.push_str(&format!("  filter: \"{}\"", self.filter));
+        }
+        if let Some(quality) = self.quality_filter.and_then(Quality::from_db_value) {
+            title.push_str(&format!("  quality: {}", quality));
+        }
+        frame.render_widget(Line::from(title).bold(), header);
+
+        self.draw_table(frame, table_area);
+        self.draw_detail(frame, detail_area);
+        if self.show_chart {
+            self.draw_chart(frame, chart);
+        }
+
+        let status = match &self.mode {
+            Mode::Filter => format!("/{}▏  (Enter: keep, Esc: clear)", self.filter),
+            Mode::Input { field, buffer } => format!(
+                "{}: {}▏  (Enter: save, Esc: cancel)",
+                FIELDS[*field], buffer
+            ),
+            Mode::ConfirmDelete => "Delete this entry? (y/N)".to_string(),
+            Mode::Edit { .. } => self
+                .message
+                .clone()
+                .unwrap_or_else(|| "↑↓ field  Enter edit  Esc done".to_string()),
+            Mode::Browse => self.message.clone().unwrap_or_else(|| {
+                "↑↓/jk move  / filter  f quality  e edit  d delete  c chart  r reload  q quit"
+                    .to_string()
+            }),
+        };
+        frame.render_widget(Line::from(status).reversed(), footer);
+    }
+
+    fn draw_table(&mut self, frame: &mut Frame, area: Rect) {
+        let header = Row::new([
+            "Date", "Start", "End", "In bed", "Asleep", "Eff", "Quality", "Fall", "Woke", "Awake",
+            "After", "Mel", "Ben", "Edi", "Exertion", "Notes",
+        ])
+        .bold()
+        .underlined();
+
+        let rows = self.visible.iter().map(|index| {
+            let entry = &self.entries[*index];
+            let time = |value: &str| value.get(11..16).unwrap_or("").to_string();
+            let or_dash = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+            Row::new([
+                Cell::from(entry.start.get(..10).unwrap_or(&entry.start).to_string()),
+                Cell::from(time(&entry.start)),
+                Cell::from(entry.end.as_deref().map(time).unwrap_or_default()),
+                Cell::from(duration(entry.time_in_bed_minutes())),
+                Cell::from(duration(entry.total_sleep_minutes())),
+                Cell::from(or_dash(entry.efficiency().map(|e| format!("{:.0}%", e)))),
+                Cell::from(entry.quality_name()),
+                Cell::from(or_dash(entry.minutes_to_fall_asleep.map(|v| v.to_string()))),
+                Cell::from(or_dash(entry.awake_count.map(|v| v.to_string()))),
+                Cell::from(or_dash(entry.time_awake.map(|v| v.to_string()))),
+                Cell::from(or_dash(
+                    entry.time_in_bed_after_waking.map(|v| v.to_string()),
+                )),
+                Cell::from(or_dash(entry.melatonin.map(|v| v.to_string()))),
+                Cell::from(or_dash(entry.benadryl.map(|v| v.to_string()))),
+                Cell::from(or_dash(entry.edible.map(|v| v.to_string()))),
+                Cell::from(exertion_name(entry)),
+                Cell::from(entry.notes.clone().unwrap_or_default()),
+            ])
+        });
+
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(10),
+                Constraint::Length(5),
+                Constraint::Length(5),
+                Constraint::Length(7),
+                Constraint::Length(7),
+                Constraint::Length(4),
+                Constraint::Length(11),
+                Constraint::Length(4),
+                Constraint::Length(4),
+                Constraint::Length(5),
+                Constraint::Length(5),
+                Constraint::Length(4),
+                Constraint::Length(4),
+                Constraint::Length(4),
+                Constraint::Length(9),
+                Constraint::Min(10),
+            ],
+        )
+        .header(header)
+        .block(Block::bordered().title(" Nights "))
+        .row_highlight_style(
+            Style::new()
+                .bg(Color::DarkGray)
+                .add_modifier(Modifier::BOLD),
+        );
+
+        frame.render_stateful_widget(table, area, &mut self.table);
+    }
+
+    fn draw_detail(&self, frame: &mut Frame, area: Rect) {
+        let block = Block::bordered().title(" Details ");
+        let Some(entry) = self.selected() else {
+            frame.render_widget(Paragraph::new("No entries").block(block), area);
+            return;
+        };
+
+        let editing = match &self.mode {
+            Mode::Edit { field } | Mode::Input { field, .. } => Some(*field),
+            _ => None,
+        };
+        let mut lines = vec![
+            Line::from(format!("Entry #{}", entry.id).bold()),
+            Line::default(),
+        ];
+        for (field, label) in FIELDS.iter().enumerate() {
+            let style = if editing == Some(field) {
+                Style::new().reversed()
+            } else {
+                Style::new()
+            };
+            lines.push(Line::from(vec![
+                Span::styled(format!("{:<25}", label), style),
+                Span::raw(" "),
+                Span::raw(field_value(entry, field)),
+            ]));
+        }
+        lines.push(Line::default());
+        lines.push(Line::from(format!(
+            "Time in bed: {}   Asleep: {}",
+            duration(entry.time_in_bed_minutes()),
+            duration(entry.total_sleep_minutes())
+        )));
+        lines.push(Line::from(format!(
+            "Efficiency: {}",
+            entry
+                .efficiency()
+                .map(|efficiency| format!("{:.1}%", efficiency))
+                .unwrap_or_else(|| "N/A".to_string())
+        )));
+        lines.push(Line::from(format!(
+            "Source: {}",
+            entry.source.as_deref().unwrap_or("manual")
+        )));
+        for problem in entry.problems() {
+            lines.push(Line::from(problem.to_string()).fg(Color::Yellow));
+        }
+
+        frame.render_widget(
+            Paragraph::new(lines)
+                .block(block)
+                .wrap(Wrap { trim: false }),
+            area,
+        );
+    }
+
+    /// Hours asleep for the nights up to the selected one, oldest on the left.
+    fn draw_chart(&self, frame: &mut Frame, area: Rect) {
+        const BAR_WIDTH: u16 = 5;
+        let capacity = (area.width.saturating_sub(2) / (BAR_WIDTH + 1)) as usize;
+        let selected = self.table.selected().unwrap_or(0);
+
+        // `visible` is newest first, so the nights before the selected one come after it
+        let window: Vec<(usize, &Entry)> = self
+            .visible
+            .iter()
+            .enumerate()
+            .skip(selected)
+            .take(capacity)
+            .map(|(position, index)| (position, &self.entries[*index]))
+            .collect();
+
+        let bars: Vec<Bar> = window
+            .iter()
+            .rev()
+            .map(|(position, entry)| {
+                let minutes = entry.total_sleep_minutes().unwrap_or(0).max(0) as u64;
+                let style = if *position == selected {
+                    Style::new().fg(Color::Yellow)
+                } else {
+                    Style::new().fg(Color::Blue)
+                };
+                Bar::default()
+                    .value(minutes)
+                    .text_value(format!("{:.1}", minutes as f64 / 60.0))
+                    .label(Line::from(entry.start.get(5..10).unwrap_or("").to_string()))
+                    .style(style)
+            })
+            .collect();
+
+        let chart = BarChart::default()
+            .block(Block::bordered().title(" Hours asleep "))
+            .data(BarGroup::default().bars(&bars))
+            .bar_width(BAR_WIDTH)
+            .bar_gap(1);
+        frame.render_widget(chart, area);
+    }
+}
+
+async fn run(
+    terminal: &mut DefaultTerminal,
+    app: &mut App,
+    storage: &impl Storage,
+    config: &Config,
+) -> Result<(), Box<dyn std::error::Error>> {
+    while !app.quit {
+        terminal.draw(|frame| app.draw(frame))?;
+        if let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+        {
+            app.handle_key(key, storage, config).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Runs the full-screen browser until the user quits.
+pub async fn browse(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let storage = storage::open(config).await?;
+    let mut app = App {
+        profile: storage.active_profile(config).await?,
+        entries: Vec::new(),
+        visible: Vec::new(),
+        table: TableState::default().with_selected(0),
+        mode: Mode::Browse,
+        filter: String::new(),
+        quality_filter: None,
+        show_chart: true,
+        message: None,
+        quit: false,
+    };
+    app.reload(&storage).await?;
+
+    let mut terminal = ratatui::try_init()?;
+    let result = run(&mut terminal, &mut app, &storage, config).await;
+    ratatui::restore();
+    result
+}